@@ -0,0 +1,372 @@
+/* monitor-tool 编码器的 C 接口，用 cbindgen 从 src/lib.rs 生成，不要手动修改 */
+
+#ifndef MONITOR_TOOL_H
+#define MONITOR_TOOL_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+// 成功
+#define MT_OK 0
+
+// 参数为空指针
+#define MT_ERR_NULL -1
+
+// 字符串不是 UTF-8
+#define MT_ERR_UTF8 -2
+
+// 顶点形状无效
+#define MT_ERR_SHAPE -3
+
+// 缓冲区太小
+#define MT_ERR_BUFFER -4
+
+// 文字锚点无效
+#define MT_ERR_ANCHOR -5
+
+// 点形状无效
+#define MT_ERR_MARKER -6
+
+// 分片数超过 65535
+#define MT_ERR_FRAGMENTS -7
+
+// 参数超出范围
+#define MT_ERR_RANGE -8
+
+// 形状：箭头
+#define MT_SHAPE_ARROW 0
+
+// 形状：圆
+#define MT_SHAPE_CIRCLE 1
+
+// 形状：文字
+#define MT_SHAPE_TEXT 2
+
+// 形状：轴对齐矩形
+#define MT_SHAPE_RECT 3
+
+// 形状：旋转矩形
+#define MT_SHAPE_ORIENTED_BOX 4
+
+// 形状：多边形
+#define MT_SHAPE_POLYGON 5
+
+// 形状：椭圆
+#define MT_SHAPE_ELLIPSE 6
+
+// 形状：向量
+#define MT_SHAPE_VECTOR 7
+
+// 点形状：方块
+#define MT_MARKER_SQUARE 0
+
+// 点形状：圆点
+#define MT_MARKER_DOT 1
+
+// 点形状：叉
+#define MT_MARKER_CROSS 2
+
+// 文字锚点：顶点位于文字框的左上角
+#define MT_ANCHOR_TOP_LEFT 0
+
+// 文字锚点：顶点位于文字框的上边中点
+#define MT_ANCHOR_TOP 1
+
+// 文字锚点：顶点位于文字框的右上角
+#define MT_ANCHOR_TOP_RIGHT 2
+
+// 文字锚点：顶点位于文字框的左边中点
+#define MT_ANCHOR_LEFT 3
+
+// 文字锚点：顶点位于文字框的中心
+#define MT_ANCHOR_CENTER 4
+
+// 文字锚点：顶点位于文字框的右边中点
+#define MT_ANCHOR_RIGHT 5
+
+// 文字锚点：顶点位于文字框的左下角
+#define MT_ANCHOR_BOTTOM_LEFT 6
+
+// 文字锚点：顶点位于文字框的下边中点
+#define MT_ANCHOR_BOTTOM 7
+
+// 文字锚点：顶点位于文字框的右下角
+#define MT_ANCHOR_BOTTOM_RIGHT 8
+
+// 编码器，以及已编码、等待取走的包
+typedef struct MtEncoder MtEncoder;
+
+// 话题句柄，编码之后仍可继续写入下一帧
+typedef struct MtTopic MtTopic;
+
+// 图形顶点，共 16 字节，与 `Vertex` 布局相同，形状用整数表示
+typedef struct MtVertex {
+  // 位置 x
+  float x;
+  // 位置 y
+  float y;
+  // 等级
+  uint8_t level;
+  // 与上一个顶点连线的透明度，0 表示不连线
+  uint8_t alpha;
+  // 必须为 0
+  uint8_t reserved;
+  // `MT_SHAPE_*`
+  uint8_t shape;
+  // 箭头方向、圆半径或矩形、多边形的转角
+  float extra;
+} MtVertex;
+
+// 栅格几何，与 `Grid` 布局相同，第 0 行第 0 列单元的左下角为原点，行号沿 y 轴正方向增长
+typedef struct MtGrid {
+  // 原点 x
+  float origin_x;
+  // 原点 y
+  float origin_y;
+  // 单元边长
+  float resolution;
+  // 列数
+  uint32_t width;
+  // 行数
+  uint32_t height;
+} MtGrid;
+
+// 栅格色标，值在相邻色标之间时线性插值
+typedef struct MtColorStop {
+  float value;
+  uint8_t r;
+  uint8_t g;
+  uint8_t b;
+  uint8_t a;
+} MtColorStop;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// 创建编码器
+struct MtEncoder *mt_encoder_new(void);
+
+// 释放编码器，之后不能再使用它的话题句柄
+void mt_encoder_free(struct MtEncoder *encoder);
+
+// 更新同步组，`life_time` 单位为秒，不大于 0 表示不修改
+int mt_encoder_sync_set(struct MtEncoder *encoder,
+                        const char *set,
+                        const char *const *topics,
+                        size_t n,
+                        double life_time);
+
+// 更新图层，`visible` 小于 0 表示不修改，0 表示隐藏，大于 0 表示显示
+int mt_encoder_layer(struct MtEncoder *encoder,
+                     const char *layer,
+                     const char *const *topics,
+                     size_t n,
+                     int visible);
+
+// 删除话题，之前写入这个话题的内容一并丢弃
+int mt_encoder_remove_topic(struct MtEncoder *encoder, const char *topic);
+
+// 删除图层及其中的所有话题
+int mt_encoder_remove_layer(struct MtEncoder *encoder, const char *layer);
+
+// 解散同步组，其中的话题保留
+int mt_encoder_dissolve_sync_set(struct MtEncoder *encoder, const char *set);
+
+// 设置视野中心和比例，非有限值表示不修改，比例为 0 表示开启自动视野
+int mt_encoder_set_view(struct MtEncoder *encoder, float x, float y, float scale);
+
+// 使视野适应话题的范围，并关闭自动视野
+int mt_encoder_fit_topic(struct MtEncoder *encoder, const char *topic);
+
+// 开启（非 0）或关闭（0）自动视野
+int mt_encoder_set_auto_view(struct MtEncoder *encoder, int auto_view);
+
+// 设置源时间，即数据产生的时刻，`sec` 和 `nsec` 从 UNIX 纪元计，同 `struct timespec`
+//
+// `nsec` 不小于 10 亿或时间超出系统表示范围时返回 `MT_ERR_RANGE`。
+int mt_encoder_set_time(struct MtEncoder *encoder,
+                        uint64_t sec,
+                        uint32_t nsec);
+
+// 把当前内容编码为一帧，每个包不超过 `max_datagram` 字节，0 表示不限
+//
+// 编码器随后清空；返回等待取走的包数，分片过多时返回 `MT_ERR_FRAGMENTS`，这一帧被丢弃。
+ptrdiff_t mt_encoder_finish(struct MtEncoder *encoder,
+                            size_t max_datagram);
+
+// 下一个待取走的包的长度，没有时返回 0
+size_t mt_encoder_next_len(const struct MtEncoder *encoder);
+
+// 取走下一个包，写入 `buf`
+//
+// 返回包长度，没有包时返回 0；缓冲区不够时返回 `MT_ERR_BUFFER`，包保留。
+ptrdiff_t mt_encoder_next(struct MtEncoder *encoder, uint8_t *buf, size_t capacity);
+
+// 编码到 `buf`
+//
+// 没有待取走的包时先把当前内容编码为一个包，然后同 [`mt_encoder_next`]。
+ptrdiff_t mt_encoder_encode(struct MtEncoder *encoder, uint8_t *buf, size_t capacity);
+
+// 打开话题，失败时返回空指针
+struct MtTopic *mt_topic_open(struct MtEncoder *encoder, const char *name);
+
+// 关闭话题句柄，不影响已写入的内容
+void mt_topic_close(struct MtTopic *topic);
+
+// 清空话题缓存
+int mt_topic_clear(struct MtTopic *topic);
+
+// 设置话题容量
+int mt_topic_set_capacity(struct MtTopic *topic, uint32_t capacity);
+
+// 设置关注数量
+int mt_topic_set_focus(struct MtTopic *topic, uint32_t focus);
+
+// 设置保留时长，单位为秒，只保留最近这么长时间内的顶点和实体，不大于 0 表示不限
+int mt_topic_set_retention(struct MtTopic *topic,
+                           double retention);
+
+// 设置级别颜色
+int mt_topic_set_color(struct MtTopic *topic,
+                       uint8_t level,
+                       uint8_t r,
+                       uint8_t g,
+                       uint8_t b,
+                       uint8_t a);
+
+// 设置级别样式，长度以像素计，`arrow_world` 非 0 时箭头长度以世界单位计
+//
+// 虚线的实段或空段不为正时画实线。
+int mt_topic_set_style(struct MtTopic *topic,
+                       uint8_t level,
+                       uint8_t marker,
+                       float marker_size,
+                       float line_width,
+                       float dash_on,
+                       float dash_off,
+                       float arrow_length,
+                       int arrow_world);
+
+// 设置级别的向量样式，画出的长度为模长的 `scale` 倍
+//
+// `magnitude_max` 大于 `magnitude_min` 时按模长在该范围内的位置着色，否则使用级别颜色。
+// 不改变级别样式的其他字段。
+int mt_topic_set_vector_style(struct MtTopic *topic,
+                              uint8_t level,
+                              float scale,
+                              float magnitude_min,
+                              float magnitude_max);
+
+// 保存 `n` 个顶点，任一顶点无效时一个也不保存
+int mt_topic_push(struct MtTopic *topic, const struct MtVertex *vertex, size_t n);
+
+// 保存一个文字标签，顶点的形状字段被忽略
+//
+// `size` 在 `world` 非 0 时以世界单位计，否则以像素计。
+int mt_topic_push_text(struct MtTopic *topic,
+                       const struct MtVertex *vertex,
+                       const char *text,
+                       uint8_t anchor,
+                       float size,
+                       int world);
+
+// 保存一个以顶点为中心的矩形，`fill` 为填充透明度，0 表示不填充
+//
+// 顶点形状为 `MT_SHAPE_ORIENTED_BOX` 时按补充数据（弧度）旋转，否则与坐标轴对齐。
+int mt_topic_push_rect(struct MtTopic *topic,
+                       const struct MtVertex *vertex,
+                       float width,
+                       float height,
+                       uint8_t fill);
+
+// 保存一个多边形，`xy` 依次为 `n` 个相对顶点的坐标 x、y，`fill` 为填充透明度
+//
+// 顶点的补充数据有限时按其旋转（弧度），顶点的形状字段被忽略。
+// 只保存前 65535 个点；`n` 大到坐标数溢出时返回 `MT_ERR_RANGE`。
+int mt_topic_push_polygon(struct MtTopic *topic,
+                          const struct MtVertex *vertex,
+                          const float *xy,
+                          size_t n,
+                          uint8_t fill);
+
+// 保存一个以顶点为中心的椭圆，`a`、`b` 为半轴，`fill` 为填充透明度
+//
+// 按顶点的补充数据（弧度）旋转，顶点的形状字段被忽略。
+int mt_topic_push_ellipse(struct MtTopic *topic,
+                          const struct MtVertex *vertex,
+                          float a,
+                          float b,
+                          uint8_t fill);
+
+// 保存以顶点为起点的向量，`dx`、`dy` 以世界单位计
+//
+// 顶点的形状字段和补充数据被忽略。
+int mt_topic_push_vector(struct MtTopic *topic, const struct MtVertex *vertex, float dx, float dy);
+
+// 保存以顶点为中心的不确定性椭圆，协方差矩阵为 `[[xx, xy], [xy, yy]]`，半轴为 `sigma` 倍标准差
+//
+// 顶点的形状字段和补充数据被忽略。
+int mt_topic_push_covariance(struct MtTopic *topic,
+                             const struct MtVertex *vertex,
+                             float xx,
+                             float xy,
+                             float yy,
+                             float sigma);
+
+// 写入编号为 `id` 的实体，已有的同号实体被替换，需要附件的形状使用缺省附件
+//
+// 实体不受容量限制，也不与其他顶点连线。
+int mt_topic_put_entity(struct MtTopic *topic,
+                        uint32_t id,
+                        const struct MtVertex *vertex);
+
+// 移动实体，`extra` 为 NaN 时不修改补充数据，实体不存在时接收端忽略
+int mt_topic_move_entity(struct MtTopic *topic, uint32_t id, float x, float y, float extra);
+
+// 删除实体，连接它的边一并删除
+int mt_topic_remove_entity(struct MtTopic *topic, uint32_t id);
+
+// 写入从实体 `from` 到实体 `to` 的边，颜色和样式取 `level` 级别的配置，`alpha` 为透明度
+//
+// 两端的实体都存在时才画出，两个端点都在视野之外时仍画出穿过视野的部分。
+int mt_topic_put_edge(struct MtTopic *topic,
+                      uint32_t from,
+                      uint32_t to,
+                      uint8_t level,
+                      uint8_t alpha);
+
+// 删除从实体 `from` 到实体 `to` 的边
+int mt_topic_remove_edge(struct MtTopic *topic, uint32_t from, uint32_t to);
+
+// 保存栅格的一块，`cells` 为 `width` 列 `height` 行的值，按行优先排列
+//
+// 色标按值递增，值在相邻色标之间时线性插值；255 表示未知。
+// 几何或值的类型改变时接收端重建栅格。
+// 原点不是有限值或单元边长不是正的有限值时返回 `MT_ERR_RANGE`。
+int mt_topic_push_tile_u8(struct MtTopic *topic,
+                          const struct MtGrid *grid,
+                          const struct MtColorStop *palette,
+                          size_t n,
+                          uint32_t column,
+                          uint32_t row,
+                          uint32_t width,
+                          uint32_t height,
+                          const uint8_t *cells);
+
+// 保存栅格的一块，与 [`mt_topic_push_tile_u8`] 相同，值为 f32，NaN 表示未知
+int mt_topic_push_tile_f32(struct MtTopic *topic,
+                           const struct MtGrid *grid,
+                           const struct MtColorStop *palette,
+                           size_t n,
+                           uint32_t column,
+                           uint32_t row,
+                           uint32_t width,
+                           uint32_t height,
+                           const float *cells);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* MONITOR_TOOL_H */
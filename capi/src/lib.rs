@@ -0,0 +1,823 @@
+﻿//! 编码器的 C 接口，声明见 `include/monitor_tool.h`。
+//!
+//! 头文件用 cbindgen 从本文件生成并提交，修改接口后用 `MT_UPDATE_HEADER=1 cargo test -p monitor-tool-capi --test c` 更新。
+//!
+//! 所有函数都检查空指针；字符串必须是以 0 结尾的 UTF-8。
+
+#![deny(warnings)]
+#![allow(clippy::missing_safety_doc)]
+
+use monitor_tool::{
+    palette::Srgba, Anchor, Cells, Ellipse, Encoder, Grid, Label, Marker, Polygon, Rect, Shape,
+    Style, Tile, TopicEncoder, Vector, Vertex,
+};
+use std::{
+    collections::VecDeque,
+    ffi::{c_char, c_int, CStr},
+    ptr, slice,
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// 成功
+pub const MT_OK: c_int = 0;
+/// 参数为空指针
+pub const MT_ERR_NULL: c_int = -1;
+/// 字符串不是 UTF-8
+pub const MT_ERR_UTF8: c_int = -2;
+/// 顶点形状无效
+pub const MT_ERR_SHAPE: c_int = -3;
+/// 缓冲区太小
+pub const MT_ERR_BUFFER: c_int = -4;
+/// 文字锚点无效
+pub const MT_ERR_ANCHOR: c_int = -5;
+/// 点形状无效
+pub const MT_ERR_MARKER: c_int = -6;
+/// 分片数超过 65535
+pub const MT_ERR_FRAGMENTS: c_int = -7;
+/// 参数超出范围
+pub const MT_ERR_RANGE: c_int = -8;
+
+/// 形状：箭头
+pub const MT_SHAPE_ARROW: u8 = 0;
+/// 形状：圆
+pub const MT_SHAPE_CIRCLE: u8 = 1;
+/// 形状：文字
+pub const MT_SHAPE_TEXT: u8 = 2;
+/// 形状：轴对齐矩形
+pub const MT_SHAPE_RECT: u8 = 3;
+/// 形状：旋转矩形
+pub const MT_SHAPE_ORIENTED_BOX: u8 = 4;
+/// 形状：多边形
+pub const MT_SHAPE_POLYGON: u8 = 5;
+/// 形状：椭圆
+pub const MT_SHAPE_ELLIPSE: u8 = 6;
+/// 形状：向量
+pub const MT_SHAPE_VECTOR: u8 = 7;
+
+/// 点形状：方块
+pub const MT_MARKER_SQUARE: u8 = 0;
+/// 点形状：圆点
+pub const MT_MARKER_DOT: u8 = 1;
+/// 点形状：叉
+pub const MT_MARKER_CROSS: u8 = 2;
+
+/// 文字锚点：顶点位于文字框的左上角
+pub const MT_ANCHOR_TOP_LEFT: u8 = 0;
+/// 文字锚点：顶点位于文字框的上边中点
+pub const MT_ANCHOR_TOP: u8 = 1;
+/// 文字锚点：顶点位于文字框的右上角
+pub const MT_ANCHOR_TOP_RIGHT: u8 = 2;
+/// 文字锚点：顶点位于文字框的左边中点
+pub const MT_ANCHOR_LEFT: u8 = 3;
+/// 文字锚点：顶点位于文字框的中心
+pub const MT_ANCHOR_CENTER: u8 = 4;
+/// 文字锚点：顶点位于文字框的右边中点
+pub const MT_ANCHOR_RIGHT: u8 = 5;
+/// 文字锚点：顶点位于文字框的左下角
+pub const MT_ANCHOR_BOTTOM_LEFT: u8 = 6;
+/// 文字锚点：顶点位于文字框的下边中点
+pub const MT_ANCHOR_BOTTOM: u8 = 7;
+/// 文字锚点：顶点位于文字框的右下角
+pub const MT_ANCHOR_BOTTOM_RIGHT: u8 = 8;
+
+/// 编码器，以及已编码、等待取走的包
+pub struct MtEncoder {
+    encoder: Encoder,
+    packets: VecDeque<Vec<u8>>,
+}
+
+/// 话题句柄，编码之后仍可继续写入下一帧
+pub struct MtTopic {
+    encoder: *mut MtEncoder,
+    name: String,
+}
+
+/// 图形顶点，共 16 字节，与 `Vertex` 布局相同，形状用整数表示
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct MtVertex {
+    /// 位置 x
+    pub x: f32,
+    /// 位置 y
+    pub y: f32,
+    /// 等级
+    pub level: u8,
+    /// 与上一个顶点连线的透明度，0 表示不连线
+    pub alpha: u8,
+    /// 必须为 0
+    pub reserved: u8,
+    /// `MT_SHAPE_*`
+    pub shape: u8,
+    /// 箭头方向、圆半径或矩形、多边形的转角
+    pub extra: f32,
+}
+
+/// 栅格几何，与 `Grid` 布局相同，第 0 行第 0 列单元的左下角为原点，行号沿 y 轴正方向增长
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct MtGrid {
+    /// 原点 x
+    pub origin_x: f32,
+    /// 原点 y
+    pub origin_y: f32,
+    /// 单元边长
+    pub resolution: f32,
+    /// 列数
+    pub width: u32,
+    /// 行数
+    pub height: u32,
+}
+
+/// 栅格色标，值在相邻色标之间时线性插值
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct MtColorStop {
+    pub value: f32,
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+macro_rules! try_c {
+    ($result:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(code) => return code as _,
+        }
+    };
+}
+
+/// 创建编码器
+#[no_mangle]
+pub extern "C" fn mt_encoder_new() -> *mut MtEncoder {
+    Box::into_raw(Box::new(MtEncoder {
+        encoder: Encoder::default(),
+        packets: VecDeque::new(),
+    }))
+}
+
+/// 释放编码器，之后不能再使用它的话题句柄
+#[no_mangle]
+pub unsafe extern "C" fn mt_encoder_free(encoder: *mut MtEncoder) {
+    if !encoder.is_null() {
+        drop(Box::from_raw(encoder));
+    }
+}
+
+/// 更新同步组，`life_time` 单位为秒，不大于 0 表示不修改
+#[no_mangle]
+pub unsafe extern "C" fn mt_encoder_sync_set(
+    encoder: *mut MtEncoder,
+    set: *const c_char,
+    topics: *const *const c_char,
+    n: usize,
+    life_time: f64,
+) -> c_int {
+    let encoder = try_c!(as_mut(encoder));
+    let set = try_c!(as_str(set));
+    let topics = try_c!(as_strs(topics, n));
+    let life_time = Duration::try_from_secs_f64(life_time)
+        .ok()
+        .filter(|t| !t.is_zero());
+    encoder.encoder.sync_set(set, &topics, life_time);
+    MT_OK
+}
+
+/// 更新图层，`visible` 小于 0 表示不修改，0 表示隐藏，大于 0 表示显示
+#[no_mangle]
+pub unsafe extern "C" fn mt_encoder_layer(
+    encoder: *mut MtEncoder,
+    layer: *const c_char,
+    topics: *const *const c_char,
+    n: usize,
+    visible: c_int,
+) -> c_int {
+    let encoder = try_c!(as_mut(encoder));
+    let layer = try_c!(as_str(layer));
+    let topics = try_c!(as_strs(topics, n));
+    let visible = if visible < 0 { None } else { Some(visible > 0) };
+    encoder.encoder.layer(layer, &topics, visible);
+    MT_OK
+}
+
+/// 删除话题，之前写入这个话题的内容一并丢弃
+#[no_mangle]
+pub unsafe extern "C" fn mt_encoder_remove_topic(
+    encoder: *mut MtEncoder,
+    topic: *const c_char,
+) -> c_int {
+    let encoder = try_c!(as_mut(encoder));
+    encoder.encoder.remove_topic(try_c!(as_str(topic)));
+    MT_OK
+}
+
+/// 删除图层及其中的所有话题
+#[no_mangle]
+pub unsafe extern "C" fn mt_encoder_remove_layer(
+    encoder: *mut MtEncoder,
+    layer: *const c_char,
+) -> c_int {
+    let encoder = try_c!(as_mut(encoder));
+    encoder.encoder.remove_layer(try_c!(as_str(layer)));
+    MT_OK
+}
+
+/// 解散同步组，其中的话题保留
+#[no_mangle]
+pub unsafe extern "C" fn mt_encoder_dissolve_sync_set(
+    encoder: *mut MtEncoder,
+    set: *const c_char,
+) -> c_int {
+    let encoder = try_c!(as_mut(encoder));
+    encoder.encoder.dissolve_sync_set(try_c!(as_str(set)));
+    MT_OK
+}
+
+/// 设置视野中心和比例，非有限值表示不修改，比例为 0 表示开启自动视野
+#[no_mangle]
+pub unsafe extern "C" fn mt_encoder_set_view(
+    encoder: *mut MtEncoder,
+    x: f32,
+    y: f32,
+    scale: f32,
+) -> c_int {
+    try_c!(as_mut(encoder)).encoder.set_view(x, y, scale);
+    MT_OK
+}
+
+/// 使视野适应话题的范围，并关闭自动视野
+#[no_mangle]
+pub unsafe extern "C" fn mt_encoder_fit_topic(
+    encoder: *mut MtEncoder,
+    topic: *const c_char,
+) -> c_int {
+    let encoder = try_c!(as_mut(encoder));
+    encoder.encoder.fit_topic(try_c!(as_str(topic)));
+    MT_OK
+}
+
+/// 开启（非 0）或关闭（0）自动视野
+#[no_mangle]
+pub unsafe extern "C" fn mt_encoder_set_auto_view(
+    encoder: *mut MtEncoder,
+    auto_view: c_int,
+) -> c_int {
+    try_c!(as_mut(encoder))
+        .encoder
+        .set_auto_view(auto_view != 0);
+    MT_OK
+}
+
+/// 设置源时间，即数据产生的时刻，`sec` 和 `nsec` 从 UNIX 纪元计，同 `struct timespec`
+///
+/// `nsec` 不小于 10 亿或时间超出系统表示范围时返回 `MT_ERR_RANGE`。
+#[no_mangle]
+pub unsafe extern "C" fn mt_encoder_set_time(
+    encoder: *mut MtEncoder,
+    sec: u64,
+    nsec: u32,
+) -> c_int {
+    let encoder = try_c!(as_mut(encoder));
+    if nsec >= 1_000_000_000 {
+        return MT_ERR_RANGE;
+    }
+    let time = try_c!(UNIX_EPOCH
+        .checked_add(Duration::new(sec, nsec))
+        .ok_or(MT_ERR_RANGE));
+    encoder.encoder.set_time(time);
+    MT_OK
+}
+
+/// 把当前内容编码为一帧，每个包不超过 `max_datagram` 字节，0 表示不限
+///
+/// 编码器随后清空；返回等待取走的包数，分片过多时返回 `MT_ERR_FRAGMENTS`，这一帧被丢弃。
+#[no_mangle]
+pub unsafe extern "C" fn mt_encoder_finish(encoder: *mut MtEncoder, max_datagram: usize) -> isize {
+    let encoder = try_c!(as_mut(encoder));
+    let frame = std::mem::take(&mut encoder.encoder);
+    if max_datagram == 0 {
+        encoder.packets.push_back(frame.encode());
+    } else {
+        let chunks = try_c!(frame
+            .encode_chunks(max_datagram)
+            .map_err(|_| MT_ERR_FRAGMENTS));
+        encoder.packets.extend(chunks);
+    }
+    encoder.packets.len() as _
+}
+
+/// 下一个待取走的包的长度，没有时返回 0
+#[no_mangle]
+pub unsafe extern "C" fn mt_encoder_next_len(encoder: *const MtEncoder) -> usize {
+    match encoder.as_ref() {
+        Some(encoder) => encoder.packets.front().map_or(0, Vec::len),
+        None => 0,
+    }
+}
+
+/// 取走下一个包，写入 `buf`
+///
+/// 返回包长度，没有包时返回 0；缓冲区不够时返回 `MT_ERR_BUFFER`，包保留。
+#[no_mangle]
+pub unsafe extern "C" fn mt_encoder_next(
+    encoder: *mut MtEncoder,
+    buf: *mut u8,
+    capacity: usize,
+) -> isize {
+    let encoder = try_c!(as_mut(encoder));
+    let Some(packet) = encoder.packets.front() else {
+        return 0;
+    };
+    if packet.len() > capacity {
+        return MT_ERR_BUFFER as _;
+    }
+    if buf.is_null() {
+        return MT_ERR_NULL as _;
+    }
+    ptr::copy_nonoverlapping(packet.as_ptr(), buf, packet.len());
+    encoder.packets.pop_front().unwrap().len() as _
+}
+
+/// 编码到 `buf`
+///
+/// 没有待取走的包时先把当前内容编码为一个包，然后同 [`mt_encoder_next`]。
+#[no_mangle]
+pub unsafe extern "C" fn mt_encoder_encode(
+    encoder: *mut MtEncoder,
+    buf: *mut u8,
+    capacity: usize,
+) -> isize {
+    if mt_encoder_next_len(encoder) == 0 {
+        mt_encoder_finish(encoder, 0);
+    }
+    mt_encoder_next(encoder, buf, capacity)
+}
+
+/// 打开话题，失败时返回空指针
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_open(
+    encoder: *mut MtEncoder,
+    name: *const c_char,
+) -> *mut MtTopic {
+    let (Ok(inner), Ok(name)) = (as_mut(encoder), as_str(name)) else {
+        return ptr::null_mut();
+    };
+    inner.encoder.topic(name);
+    Box::into_raw(Box::new(MtTopic {
+        encoder,
+        name: name.into(),
+    }))
+}
+
+/// 关闭话题句柄，不影响已写入的内容
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_close(topic: *mut MtTopic) {
+    if !topic.is_null() {
+        drop(Box::from_raw(topic));
+    }
+}
+
+/// 清空话题缓存
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_clear(topic: *mut MtTopic) -> c_int {
+    with_topic(topic, |mut topic| topic.clear())
+}
+
+/// 设置话题容量
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_set_capacity(topic: *mut MtTopic, capacity: u32) -> c_int {
+    with_topic(topic, |mut topic| topic.set_capacity(capacity))
+}
+
+/// 设置关注数量
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_set_focus(topic: *mut MtTopic, focus: u32) -> c_int {
+    with_topic(topic, |mut topic| topic.set_focus(focus))
+}
+
+/// 设置保留时长，单位为秒，只保留最近这么长时间内的顶点和实体，不大于 0 表示不限
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_set_retention(topic: *mut MtTopic, retention: f64) -> c_int {
+    let retention = Duration::try_from_secs_f64(retention)
+        .ok()
+        .filter(|t| !t.is_zero());
+    with_topic(topic, |mut topic| topic.set_retention(retention))
+}
+
+/// 设置级别颜色
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_set_color(
+    topic: *mut MtTopic,
+    level: u8,
+    r: u8,
+    g: u8,
+    b: u8,
+    a: u8,
+) -> c_int {
+    let color = Srgba::new(r, g, b, a).into_format();
+    with_topic(topic, |mut topic| topic.set_color(level, color))
+}
+
+/// 设置级别样式，长度以像素计，`arrow_world` 非 0 时箭头长度以世界单位计
+///
+/// 虚线的实段或空段不为正时画实线。
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_set_style(
+    topic: *mut MtTopic,
+    level: u8,
+    marker: u8,
+    marker_size: f32,
+    line_width: f32,
+    dash_on: f32,
+    dash_off: f32,
+    arrow_length: f32,
+    arrow_world: c_int,
+) -> c_int {
+    let marker = try_c!(Marker::try_from(marker).map_err(|_| MT_ERR_MARKER));
+    let style = Style {
+        marker,
+        marker_size,
+        line_width,
+        dash: [dash_on, dash_off],
+        arrow_length,
+        arrow_world: arrow_world != 0,
+        ..Style::DEFAULT
+    };
+    with_topic(topic, |mut topic| topic.set_style(level, style))
+}
+
+/// 设置级别的向量样式，画出的长度为模长的 `scale` 倍
+///
+/// `magnitude_max` 大于 `magnitude_min` 时按模长在该范围内的位置着色，否则使用级别颜色。
+/// 不改变级别样式的其他字段。
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_set_vector_style(
+    topic: *mut MtTopic,
+    level: u8,
+    scale: f32,
+    magnitude_min: f32,
+    magnitude_max: f32,
+) -> c_int {
+    with_topic(topic, |mut topic| {
+        let style = topic.style_mut(level);
+        style.vector_scale = scale;
+        style.magnitude = [magnitude_min, magnitude_max];
+    })
+}
+
+/// 保存 `n` 个顶点，任一顶点无效时一个也不保存
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_push(
+    topic: *mut MtTopic,
+    vertex: *const MtVertex,
+    n: usize,
+) -> c_int {
+    if topic.is_null() || (vertex.is_null() && n > 0) {
+        return MT_ERR_NULL;
+    }
+    let vertex = try_c!(as_slice(vertex, n)
+        .iter()
+        .map(vertex_of)
+        .collect::<Result<Vec<_>, c_int>>());
+    with_topic(topic, |mut topic| topic.extend(vertex))
+}
+
+/// 保存一个文字标签，顶点的形状字段被忽略
+///
+/// `size` 在 `world` 非 0 时以世界单位计，否则以像素计。
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_push_text(
+    topic: *mut MtTopic,
+    vertex: *const MtVertex,
+    text: *const c_char,
+    anchor: u8,
+    size: f32,
+    world: c_int,
+) -> c_int {
+    if topic.is_null() || vertex.is_null() {
+        return MT_ERR_NULL;
+    }
+    let text = try_c!(as_str(text));
+    let anchor = try_c!(Anchor::try_from(anchor).map_err(|_| MT_ERR_ANCHOR));
+    let vertex = try_c!(vertex_of(&MtVertex {
+        shape: Shape::Text as u8,
+        ..*vertex
+    }));
+    let label = Label {
+        text: text.into(),
+        anchor,
+        size,
+        world: world != 0,
+    };
+    with_topic(topic, |mut topic| topic.push_attached(vertex, label))
+}
+
+/// 保存一个以顶点为中心的矩形，`fill` 为填充透明度，0 表示不填充
+///
+/// 顶点形状为 `MT_SHAPE_ORIENTED_BOX` 时按补充数据（弧度）旋转，否则与坐标轴对齐。
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_push_rect(
+    topic: *mut MtTopic,
+    vertex: *const MtVertex,
+    width: f32,
+    height: f32,
+    fill: u8,
+) -> c_int {
+    if topic.is_null() || vertex.is_null() {
+        return MT_ERR_NULL;
+    }
+    let vertex = try_c!(vertex_of(&*vertex));
+    let rect = Rect {
+        width,
+        height,
+        fill,
+    };
+    with_topic(topic, |mut topic| topic.push_attached(vertex, rect))
+}
+
+/// 保存一个多边形，`xy` 依次为 `n` 个相对顶点的坐标 x、y，`fill` 为填充透明度
+///
+/// 顶点的补充数据有限时按其旋转（弧度），顶点的形状字段被忽略。
+/// 只保存前 65535 个点；`n` 大到坐标数溢出时返回 `MT_ERR_RANGE`。
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_push_polygon(
+    topic: *mut MtTopic,
+    vertex: *const MtVertex,
+    xy: *const f32,
+    n: usize,
+    fill: u8,
+) -> c_int {
+    if topic.is_null() || vertex.is_null() || (xy.is_null() && n > 0) {
+        return MT_ERR_NULL;
+    }
+    let vertex = try_c!(vertex_of(&MtVertex {
+        shape: Shape::Polygon as u8,
+        ..*vertex
+    }));
+    if n.checked_mul(2).is_none() {
+        return MT_ERR_RANGE;
+    }
+    let points = as_slice(xy, n.min(u16::MAX as usize) * 2)
+        .chunks_exact(2)
+        .map(|p| [p[0], p[1]])
+        .collect();
+    let polygon = Polygon { points, fill };
+    with_topic(topic, |mut topic| topic.push_attached(vertex, polygon))
+}
+
+/// 保存一个以顶点为中心的椭圆，`a`、`b` 为半轴，`fill` 为填充透明度
+///
+/// 按顶点的补充数据（弧度）旋转，顶点的形状字段被忽略。
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_push_ellipse(
+    topic: *mut MtTopic,
+    vertex: *const MtVertex,
+    a: f32,
+    b: f32,
+    fill: u8,
+) -> c_int {
+    if topic.is_null() || vertex.is_null() {
+        return MT_ERR_NULL;
+    }
+    let vertex = try_c!(vertex_of(&MtVertex {
+        shape: Shape::Ellipse as u8,
+        ..*vertex
+    }));
+    let ellipse = Ellipse { a, b, fill };
+    with_topic(topic, |mut topic| topic.push_attached(vertex, ellipse))
+}
+
+/// 保存以顶点为起点的向量，`dx`、`dy` 以世界单位计
+///
+/// 顶点的形状字段和补充数据被忽略。
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_push_vector(
+    topic: *mut MtTopic,
+    vertex: *const MtVertex,
+    dx: f32,
+    dy: f32,
+) -> c_int {
+    if topic.is_null() || vertex.is_null() {
+        return MT_ERR_NULL;
+    }
+    let vertex = try_c!(vertex_of(&MtVertex {
+        shape: Shape::Vector as u8,
+        ..*vertex
+    }));
+    with_topic(topic, |mut topic| {
+        topic.push_attached(vertex, Vector { dx, dy })
+    })
+}
+
+/// 保存以顶点为中心的不确定性椭圆，协方差矩阵为 `[[xx, xy], [xy, yy]]`，半轴为 `sigma` 倍标准差
+///
+/// 顶点的形状字段和补充数据被忽略。
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_push_covariance(
+    topic: *mut MtTopic,
+    vertex: *const MtVertex,
+    xx: f32,
+    xy: f32,
+    yy: f32,
+    sigma: f32,
+) -> c_int {
+    if topic.is_null() || vertex.is_null() {
+        return MT_ERR_NULL;
+    }
+    let vertex = try_c!(vertex_of(&MtVertex {
+        shape: Shape::Ellipse as u8,
+        ..*vertex
+    }));
+    with_topic(topic, |mut topic| {
+        topic.push_covariance(vertex, [xx, xy, yy], sigma)
+    })
+}
+
+/// 写入编号为 `id` 的实体，已有的同号实体被替换，需要附件的形状使用缺省附件
+///
+/// 实体不受容量限制，也不与其他顶点连线。
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_put_entity(
+    topic: *mut MtTopic,
+    id: u32,
+    vertex: *const MtVertex,
+) -> c_int {
+    if topic.is_null() || vertex.is_null() {
+        return MT_ERR_NULL;
+    }
+    let vertex = try_c!(vertex_of(&*vertex));
+    with_topic(topic, |mut topic| topic.put_entity(id, vertex))
+}
+
+/// 移动实体，`extra` 为 NaN 时不修改补充数据，实体不存在时接收端忽略
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_move_entity(
+    topic: *mut MtTopic,
+    id: u32,
+    x: f32,
+    y: f32,
+    extra: f32,
+) -> c_int {
+    with_topic(topic, |mut topic| topic.move_entity(id, x, y, extra))
+}
+
+/// 删除实体，连接它的边一并删除
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_remove_entity(topic: *mut MtTopic, id: u32) -> c_int {
+    with_topic(topic, |mut topic| topic.remove_entity(id))
+}
+
+/// 写入从实体 `from` 到实体 `to` 的边，颜色和样式取 `level` 级别的配置，`alpha` 为透明度
+///
+/// 两端的实体都存在时才画出，两个端点都在视野之外时仍画出穿过视野的部分。
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_put_edge(
+    topic: *mut MtTopic,
+    from: u32,
+    to: u32,
+    level: u8,
+    alpha: u8,
+) -> c_int {
+    with_topic(topic, |mut topic| topic.put_edge(from, to, level, alpha))
+}
+
+/// 删除从实体 `from` 到实体 `to` 的边
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_remove_edge(topic: *mut MtTopic, from: u32, to: u32) -> c_int {
+    with_topic(topic, |mut topic| topic.remove_edge(from, to))
+}
+
+/// 保存栅格的一块，`cells` 为 `width` 列 `height` 行的值，按行优先排列
+///
+/// 色标按值递增，值在相邻色标之间时线性插值；255 表示未知。
+/// 几何或值的类型改变时接收端重建栅格。
+/// 原点不是有限值或单元边长不是正的有限值时返回 `MT_ERR_RANGE`。
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_push_tile_u8(
+    topic: *mut MtTopic,
+    grid: *const MtGrid,
+    palette: *const MtColorStop,
+    n: usize,
+    column: u32,
+    row: u32,
+    width: u32,
+    height: u32,
+    cells: *const u8,
+) -> c_int {
+    let len = width as usize * height as usize;
+    if cells.is_null() && len > 0 {
+        return MT_ERR_NULL;
+    }
+    let cells = Cells::U8(as_slice(cells, len).to_vec());
+    let tile = try_c!(tile_of(grid, palette, n, column, row, width, cells));
+    with_topic(topic, |mut topic| topic.push_tile(tile))
+}
+
+/// 保存栅格的一块，与 [`mt_topic_push_tile_u8`] 相同，值为 f32，NaN 表示未知
+#[no_mangle]
+pub unsafe extern "C" fn mt_topic_push_tile_f32(
+    topic: *mut MtTopic,
+    grid: *const MtGrid,
+    palette: *const MtColorStop,
+    n: usize,
+    column: u32,
+    row: u32,
+    width: u32,
+    height: u32,
+    cells: *const f32,
+) -> c_int {
+    let len = width as usize * height as usize;
+    if cells.is_null() && len > 0 {
+        return MT_ERR_NULL;
+    }
+    let cells = Cells::F32(as_slice(cells, len).to_vec());
+    let tile = try_c!(tile_of(grid, palette, n, column, row, width, cells));
+    with_topic(topic, |mut topic| topic.push_tile(tile))
+}
+
+#[inline]
+fn vertex_of(v: &MtVertex) -> Result<Vertex, c_int> {
+    Ok(Vertex {
+        x: v.x,
+        y: v.y,
+        level: v.level,
+        alpha: v.alpha,
+        _zero: 0,
+        shape: Shape::try_from(v.shape).map_err(|_| MT_ERR_SHAPE)?,
+        extra: v.extra,
+    })
+}
+
+unsafe fn tile_of(
+    grid: *const MtGrid,
+    palette: *const MtColorStop,
+    n: usize,
+    column: u32,
+    row: u32,
+    width: u32,
+    cells: Cells,
+) -> Result<Tile, c_int> {
+    if grid.is_null() || (palette.is_null() && n > 0) {
+        return Err(MT_ERR_NULL);
+    }
+    let grid = &*grid;
+    let grid = Grid {
+        origin: [grid.origin_x, grid.origin_y],
+        resolution: grid.resolution,
+        width: grid.width,
+        height: grid.height,
+    };
+    if !grid.is_valid() {
+        return Err(MT_ERR_RANGE);
+    }
+    Ok(Tile {
+        grid,
+        palette: as_slice(palette, n)
+            .iter()
+            .map(|s| (s.value, Srgba::new(s.r, s.g, s.b, s.a)))
+            .collect(),
+        column,
+        row,
+        width,
+        cells,
+    })
+}
+
+#[inline]
+unsafe fn as_mut<'a, T>(ptr: *mut T) -> Result<&'a mut T, c_int> {
+    ptr.as_mut().ok_or(MT_ERR_NULL)
+}
+
+#[inline]
+unsafe fn as_str<'a>(ptr: *const c_char) -> Result<&'a str, c_int> {
+    if ptr.is_null() {
+        Err(MT_ERR_NULL)
+    } else {
+        CStr::from_ptr(ptr).to_str().map_err(|_| MT_ERR_UTF8)
+    }
+}
+
+#[inline]
+unsafe fn as_slice<'a, T>(ptr: *const T, n: usize) -> &'a [T] {
+    if n == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(ptr, n)
+    }
+}
+
+unsafe fn as_strs<'a>(ptr: *const *const c_char, n: usize) -> Result<Vec<&'a str>, c_int> {
+    if ptr.is_null() && n > 0 {
+        Err(MT_ERR_NULL)
+    } else {
+        as_slice(ptr, n).iter().map(|s| as_str(*s)).collect()
+    }
+}
+
+unsafe fn with_topic(topic: *mut MtTopic, f: impl FnOnce(TopicEncoder)) -> c_int {
+    let topic = try_c!(as_mut(topic));
+    let encoder = try_c!(as_mut(topic.encoder));
+    f(encoder.encoder.topic(&topic.name));
+    MT_OK
+}
@@ -0,0 +1,78 @@
+﻿use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const MANIFEST_DIR: &str = env!("CARGO_MANIFEST_DIR");
+
+/// 提交的头文件与 cbindgen 的输出一致，设置 `MT_UPDATE_HEADER` 时重新生成
+#[test]
+fn header_up_to_date() {
+    let dir = Path::new(MANIFEST_DIR);
+    let config = cbindgen::Config::from_file(dir.join("cbindgen.toml")).unwrap();
+    let mut header = Vec::new();
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(dir.join("src/lib.rs"))
+        .generate()
+        .expect("failed to generate monitor_tool.h")
+        .write(&mut header);
+    let path = dir.join("include/monitor_tool.h");
+    if env::var_os("MT_UPDATE_HEADER").is_some() {
+        fs::write(&path, &header).unwrap();
+    }
+    assert!(
+        fs::read(&path).unwrap() == header,
+        "{:?} is out of date, run `MT_UPDATE_HEADER=1 cargo test -p monitor-tool-capi --test c`",
+        path,
+    );
+}
+
+/// 头文件声明了所有导出的函数
+#[test]
+fn header_declares_exports() {
+    let source = fs::read_to_string(Path::new(MANIFEST_DIR).join("src/lib.rs")).unwrap();
+    let header =
+        fs::read_to_string(Path::new(MANIFEST_DIR).join("include/monitor_tool.h")).unwrap();
+    let exports = source
+        .split("extern \"C\" fn ")
+        .skip(1)
+        .map(|s| &s[..s.find('(').unwrap()])
+        .collect::<Vec<_>>();
+    assert!(!exports.is_empty());
+    for name in exports {
+        assert!(
+            header.contains(&format!("{}(", name)),
+            "{} not declared",
+            name
+        );
+    }
+}
+
+/// 编译并运行链接静态库的 C 程序
+#[test]
+fn c_program() {
+    // 测试程序位于 target/<profile>/deps
+    let exe = env::current_exe().unwrap();
+    let profile = exe.parent().unwrap().parent().unwrap();
+    let lib = profile.join("libmonitor_tool_capi.a");
+    assert!(lib.exists(), "{:?} not built", lib);
+
+    let out = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("encode");
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".into());
+    let status = Command::new(cc)
+        .arg("-std=c99")
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-I")
+        .arg(Path::new(MANIFEST_DIR).join("include"))
+        .arg(Path::new(MANIFEST_DIR).join("tests/encode.c"))
+        .arg(&lib)
+        .args(["-lpthread", "-ldl", "-lm", "-o"])
+        .arg(&out)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(Command::new(&out).status().unwrap().success());
+}
@@ -0,0 +1,122 @@
+/* 通过 C 接口编码，检查包头和错误处理 */
+
+#include "monitor_tool.h"
+#include <math.h>
+#include <stdio.h>
+#include <string.h>
+
+#define CHECK(cond)                                              \
+    do {                                                         \
+        if (!(cond)) {                                           \
+            fprintf(stderr, "%s:%d: %s\n", __FILE__, __LINE__, #cond); \
+            return 1;                                            \
+        }                                                        \
+    } while (0)
+
+int main(void) {
+    const char *topics[] = {"a"};
+    const float triangle[] = {0, 0, 1, 0, 0, 1};
+    const MtGrid grid = {-10, -10, 0.5f, 40, 40};
+    const MtColorStop stops[] = {{0, 255, 255, 255, 255}, {100, 0, 0, 0, 255}};
+    uint8_t occupancy[40 * 40];
+    float heights[4] = {0, 0.5f, 1, 2};
+    uint8_t buf[65536];
+    MtVertex vertex[1000];
+    MtEncoder *encoder = mt_encoder_new();
+    MtTopic *topic = mt_topic_open(encoder, "a");
+    size_t i, n;
+    ptrdiff_t len;
+
+    CHECK(sizeof(MtVertex) == 16);
+    CHECK(topic != NULL);
+    CHECK(mt_encoder_sync_set(encoder, "set", topics, 1, 1.5) == MT_OK);
+    CHECK(mt_encoder_layer(encoder, "layer", topics, 1, 1) == MT_OK);
+    CHECK(mt_topic_set_capacity(topic, 10000) == MT_OK);
+    CHECK(mt_topic_set_focus(topic, 100) == MT_OK);
+    CHECK(mt_topic_set_retention(topic, 30.0) == MT_OK);
+    CHECK(mt_topic_set_retention(NULL, 30.0) == MT_ERR_NULL);
+    CHECK(mt_topic_set_color(topic, 0, 255, 0, 0, 255) == MT_OK);
+    CHECK(mt_topic_set_style(topic, 0, MT_MARKER_DOT, 4, 2, 4, 2, 15, 0) == MT_OK);
+    CHECK(mt_topic_set_style(topic, 0, 7, 4, 2, 0, 0, 15, 0) == MT_ERR_MARKER);
+    CHECK(mt_topic_set_vector_style(topic, 0, 0.5f, 0, 2) == MT_OK);
+    for (i = 0; i < 1000; ++i) {
+        vertex[i].x = (float)i;
+        vertex[i].y = (float)i;
+        vertex[i].level = 0;
+        vertex[i].alpha = 255;
+        vertex[i].reserved = 0;
+        vertex[i].shape = MT_SHAPE_CIRCLE;
+        vertex[i].extra = 0.5f;
+    }
+    CHECK(mt_topic_push(topic, vertex, 1000) == MT_OK);
+    CHECK(mt_encoder_fit_topic(encoder, "a") == MT_OK);
+    CHECK(mt_encoder_set_view(NULL, 0, 0, 1) == MT_ERR_NULL);
+    CHECK(mt_encoder_set_time(encoder, 1760000000u, 500000000u) == MT_OK);
+    CHECK(mt_encoder_set_time(NULL, 0, 0) == MT_ERR_NULL);
+    CHECK(mt_encoder_set_time(encoder, UINT64_MAX, 0) == MT_ERR_RANGE);
+    CHECK(mt_encoder_set_time(encoder, 0, 1000000000u) == MT_ERR_RANGE);
+
+    /* 无效形状整批拒绝 */
+    vertex[0].shape = 9;
+    CHECK(mt_topic_push(topic, vertex, 1) == MT_ERR_SHAPE);
+    CHECK(mt_topic_push(NULL, vertex, 1) == MT_ERR_NULL);
+
+    /* 文字标签 */
+    CHECK(mt_topic_push_text(topic, vertex + 1, "a", MT_ANCHOR_LEFT, 12, 0) == MT_OK);
+    CHECK(mt_topic_push_text(topic, vertex + 1, "a", 9, 12, 0) == MT_ERR_ANCHOR);
+    CHECK(mt_topic_push_text(topic, vertex + 1, NULL, MT_ANCHOR_LEFT, 12, 0) == MT_ERR_NULL);
+
+    /* 矩形和多边形 */
+    CHECK(mt_topic_push_rect(topic, vertex + 1, 2, 1, 128) == MT_OK);
+    CHECK(mt_topic_push_polygon(topic, vertex + 1, triangle, 3, 0) == MT_OK);
+    CHECK(mt_topic_push_polygon(topic, vertex + 1, NULL, 3, 0) == MT_ERR_NULL);
+    CHECK(mt_topic_push_polygon(topic, vertex + 1, triangle, SIZE_MAX, 0) == MT_ERR_RANGE);
+    CHECK(mt_topic_push_ellipse(topic, vertex + 1, 2.0f, 1.0f, 32) == MT_OK);
+    CHECK(mt_topic_push_covariance(topic, vertex, 4.0f, 0.5f, 1.0f, 3.0f) == MT_OK);
+    CHECK(mt_topic_push_covariance(NULL, vertex, 4.0f, 0.5f, 1.0f, 3.0f) == MT_ERR_NULL);
+    CHECK(mt_topic_push_vector(topic, vertex, 1.0f, -0.5f) == MT_OK);
+
+    /* 实体 */
+    CHECK(mt_topic_put_entity(topic, 7, vertex + 1) == MT_OK);
+    CHECK(mt_topic_put_entity(topic, 7, NULL) == MT_ERR_NULL);
+    CHECK(mt_topic_move_entity(topic, 7, 1.0f, 2.0f, NAN) == MT_OK);
+    CHECK(mt_topic_remove_entity(NULL, 7) == MT_ERR_NULL);
+    CHECK(mt_topic_remove_entity(topic, 7) == MT_OK);
+    CHECK(mt_topic_put_edge(topic, 7, 8, 0, 255) == MT_OK);
+    CHECK(mt_topic_put_edge(NULL, 7, 8, 0, 255) == MT_ERR_NULL);
+    CHECK(mt_topic_remove_edge(topic, 7, 8) == MT_OK);
+
+    /* 栅格 */
+    CHECK(sizeof(MtGrid) == 20 && sizeof(MtColorStop) == 8);
+    memset(occupancy, 255, sizeof occupancy);
+    CHECK(mt_topic_push_tile_u8(topic, &grid, stops, 2, 0, 0, 40, 40, occupancy) == MT_OK);
+    CHECK(mt_topic_push_tile_f32(topic, &grid, NULL, 0, 4, 4, 2, 2, heights) == MT_OK);
+    CHECK(mt_topic_push_tile_u8(topic, NULL, stops, 2, 0, 0, 40, 40, occupancy) == MT_ERR_NULL);
+    CHECK(mt_topic_push_tile_f32(topic, &grid, stops, 2, 0, 0, 2, 2, NULL) == MT_ERR_NULL);
+
+    /* 拆成多个包 */
+    len = mt_encoder_finish(encoder, 1400);
+    CHECK(len > 1);
+    n = (size_t)len;
+    CHECK(mt_encoder_next(encoder, buf, 4) == MT_ERR_BUFFER);
+    for (i = 0; i < n; ++i) {
+        len = mt_encoder_next(encoder, buf, sizeof buf);
+        CHECK(len > 0 && len <= 1400);
+        CHECK(memcmp(buf, "MTRS", 4) == 0);
+        CHECK(buf[9] == i && buf[11] == n);
+    }
+    CHECK(mt_encoder_next(encoder, buf, sizeof buf) == 0);
+
+    /* 句柄在编码后仍可使用 */
+    CHECK(mt_encoder_remove_topic(encoder, "a") == MT_OK);
+    CHECK(mt_encoder_dissolve_sync_set(encoder, "set") == MT_OK);
+    CHECK(mt_encoder_remove_layer(encoder, NULL) == MT_ERR_NULL);
+    CHECK(mt_topic_push(topic, vertex + 1, 1) == MT_OK);
+    len = mt_encoder_encode(encoder, buf, sizeof buf);
+    CHECK(len > 0);
+    CHECK(buf[11] == 1);
+
+    mt_topic_close(topic);
+    mt_encoder_free(encoder);
+    return 0;
+}
@@ -1,10 +1,15 @@
-﻿use super::{figure::FigureSnapshot, figure_program::FigureEvent, Figure};
+﻿use super::{
+    figure::{Eviction, FigureSnapshot},
+    figure_program::FigureEvent,
+    Figure,
+};
 use crate::protocol::decode;
 use async_std::{
     channel::{unbounded, Receiver, RecvError, TryRecvError},
     task::{self, JoinHandle},
 };
 use iced::{canvas::Geometry, Point, Rectangle};
+use std::time::Duration;
 
 pub fn spawn_background(
     input: Receiver<FigureEvent>,
@@ -54,7 +59,14 @@ fn handle(mut figure: Box<Figure>, event: FigureEvent) -> JoinHandle<Box<Figure>
             ReadyForGrab => figure.auto_view = false,
             Grab(v) => figure.grab(v),
             Select(bounds, p0, p1) => figure.select(bounds, p0, p1),
-            Packet(time, buf) => decode(figure.as_mut(), time, buf.as_slice()),
+            Packet(time, source, frame) => {
+                let frame = frame.iter().map(Vec::as_slice);
+                if let Err(e) = decode(figure.as_mut(), time, &source, frame) {
+                    figure.report_malformed(e);
+                }
+            }
+            #[cfg(feature = "json")]
+            Text(time, source, packet) => packet.apply(figure.as_mut(), time, &source),
             Line(line) => {
                 let words = line.split_whitespace().collect::<Vec<_>>();
                 match words.as_slice() {
@@ -73,6 +85,16 @@ fn handle(mut figure: Box<Figure>, event: FigureEvent) -> JoinHandle<Box<Figure>
                             figure.set_view(x, y, f32::NAN, f32::NAN);
                         }
                     }
+                    ["usage"] => figure.print_usage(),
+                    // 内存预算（字节），0 表示不限
+                    ["budget", num] => {
+                        if let Ok(n) = num.parse() {
+                            figure.budget.limit = n;
+                            println!("set memory budget {} bytes", n);
+                        }
+                    }
+                    ["evict", "oldest"] => figure.budget.eviction = Eviction::Oldest,
+                    ["evict", "proportional"] => figure.budget.eviction = Eviction::Proportional,
                     ["show", layer] => figure.set_visible(layer, true),
                     ["hide", layer] => figure.set_visible(layer, false),
                     [topic, "focus", num] => {
@@ -83,6 +105,27 @@ fn handle(mut figure: Box<Figure>, event: FigureEvent) -> JoinHandle<Box<Figure>
                             }
                         }
                     }
+                    [topic, "capacity", num] => {
+                        if let Ok(n) = num.parse() {
+                            if let Some(content) = figure.get_topic(topic) {
+                                content.set_capacity(n);
+                                println!("set capacity {} for {}", n, topic);
+                            }
+                        }
+                    }
+                    // 保留时长以秒计，0 表示不限
+                    [topic, "retention", secs] => {
+                        if let Ok(secs) = secs.parse::<f64>() {
+                            let retention = Some(secs)
+                                .filter(|secs| *secs > 0.0)
+                                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                                .unwrap_or(Duration::MAX);
+                            if let Some(content) = figure.get_topic(topic) {
+                                content.set_retention(retention);
+                                println!("set retention {}s for {}", secs, topic);
+                            }
+                        }
+                    }
                     _ => {}
                 }
             }
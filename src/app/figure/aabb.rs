@@ -1,6 +1,9 @@
-﻿use crate::{Shape, Vertex};
+﻿use crate::{
+    protocol::{Attachment, Grid, Label},
+    Shape, Vertex,
+};
 use iced::{Point, Size};
-use std::cmp::Ordering::*;
+use std::{cmp::Ordering::*, ops::Range};
 
 /// 用外边界表示的范围盒子
 #[allow(clippy::upper_case_acronyms)]
@@ -28,7 +31,13 @@ impl From<Vertex> for AABB {
     /// 从一个点生成无限小的盒
     fn from(v: Vertex) -> Self {
         match v.shape {
-            Shape::Arrow => Self {
+            Shape::Arrow
+            | Shape::Text
+            | Shape::Rect
+            | Shape::OrientedBox
+            | Shape::Polygon
+            | Shape::Ellipse
+            | Shape::Vector => Self {
                 min_x: v.x,
                 max_x: v.x,
                 min_y: v.y,
@@ -56,15 +65,77 @@ impl AABB {
     }
 
     /// 计算一组点的 AABB 盒
-    pub fn foreach_vertex(iter: impl IntoIterator<Item = Vertex>) -> Option<Self> {
+    pub fn foreach_vertex<'a>(
+        iter: impl IntoIterator<Item = (&'a Vertex, Option<&'a Attachment>)>,
+    ) -> Option<Self> {
         let mut iter = iter.into_iter();
-        iter.next().map(|front| {
-            let mut aabb = AABB::from(front);
-            iter.for_each(|v| aabb += Self::from(v));
+        iter.next().map(|(v, a)| {
+            let mut aabb = AABB::of(v, a);
+            iter.for_each(|(v, a)| aabb += Self::of(v, a));
             aabb
         })
     }
 
+    /// 带附件的顶点的范围，以像素为字号的文字视为一个点
+    #[inline]
+    pub fn of(v: &Vertex, attachment: Option<&Attachment>) -> Self {
+        Self::extent(v, attachment).unwrap_or_else(|| Self::from(*v))
+    }
+
+    /// 附件占据的范围：以世界单位为字号的文字、矩形、多边形、椭圆和向量
+    ///
+    /// 向量画出的长度取决于样式，这里按未缩放的终点计算。
+    fn extent(v: &Vertex, attachment: Option<&Attachment>) -> Option<Self> {
+        let place = |[x, y]: [f32; 2]| {
+            let (sin, cos) = yaw(v).sin_cos();
+            Point {
+                x: v.x + x * cos - y * sin,
+                y: v.y + x * sin + y * cos,
+            }
+        };
+        match attachment? {
+            Attachment::Text(label) if label.world => Some(Self::label(v, label)),
+            Attachment::Vector(vector) if vector.dx.is_finite() && vector.dy.is_finite() => {
+                Self::foreach([
+                    Point { x: v.x, y: v.y },
+                    Point {
+                        x: v.x + vector.dx,
+                        y: v.y + vector.dy,
+                    },
+                ])
+            }
+            Attachment::Text(_) | Attachment::Vector(_) => None,
+            Attachment::Rect(rect) => Self::foreach(rect.corners().into_iter().map(place)),
+            Attachment::Polygon(polygon) => {
+                Self::foreach(polygon.points.iter().copied().map(place))
+            }
+            Attachment::Ellipse(ellipse) => {
+                let [x, y] = ellipse.half_extent(yaw(v));
+                Some(Self {
+                    min_x: v.x - x,
+                    max_x: v.x + x,
+                    min_y: v.y - y,
+                    max_y: v.y + y,
+                })
+            }
+        }
+    }
+
+    /// 以世界单位为字号的文字框，按字宽 0.6 倍字号估计
+    fn label(v: &Vertex, label: &Label) -> Self {
+        let height = label.size;
+        let width = 0.6 * label.size * label.text.chars().count() as f32;
+        let (column, row) = label.anchor.grid();
+        let min_x = v.x - width * column as f32 / 2.0;
+        let max_y = v.y + height * row as f32 / 2.0;
+        Self {
+            min_x,
+            max_x: min_x + width,
+            min_y: max_y - height,
+            max_y,
+        }
+    }
+
     /// 计算 [`Size`]
     #[inline]
     pub fn size(&self) -> Size {
@@ -83,11 +154,20 @@ impl AABB {
         }
     }
 
-    /// 判断包含关系
+    /// 判断包含关系，附件占据的范围与盒相交即视为包含
     #[inline]
-    pub fn contains(&self, v: &Vertex) -> bool {
+    pub fn contains(&self, v: &Vertex, attachment: Option<&Attachment>) -> bool {
+        if let Some(extent) = Self::extent(v, attachment) {
+            return self.intersect(extent);
+        }
         match v.shape {
-            Shape::Arrow => {
+            Shape::Arrow
+            | Shape::Text
+            | Shape::Rect
+            | Shape::OrientedBox
+            | Shape::Polygon
+            | Shape::Ellipse
+            | Shape::Vector => {
                 self.min_x <= v.x && v.x <= self.max_x && self.min_y <= v.y && v.y <= self.max_y
             }
             Shape::Circle => {
@@ -124,8 +204,37 @@ impl AABB {
         }
     }
 
+    /// 栅格覆盖的范围，没有单元或几何无效时为 `None`
+    pub fn of_grid(grid: &Grid) -> Option<Self> {
+        let Grid {
+            origin: [x, y],
+            resolution,
+            width,
+            height,
+        } = *grid;
+        (grid.is_valid() && width > 0 && height > 0).then_some(Self {
+            min_x: x,
+            max_x: x + resolution * width as f32,
+            min_y: y,
+            max_y: y + resolution * height as f32,
+        })
+    }
+
+    /// 与盒相交的栅格列和行
+    pub fn cells(&self, grid: &Grid) -> (Range<usize>, Range<usize>) {
+        let span = |min: f32, max: f32, origin: f32, n: u32| {
+            let n = n as f32;
+            let begin = ((min - origin) / grid.resolution).floor().clamp(0.0, n) as usize;
+            let end = ((max - origin) / grid.resolution).ceil().clamp(0.0, n) as usize;
+            begin..end.max(begin)
+        };
+        (
+            span(self.min_x, self.max_x, grid.origin[0], grid.width),
+            span(self.min_y, self.max_y, grid.origin[1], grid.height),
+        )
+    }
+
     /// 判断是否相交
-    #[allow(dead_code)]
     #[inline]
     pub fn intersect(&self, others: Self) -> bool {
         self.min_x <= others.max_x
@@ -149,6 +258,15 @@ impl AABB {
     }
 }
 
+/// 矩形、多边形和椭圆的转角，轴对齐的矩形和非有限值为 0
+#[inline]
+pub(super) fn yaw(v: &Vertex) -> f32 {
+    match v.shape {
+        Shape::OrientedBox | Shape::Polygon | Shape::Ellipse if v.extra.is_finite() => v.extra,
+        _ => 0.0,
+    }
+}
+
 impl std::ops::Add for AABB {
     type Output = Self;
 
@@ -196,3 +314,125 @@ fn test_size() {
     assert_eq!(SIZE.width / width, f32::INFINITY);
     assert_eq!(SIZE.height / height, f32::INFINITY);
 }
+
+#[test]
+fn test_label() {
+    use crate::protocol::Anchor;
+
+    let v = crate::vertex!(0; 1, 1; Text, 0.0; 0);
+    let label = Attachment::Text(Label {
+        text: "ab".into(),
+        anchor: Anchor::TopLeft,
+        size: 1.0,
+        world: true,
+    });
+    let aabb = AABB::of(&v, Some(&label));
+    assert_eq!(aabb.center(), Point { x: 1.6, y: 0.5 });
+    assert_eq!(
+        aabb.size(),
+        Size {
+            width: 1.2,
+            height: 1.0,
+        }
+    );
+    // 文字框与范围相交即可见
+    let view = AABB::from(Point { x: 2.0, y: 0.0 });
+    assert!(view.contains(&v, Some(&label)));
+    let screen = Attachment::Text(Label::screen("ab", 1.0));
+    assert!(!view.contains(&v, Some(&screen)));
+}
+
+#[test]
+fn test_rect_and_polygon() {
+    use crate::protocol::{Polygon, Rect};
+    use std::f32::consts::FRAC_PI_2;
+
+    let rect = Attachment::Rect(Rect {
+        width: 2.0,
+        height: 1.0,
+        fill: 0,
+    });
+    // 轴对齐的矩形忽略补充数据
+    let v = crate::vertex!(0; 1, 1; Rect, FRAC_PI_2; 0);
+    let size = AABB::of(&v, Some(&rect)).size();
+    assert_eq!((size.width, size.height), (2.0, 1.0));
+    // 旋转 90° 的矩形
+    let v = crate::vertex!(0; 1, 1; OrientedBox, FRAC_PI_2; 0);
+    let size = AABB::of(&v, Some(&rect)).size();
+    assert!((size.width - 1.0).abs() < 1e-6 && (size.height - 2.0).abs() < 1e-6);
+    // 多边形的点相对顶点
+    let polygon = Attachment::Polygon(Polygon {
+        points: vec![[1.0, 0.0], [3.0, 0.0], [3.0, 2.0]],
+        fill: 0,
+    });
+    let v = crate::vertex!(0; 1, 1; Polygon, f32::NAN; 0);
+    let aabb = AABB::of(&v, Some(&polygon));
+    assert_eq!(aabb.center(), Point { x: 3.0, y: 2.0 });
+    assert!(AABB::from(Point { x: 2.5, y: 1.5 }).contains(&v, Some(&polygon)));
+    assert!(!AABB::from(Point { x: 0.0, y: 0.0 }).contains(&v, Some(&polygon)));
+}
+
+#[test]
+fn test_ellipse() {
+    use crate::protocol::Ellipse;
+    use std::f32::consts::FRAC_PI_2;
+
+    let ellipse = Attachment::Ellipse(Ellipse {
+        a: 2.0,
+        b: 1.0,
+        fill: 0,
+    });
+    let v = crate::vertex!(0; 1, 1; Ellipse, 0.0; 0);
+    let size = AABB::of(&v, Some(&ellipse)).size();
+    assert_eq!((size.width, size.height), (4.0, 2.0));
+    // 旋转 90° 后长轴沿 y 轴
+    let v = crate::vertex!(0; 1, 1; Ellipse, FRAC_PI_2; 0);
+    let size = AABB::of(&v, Some(&ellipse)).size();
+    assert!((size.width - 2.0).abs() < 1e-6 && (size.height - 4.0).abs() < 1e-6);
+    assert!(AABB::from(Point { x: 1.5, y: 2.5 }).contains(&v, Some(&ellipse)));
+    assert!(!AABB::from(Point { x: 2.5, y: 1.0 }).contains(&v, Some(&ellipse)));
+}
+
+#[test]
+fn test_vector() {
+    use crate::protocol::Vector;
+
+    // 起点在视野外、终点在视野内的向量仍然可见
+    let vector = Attachment::Vector(Vector { dx: 4.0, dy: -2.0 });
+    let v = crate::vertex!(0; 1, 1; Vector, f32::NAN; 0);
+    let aabb = AABB::of(&v, Some(&vector));
+    assert_eq!(aabb.center(), Point { x: 3.0, y: 0.0 });
+    assert!(AABB::from(Point { x: 5.0, y: -1.0 }).contains(&v, Some(&vector)));
+    // 非有限的向量不画，只计起点
+    let vector = Attachment::Vector(Vector {
+        dx: f32::NAN,
+        dy: 0.0,
+    });
+    assert_eq!(AABB::of(&v, Some(&vector)), AABB::from(v));
+}
+
+#[test]
+fn test_grid() {
+    let grid = Grid {
+        origin: [-1.0, 2.0],
+        resolution: 0.5,
+        width: 8,
+        height: 4,
+    };
+    let aabb = AABB::of_grid(&grid).unwrap();
+    assert_eq!(aabb.center(), Point { x: 1.0, y: 3.0 });
+    assert_eq!(aabb.cells(&grid), (0..8, 0..4));
+    // 视野只盖住一部分单元，边上的单元算在内
+    let view = AABB::foreach([Point { x: 0.2, y: 2.9 }, Point { x: 5.0, y: 10.0 }]).unwrap();
+    assert_eq!(view.cells(&grid), (2..8, 1..4));
+    // 视野与栅格不相交
+    let view = AABB::from(Point { x: -5.0, y: 0.0 });
+    assert!(view.cells(&grid).0.is_empty());
+    assert_eq!(
+        AABB::of_grid(&Grid {
+            resolution: 0.0,
+            ..grid
+        }),
+        None
+    );
+}
@@ -0,0 +1,290 @@
+﻿use super::TopicContent;
+use std::{cmp::Reverse, collections::BinaryHeap, fmt, time::Duration};
+
+/// 缺省预算 1 GiB，正常使用时不会触及，防止出错的生产者耗尽内存
+const DEFAULT_LIMIT: usize = 1 << 30;
+
+/// 超出预算时的消除策略
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Eviction {
+    Oldest,       // 先消除所有话题中最旧的顶点
+    Proportional, // 按容量比例分配预算，各话题消除超出份额的旧顶点
+}
+
+/// 全局内存预算，限制所有话题估算占用的内存之和
+///
+/// 队列、实体、边和栅格都计入预算。先按策略消除队列中的旧顶点，
+/// 仍然超出时从大到小删除栅格，再删除最久没有更新的实体。
+pub(crate) struct Budget {
+    pub limit: usize,       // 内存的上限（字节），0 表示不限
+    pub eviction: Eviction, // 消除策略
+    evicted: usize,         // 累计消除的顶点、栅格和实体数
+}
+
+/// 话题的用量
+pub(crate) struct Usage {
+    pub bytes: usize,                // 估算占用的内存（字节）
+    pub vertex: usize,               // 队列中的顶点数
+    pub capacity: usize,             // 容量
+    pub entities: usize,             // 实体数
+    pub cells: usize,                // 栅格单元数
+    pub retention: Option<Duration>, // 保留时长
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Self {
+            limit: DEFAULT_LIMIT,
+            eviction: Eviction::Oldest,
+            evicted: 0,
+        }
+    }
+}
+
+impl Budget {
+    /// 累计消除的顶点、栅格和实体数
+    #[inline]
+    pub fn evicted(&self) -> usize {
+        self.evicted
+    }
+
+    /// 按预算消除，返回本次消除的顶点、栅格和实体数
+    pub fn enforce<'a>(&mut self, topics: impl Iterator<Item = &'a mut TopicContent>) -> usize {
+        if self.limit == 0 {
+            return 0;
+        }
+        let mut topics = topics.collect::<Vec<_>>();
+        let total = topics.iter().map(|t| t.memory()).sum::<usize>();
+        if total <= self.limit {
+            return 0;
+        }
+        let counts = match self.eviction {
+            Eviction::Oldest => oldest(&topics, total - self.limit),
+            Eviction::Proportional => proportional(&topics, self.limit),
+        };
+        let mut sum = 0;
+        for (topic, n) in topics.iter_mut().zip(counts) {
+            if n > 0 {
+                topic.evict(n);
+                sum += n;
+            }
+        }
+        // 顶点不够消除时删除栅格和实体
+        let total = topics.iter().map(|t| t.memory()).sum::<usize>();
+        if total > self.limit {
+            sum += shed(&mut topics, total - self.limit);
+        }
+        if self.evicted == 0 && sum > 0 {
+            eprintln!(
+                "memory budget {} bytes exceeded, evicting {:?} data; type `usage` for details",
+                self.limit, self.eviction
+            );
+        }
+        self.evicted += sum;
+        sum
+    }
+}
+
+/// 依次消除所有话题中最旧的顶点，直到释放 `excess` 字节，得到各话题消除的数量
+fn oldest(topics: &[&mut TopicContent], excess: usize) -> Vec<usize> {
+    let mut counts = vec![0; topics.len()];
+    let mut heap = topics
+        .iter()
+        .enumerate()
+        .filter_map(|(i, t)| Some(Reverse((t.nth_oldest(0)?, i))))
+        .collect::<BinaryHeap<_>>();
+    let mut freed = 0;
+    while freed < excess {
+        let (size, i) = match heap.pop() {
+            Some(Reverse(((_, size), i))) => (size, i),
+            None => break,
+        };
+        freed += size;
+        counts[i] += 1;
+        if let Some(next) = topics[i].nth_oldest(counts[i]) {
+            heap.push(Reverse((next, i)));
+        }
+    }
+    counts
+}
+
+/// 按容量比例分配预算，得到各话题为回到份额之内要消除的数量，总容量为 0 时份额都是 0
+fn proportional(topics: &[&mut TopicContent], limit: usize) -> Vec<usize> {
+    let capacity = topics.iter().map(|t| t.capacity() as f64).sum::<f64>();
+    topics
+        .iter()
+        .map(|t| {
+            let share = if capacity > 0.0 {
+                (limit as f64 * t.capacity() as f64 / capacity) as usize
+            } else {
+                0
+            };
+            let excess = t.memory().saturating_sub(share);
+            let mut freed = 0;
+            (0..)
+                .map_while(|n| t.nth_oldest(n))
+                .take_while(|(_, size)| {
+                    let done = freed >= excess;
+                    freed += size;
+                    !done
+                })
+                .count()
+        })
+        .collect()
+}
+
+/// 从大到小删除栅格，再删除所有话题中最久没有更新的实体，直到释放 `excess` 字节，返回删除的数量
+fn shed(topics: &mut [&mut TopicContent], excess: usize) -> usize {
+    let mut freed = 0;
+    let mut count = 0;
+    let mut rasters = topics
+        .iter()
+        .enumerate()
+        .map(|(i, t)| (t.raster_memory(), i))
+        .filter(|(size, _)| *size > 0)
+        .collect::<Vec<_>>();
+    rasters.sort_unstable_by(|a, b| b.cmp(a));
+    for (size, i) in rasters {
+        if freed >= excess {
+            return count;
+        }
+        topics[i].drop_raster();
+        freed += size;
+        count += 1;
+    }
+    let mut entities = topics
+        .iter()
+        .enumerate()
+        .flat_map(|(i, t)| {
+            t.entity_ages()
+                .map(move |(time, size, id)| (time, i, id, size))
+        })
+        .collect::<Vec<_>>();
+    entities.sort_unstable();
+    let mut removed = vec![Vec::new(); topics.len()];
+    for (_, i, id, size) in entities {
+        if freed >= excess {
+            break;
+        }
+        removed[i].push(id);
+        freed += size;
+    }
+    for (topic, ids) in topics.iter_mut().zip(removed) {
+        if !ids.is_empty() {
+            count += ids.len();
+            topic.remove_entities(ids);
+        }
+    }
+    count
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes, {}/{} vertices, {} entities, {} cells",
+            self.bytes, self.vertex, self.capacity, self.entities, self.cells
+        )?;
+        if let Some(retention) = self.retention {
+            write!(f, ", retention {:?}", retention)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn evict_by_budget() {
+    use super::content::Entry;
+    use crate::{
+        protocol::{EntityCommand, Grid, Tile},
+        vertex, Label,
+    };
+    use std::{mem::size_of, time::Instant};
+
+    // 不带附件的顶点占用的内存
+    const E: usize = size_of::<Entry>();
+
+    // 话题 0 的顶点较旧，话题 1 的容量是话题 0 的 3 倍
+    let time = Instant::now();
+    let topics = || {
+        [(1000, 0), (3000, 1)]
+            .into_iter()
+            .map(|(capacity, age)| {
+                let mut topic = TopicContent::default();
+                topic.set_capacity(capacity);
+                for i in 0..100 {
+                    let t = time - Duration::from_millis(200 * (1 - age) + 100 - i);
+                    topic.extend(t, [(vertex!(0; i, 0; 0), None)]);
+                }
+                topic
+            })
+            .collect::<Vec<_>>()
+    };
+    // 缺省预算足够大
+    let mut oldest = topics();
+    assert_eq!(Budget::default().enforce(oldest.iter_mut()), 0);
+    // 先消除最旧的
+    let mut budget = Budget {
+        limit: 150 * E,
+        ..Default::default()
+    };
+    assert_eq!(budget.enforce(oldest.iter_mut()), 50);
+    assert_eq!(oldest[0].usage().vertex, 50);
+    assert_eq!(oldest[1].usage().vertex, 100);
+    assert_eq!(budget.enforce(oldest.iter_mut()), 0);
+    assert_eq!(budget.evicted(), 50);
+    // 实体及其附件也计入预算
+    let label = Label::screen("x".repeat(10 * E), 12.0);
+    oldest[1].update_entity(
+        time,
+        EntityCommand::Put {
+            id: 0,
+            vertex: vertex!(0; 0, 0; Text, 0.0; 0),
+            attachment: Some(label.into()),
+        },
+    );
+    let excess = oldest.iter().map(|t| t.memory()).sum::<usize>() - budget.limit;
+    assert!(excess > 10 * E);
+    let evicted = budget.enforce(oldest.iter_mut());
+    assert_eq!(evicted, excess.div_ceil(E));
+    assert_eq!(oldest[0].usage().vertex, 50 - evicted);
+    // 按容量比例
+    let mut proportional = topics();
+    budget.limit = 100 * E;
+    budget.eviction = Eviction::Proportional;
+    assert_eq!(budget.enforce(proportional.iter_mut()), 100);
+    assert_eq!(proportional[0].usage().vertex, 25);
+    assert_eq!(proportional[1].usage().vertex, 75);
+    // 没有顶点可消除时先删除栅格，再删除最久没有更新的实体
+    let mut zero = topics();
+    for (i, topic) in zero.iter_mut().enumerate() {
+        topic.set_capacity(0);
+        topic.update_entity(
+            time + Duration::from_millis(i as u64),
+            EntityCommand::Put {
+                id: 0,
+                vertex: vertex!(0; 0, 0; 0),
+                attachment: None,
+            },
+        );
+    }
+    let grid = Grid {
+        origin: [0.0, 0.0],
+        resolution: 1.0,
+        width: 10,
+        height: 10,
+    };
+    zero[0].put_tile(Tile::whole(grid, vec![], vec![0u8; 100]));
+    budget.limit = zero[0].memory() + zero[1].memory() - 100;
+    assert_eq!(budget.enforce(zero.iter_mut()), 1);
+    assert_eq!(zero[0].usage().cells, 0);
+    budget.limit -= 1;
+    assert_eq!(budget.enforce(zero.iter_mut()), 1);
+    assert_eq!(zero[0].usage().entities, 0);
+    assert_eq!(zero[1].usage().entities, 1);
+    assert_eq!(budget.enforce(zero.iter_mut()), 0);
+    // 不限时不消除
+    budget.limit = 0;
+    assert_eq!(budget.enforce(oldest.iter_mut()), 0);
+    assert_eq!(budget.evicted(), 150 + evicted + 2);
+}
@@ -1,23 +1,60 @@
-﻿use crate::Vertex;
+﻿use super::Raster;
+use crate::{
+    protocol::{Attachment, Style},
+    Vertex,
+};
 use iced::Color;
 use std::{
-    collections::{HashMap, VecDeque},
-    time::Instant,
+    collections::{BTreeMap, HashMap, VecDeque},
+    mem::size_of,
+    time::{Duration, Instant},
 };
 
+/// 队列中的一项：数据时间（有源时间时换算到本地时钟，否则为到达时间）、顶点、附件
+pub(crate) type Entry = (Instant, Vertex, Option<Box<Attachment>>);
+
+/// 估算一项占用的内存（字节），包括附件及其中的文字和多边形的点
+pub(crate) fn entry_size((_, _, attachment): &Entry) -> usize {
+    let attachment = attachment.as_deref().map_or(0, |a| {
+        size_of::<Attachment>()
+            + match a {
+                Attachment::Text(label) => label.text.len(),
+                Attachment::Polygon(polygon) => polygon.points.len() * size_of::<[f32; 2]>(),
+                _ => 0,
+            }
+    });
+    size_of::<Entry>() + attachment
+}
+
+/// 估算一个实体占用的内存（字节），包括编号
+#[inline]
+pub(crate) fn entity_size(entry: &Entry) -> usize {
+    size_of::<u32>() + entry_size(entry)
+}
+
 #[derive(Clone)]
 pub(crate) struct TopicBuffer {
-    pub capacity: usize,                    // 缓存容量
-    pub queue: VecDeque<(Instant, Vertex)>, // 点数据
-    pub color_map: HashMap<u8, Color>,      // 色彩映射
+    pub capacity: usize,                       // 缓存容量
+    pub retention: Option<Duration>,           // 保留时长，`None` 表示不限
+    pub queue: VecDeque<Entry>,                // 点数据
+    pub entities: BTreeMap<u32, Entry>,        // 实体，时间为最后更新时间
+    pub edges: BTreeMap<(u32, u32), (u8, u8)>, // 实体之间的边：级别、透明度
+    pub color_map: HashMap<u8, Color>,         // 色彩映射
+    pub style_map: HashMap<u8, Style>,         // 样式映射
+    pub raster: Option<Raster>,                // 栅格
 }
 
 impl Default for TopicBuffer {
     fn default() -> Self {
         Self {
             capacity: 2000,
+            retention: None,
             queue: Default::default(),
+            entities: Default::default(),
+            edges: Default::default(),
             color_map: Default::default(),
+            style_map: Default::default(),
+            raster: None,
         }
     }
 }
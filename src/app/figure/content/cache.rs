@@ -1,10 +1,14 @@
-﻿use crate::Vertex;
+﻿use crate::{
+    protocol::{Attachment, Marker, Style},
+    Vertex,
+};
 
-use super::{FigureItem, Items, AABB};
+use super::{items::Tie, FigureItem, AABB};
 use iced::{
-    canvas::{Cache, Geometry, Path, Stroke},
-    Color, Point, Size, Vector,
+    canvas::{Cache, Frame, Geometry, Path, Stroke, Text},
+    Color, HorizontalAlignment, Point, Size, Vector, VerticalAlignment,
 };
+use std::f32::consts::TAU;
 
 #[derive(Default)]
 pub(super) struct TopicCache {
@@ -13,23 +17,21 @@ pub(super) struct TopicCache {
     cache: Cache,
 }
 
-#[derive(Debug)]
+#[derive(Default, Debug)]
 enum Bound {
     Cached(AABB),
     CachedVoid,
     Suspectable(AABB),
+    #[default]
     Invalid,
 }
 
-impl Default for Bound {
-    fn default() -> Self {
-        Self::Invalid
-    }
-}
-
 impl TopicCache {
     /// 计算范围
-    pub fn aabb(&mut self, iter: impl Iterator<Item = Vertex>) -> Option<AABB> {
+    pub fn aabb<'a>(
+        &mut self,
+        iter: impl Iterator<Item = (&'a Vertex, Option<&'a Attachment>)>,
+    ) -> Option<AABB> {
         match self.bound {
             Bound::Cached(aabb) => Some(aabb),
             Bound::CachedVoid => None,
@@ -46,78 +48,144 @@ impl TopicCache {
                     None
                 }
             }
-            Bound::Invalid => AABB::foreach_vertex(iter.take(self.focus_len)).map(|aabb| {
-                self.bound = Bound::Cached(aabb);
+            Bound::Invalid => AABB::foreach_vertex(iter.take(self.focus_len)).inspect(|aabb| {
+                self.bound = Bound::Cached(*aabb);
                 self.redraw();
-                aabb
             }),
         }
     }
 
     /// 画图
-    pub fn draw(&mut self, items: Items, size: Size, scale: f32) -> Geometry {
+    pub fn draw<'a>(
+        &mut self,
+        items: impl Iterator<Item = (Option<Tie<'a>>, FigureItem<'a>, &'a Style)>,
+        size: Size,
+        scale: f32,
+    ) -> Geometry {
         const MASS: usize = 2000;
-        const WIDTH: f32 = 1.5;
-        const D: f32 = 3.5;
 
         let items = items.collect::<Vec<_>>();
         let mass = items.len() > MASS;
-        let d = if mass { WIDTH } else { D } / scale;
-        let len_arrow = 15.0 / scale;
-        let offset = Vector { x: d, y: d } * -0.5;
 
         self.cache.draw(size, |frame| {
             frame.translate(frame.center() - Point::ORIGIN);
             frame.scale(scale);
 
-            let size = Size {
-                width: d,
-                height: d,
-            };
-            let mut stroke = Stroke {
-                width: WIDTH,
-                ..Default::default()
-            };
             let min = 0.1 / scale.powi(2);
-            for (tie, item) in items.iter().copied() {
+            for (tie, item, style) in items.iter().copied() {
+                let p = item.pos();
+                let tied = draw_tie(tie, mass, p, min, |p0, color, style| {
+                    stroke_polyline(frame, &[p0, p], stroke(color, style), dash(style, scale));
+                });
+                // 大规模时点缩小到线宽
+                let d = if mass {
+                    style.line_width
+                } else {
+                    style.marker_size
+                } / scale;
                 match item {
-                    FigureItem::End(p) => {
-                        draw_tie(tie, mass, p, min, |p0, color| {
-                            stroke.color = color;
-                            frame.stroke(&Path::line(p0, p), stroke);
-                        });
-                    }
+                    FigureItem::End(_) => {}
                     FigureItem::Point(p, color) => {
-                        let tied = draw_tie(tie, mass, p, min, |p0, color| {
-                            stroke.color = color;
-                            frame.stroke(&Path::line(p0, p), stroke);
-                        });
                         // 小规模时一定画点
                         // 大规模时如果连了线就不画点
                         if !mass || !tied {
-                            frame.fill_rectangle(p + offset, size, color);
+                            mark(frame, p, style, d, color);
                         }
                     }
-                    FigureItem::Arrow(p, d, color) => {
-                        draw_tie(tie, mass, p, min, |p0, color| {
-                            stroke.color = color;
-                            frame.stroke(&Path::line(p0, p), stroke);
-                        });
+                    FigureItem::Arrow(p, dir, color) => {
                         if !mass {
-                            frame.fill_rectangle(p + offset, size, color)
+                            mark(frame, p, style, d, color);
                         }
-                        let (sin, cos) = d.sin_cos();
-                        let d = Vector { x: cos, y: sin } * len_arrow;
-                        stroke.color = color;
-                        frame.stroke(&Path::line(p, p + d), stroke);
+                        let len = if style.arrow_world {
+                            style.arrow_length
+                        } else {
+                            style.arrow_length / scale
+                        };
+                        let (sin, cos) = dir.sin_cos();
+                        let v = Vector { x: cos, y: sin } * len;
+                        frame.stroke(&Path::line(p, p + v), stroke(color, style));
                     }
-                    FigureItem::Circle(c, r, color) => {
-                        draw_tie(tie, mass, c, min, |p0, color| {
-                            stroke.color = color;
-                            frame.stroke(&Path::line(p0, c), stroke);
+                    FigureItem::Circle(c, r, color) => match dash(style, scale) {
+                        Some(dash) => {
+                            let points = (0..=CIRCLE_SEGMENTS).map(|i| {
+                                let (sin, cos) =
+                                    (i as f32 * TAU / CIRCLE_SEGMENTS as f32).sin_cos();
+                                c + Vector { x: cos, y: sin } * r
+                            });
+                            let points = points.collect::<Vec<_>>();
+                            stroke_polyline(frame, &points, stroke(color, style), Some(dash));
+                        }
+                        None => frame.stroke(&Path::circle(c, r), stroke(color, style)),
+                    },
+                    FigureItem::Text(p, label, color) => {
+                        // 字号不随变换缩放，世界单位需要换算成像素
+                        let (column, row) = label.anchor.grid();
+                        frame.fill_text(Text {
+                            content: label.text.clone(),
+                            position: p,
+                            color,
+                            size: if label.world {
+                                label.size * scale
+                            } else {
+                                label.size
+                            },
+                            horizontal_alignment: [
+                                HorizontalAlignment::Left,
+                                HorizontalAlignment::Center,
+                                HorizontalAlignment::Right,
+                            ][column as usize],
+                            vertical_alignment: [
+                                VerticalAlignment::Top,
+                                VerticalAlignment::Center,
+                                VerticalAlignment::Bottom,
+                            ][row as usize],
+                            ..Default::default()
                         });
-                        stroke.color = color;
-                        frame.stroke(&Path::circle(c, r), stroke);
+                    }
+                    FigureItem::Rect(p, yaw, rect, color) => {
+                        let points = outline(p, yaw, rect.corners());
+                        draw_outline(frame, &points, rect.fill, color, style, scale);
+                    }
+                    FigureItem::Polygon(p, yaw, polygon, color) => {
+                        let points = outline(p, yaw, polygon.points.iter().copied());
+                        draw_outline(frame, &points, polygon.fill, color, style, scale);
+                    }
+                    FigureItem::Ellipse(p, yaw, ellipse, color) => {
+                        let points = (0..CIRCLE_SEGMENTS).map(|i| {
+                            let (sin, cos) = (i as f32 * TAU / CIRCLE_SEGMENTS as f32).sin_cos();
+                            [ellipse.a * cos, ellipse.b * sin]
+                        });
+                        let points = outline(p, yaw, points);
+                        draw_outline(frame, &points, ellipse.fill, color, style, scale);
+                    }
+                    FigureItem::Vector(p, v, color) => {
+                        let len = v.x.hypot(v.y);
+                        if len > 0.0 {
+                            // 大规模时只画线段
+                            let head = if mass {
+                                0.0
+                            } else {
+                                (0.3 * len).min(4.0 * style.marker_size / scale)
+                            };
+                            let back = v * (head / len);
+                            let tip = p + v;
+                            frame.stroke(&Path::line(p, tip - back), stroke(color, style));
+                            if head > 0.0 {
+                                let side = Vector {
+                                    x: -back.y,
+                                    y: back.x,
+                                } * 0.5;
+                                let head = Path::new(|b| {
+                                    b.move_to(tip);
+                                    b.line_to(tip - back + side);
+                                    b.line_to(tip - back - side);
+                                    b.close();
+                                });
+                                frame.fill(&head, color);
+                            }
+                        } else if !mass || !tied {
+                            mark(frame, p, style, d, color);
+                        }
                     }
                 }
             }
@@ -153,20 +221,167 @@ impl TopicCache {
     }
 }
 
+/// 虚线化的圆和椭圆分成的段数
+const CIRCLE_SEGMENTS: usize = 64;
+/// 一条线段最多分成的虚线段数，超过时画实线
+const MAX_DASHES: f32 = 10000.0;
+
+impl FigureItem<'_> {
+    /// 绘图对象的位置，也是连线的终点
+    #[inline]
+    fn pos(&self) -> Point {
+        match *self {
+            Self::End(p)
+            | Self::Point(p, _)
+            | Self::Arrow(p, _, _)
+            | Self::Circle(p, _, _)
+            | Self::Text(p, _, _)
+            | Self::Rect(p, _, _, _)
+            | Self::Polygon(p, _, _, _)
+            | Self::Ellipse(p, _, _, _)
+            | Self::Vector(p, _, _) => p,
+        }
+    }
+}
+
+/// 按样式的线宽描边
+#[inline]
+fn stroke(color: Color, style: &Style) -> Stroke {
+    Stroke {
+        color,
+        width: style.line_width,
+        ..Default::default()
+    }
+}
+
+/// 换算到画布坐标的虚线实段和空段长度，实线返回 `None`
+#[inline]
+fn dash(style: &Style, scale: f32) -> Option<[f32; 2]> {
+    if style.is_dashed() {
+        Some([style.dash[0] / scale, style.dash[1] / scale])
+    } else {
+        None
+    }
+}
+
+/// 画点，`d` 为画布坐标下的大小
+fn mark(frame: &mut Frame, p: Point, style: &Style, d: f32, color: Color) {
+    match style.marker {
+        Marker::Square => {
+            let size = Size {
+                width: d,
+                height: d,
+            };
+            frame.fill_rectangle(p + Vector { x: d, y: d } * -0.5, size, color);
+        }
+        Marker::Dot => frame.fill(&Path::circle(p, d / 2.0), color),
+        Marker::Cross => {
+            let h = d / 2.0;
+            let path = Path::new(|builder| {
+                builder.move_to(p + Vector { x: -h, y: -h });
+                builder.line_to(p + Vector { x: h, y: h });
+                builder.move_to(p + Vector { x: -h, y: h });
+                builder.line_to(p + Vector { x: h, y: -h });
+            });
+            frame.stroke(&path, stroke(color, style));
+        }
+    }
+}
+
+/// 描一条折线，虚线的相位在各段之间连续
+fn stroke_polyline(frame: &mut Frame, points: &[Point], stroke: Stroke, dash: Option<[f32; 2]>) {
+    let length = |p0: Point, p1: Point| (p1 - p0).x.hypot((p1 - p0).y);
+    let total = points.windows(2).map(|w| length(w[0], w[1])).sum::<f32>();
+    let [on, off] = match dash {
+        Some(dash) if total / (dash[0] + dash[1]) <= MAX_DASHES => dash,
+        _ => {
+            let path = Path::new(|builder| {
+                if let Some((first, rest)) = points.split_first() {
+                    builder.move_to(*first);
+                    rest.iter().for_each(|p| builder.line_to(*p));
+                }
+            });
+            return frame.stroke(&path, stroke);
+        }
+    };
+    // 第 k 个实段占据折线上 [k * period, k * period + on]
+    let period = on + off;
+    let path = Path::new(|builder| {
+        let mut s0 = 0.0;
+        for w in points.windows(2) {
+            let len = length(w[0], w[1]);
+            let s1 = s0 + len;
+            let at = |s: f32| w[0] + (w[1] - w[0]) * ((s - s0) / len);
+            let mut k = (s0 / period).floor();
+            while k * period < s1 {
+                let begin = (k * period).max(s0);
+                let end = (k * period + on).min(s1);
+                if begin < end {
+                    builder.move_to(at(begin));
+                    builder.line_to(at(end));
+                }
+                k += 1.0;
+            }
+            s0 = s1;
+        }
+    });
+    frame.stroke(&path, stroke);
+}
+
+/// 以 `p` 为原点、转过 `yaw` 的闭合轮廓，各点的 y 轴向上，首点在末尾重复
+fn outline(p: Point, yaw: f32, points: impl IntoIterator<Item = [f32; 2]>) -> Vec<Point> {
+    let (sin, cos) = yaw.sin_cos();
+    let mut points = points
+        .into_iter()
+        .map(|[x, y]| {
+            let y = -y;
+            p + Vector {
+                x: x * cos - y * sin,
+                y: x * sin + y * cos,
+            }
+        })
+        .collect::<Vec<_>>();
+    if let Some(first) = points.first().copied() {
+        points.push(first);
+    }
+    points
+}
+
+/// 按填充透明度填充轮廓（0 表示不填充），再按样式描边
+fn draw_outline(
+    frame: &mut Frame,
+    points: &[Point],
+    fill: u8,
+    color: Color,
+    style: &Style,
+    scale: f32,
+) {
+    if fill > 0 && points.len() > 2 {
+        let path = Path::new(|builder| {
+            builder.move_to(points[0]);
+            points[1..].iter().for_each(|p| builder.line_to(*p));
+            builder.close();
+        });
+        let a = color.a * fill as f32 / 255.0;
+        frame.fill(&path, Color { a, ..color });
+    }
+    stroke_polyline(frame, points, stroke(color, style), dash(style, scale));
+}
+
 fn draw_tie(
-    tie: Option<(Point, Color)>,
+    tie: Option<Tie>,
     mass: bool,
     p: Point,
     min: f32,
-    f: impl FnOnce(Point, Color),
+    f: impl FnOnce(Point, Color, &Style),
 ) -> bool {
-    if let Some((p0, color)) = tie {
+    if let Some((p0, color, style)) = tie {
         let tied = mass || {
             let v = p0 - p;
             (v.x.powi(2) + v.y.powi(2)) > min
         };
         if tied {
-            f(p0, color);
+            f(p0, color, style);
         }
         tied
     } else {
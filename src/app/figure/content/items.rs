@@ -1,86 +1,233 @@
-﻿use super::{super::convert, FigureItem, Vertex, AABB};
+﻿use super::{
+    super::{aabb::yaw, convert},
+    Attachment, Entry, FigureItem, Style, AABB,
+};
 use crate::Shape::*;
 use iced::{Color, Point};
-use std::{
-    collections::{vec_deque::Iter, HashMap, VecDeque},
-    time::Instant,
-};
+use std::collections::{BTreeMap, HashMap};
 
 /// 产生绘图对象的迭代器
-pub(super) struct Items<'a> {
-    memory: Option<TieMemory>,
+pub(super) struct Items<'a, I> {
+    memory: Option<TieMemory<'a>>,
+    tied: bool,
     center: Point,
     aabb: AABB,
-    iter: Iter<'a, (Instant, Vertex)>,
+    iter: I,
     color_map: &'a mut HashMap<u8, Color>,
+    style_map: &'a HashMap<u8, Style>,
 }
 
-struct TieMemory {
+/// 连线的起点、颜色和样式
+pub(super) type Tie<'a> = (Point, Color, &'a Style);
+
+struct TieMemory<'a> {
     pos: Point,
     inside: bool,
     color: Color,
+    style: &'a Style,
 }
 
-impl<'a> Items<'a> {
+impl<'a, I: Iterator<Item = &'a Entry>> Items<'a, I> {
     pub fn new(
-        queue: &'a VecDeque<(Instant, Vertex)>,
+        iter: I,
         color_map: &'a mut HashMap<u8, Color>,
+        style_map: &'a HashMap<u8, Style>,
         center: Point,
         aabb: AABB,
-    ) -> Option<Self> {
-        if queue.is_empty() {
+    ) -> Option<Self>
+    where
+        I: ExactSizeIterator,
+    {
+        if iter.len() == 0 {
             None
         } else {
             Some(Items {
                 memory: None,
+                tied: true,
                 center,
                 aabb,
-                iter: queue.iter(),
+                iter,
                 color_map,
+                style_map,
             })
         }
     }
 
+    /// 各项之间不连线
+    #[inline]
+    pub fn untied(self) -> Self {
+        Self {
+            tied: false,
+            ..self
+        }
+    }
+
     #[inline]
     fn find_color(&mut self, level: u8) -> Color {
         *self.color_map.entry(level).or_insert(Color::BLACK)
     }
+
+    #[inline]
+    fn find_style(&self, level: u8) -> &'a Style {
+        self.style_map.get(&level).unwrap_or(&Style::DEFAULT)
+    }
 }
 
-impl<'a> Iterator for Items<'a> {
-    type Item = (Option<(Point, Color)>, FigureItem);
+impl<'a, I: Iterator<Item = &'a Entry>> Iterator for Items<'a, I> {
+    type Item = (Option<Tie<'a>>, FigureItem<'a>, &'a Style);
 
     fn next(&mut self) -> Option<Self::Item> {
-        while let Some((_, v)) = self.iter.next() {
-            let inside = self.aabb.contains(v);
+        while let Some((_, v, a)) = self.iter.next() {
+            let a = a.as_deref();
+            let inside = self.aabb.contains(v, a);
             let pos = convert(Point { x: v.x, y: v.y }, self.center);
             let tie = self.memory.take();
-            if v.alpha > 0 {
+            let style = self.find_style(v.level);
+            if self.tied && v.alpha > 0 {
                 let mut color = self.find_color(v.level);
                 color.a *= v.alpha as f32 / 255.0;
-                self.memory = Some(TieMemory { pos, inside, color });
+                self.memory = Some(TieMemory {
+                    pos,
+                    inside,
+                    color,
+                    style,
+                });
             }
             if inside {
                 let color = self.find_color(v.level);
-                let tie = tie.map(|mem| (mem.pos, mem.color));
-                match v.shape {
-                    Arrow => {
-                        if v.extra.is_finite() {
-                            return Some((tie, FigureItem::Arrow(pos, -v.extra, color)));
-                        } else {
-                            return Some((tie, FigureItem::Point(pos, color)));
+                let tie = tie.map(|mem| (mem.pos, mem.color, mem.style));
+                let item = match v.shape {
+                    Arrow if v.extra.is_finite() => Some(FigureItem::Arrow(pos, -v.extra, color)),
+                    Arrow => Some(FigureItem::Point(pos, color)),
+                    Circle if v.extra.is_normal() => Some(FigureItem::Circle(pos, v.extra, color)),
+                    Circle => None,
+                    Text => match a {
+                        Some(Attachment::Text(label)) => Some(FigureItem::Text(pos, label, color)),
+                        _ => None,
+                    },
+                    Rect | OrientedBox => match a {
+                        Some(Attachment::Rect(rect)) => {
+                            Some(FigureItem::Rect(pos, -yaw(v), rect, color))
+                        }
+                        _ => None,
+                    },
+                    Polygon => match a {
+                        Some(Attachment::Polygon(polygon)) => {
+                            Some(FigureItem::Polygon(pos, -yaw(v), polygon, color))
+                        }
+                        _ => None,
+                    },
+                    Ellipse => match a {
+                        Some(Attachment::Ellipse(ellipse)) => {
+                            Some(FigureItem::Ellipse(pos, -yaw(v), ellipse, color))
                         }
-                    }
-                    Circle => {
-                        if v.extra.is_normal() {
-                            return Some((tie, FigureItem::Circle(pos, v.extra, color)));
+                        _ => None,
+                    },
+                    Vector => match a {
+                        Some(Attachment::Vector(vector))
+                            if vector.dx.is_finite() && vector.dy.is_finite() =>
+                        {
+                            let color = style
+                                .magnitude_ratio(vector.magnitude())
+                                .map_or(color, |t| ramp(t, color.a));
+                            let d = iced::Vector {
+                                x: vector.dx,
+                                y: -vector.dy,
+                            } * style.vector_scale;
+                            Some(FigureItem::Vector(pos, d, color))
                         }
-                    }
+                        _ => None,
+                    },
+                };
+                if let Some(item) = item {
+                    return Some((tie, item, style));
                 }
-            } else if let Some(tie) = tie.filter(|mem| mem.inside).map(|mem| (mem.pos, mem.color)) {
-                return Some((Some(tie), FigureItem::End(pos)));
+            } else if let Some(tie) = tie
+                .filter(|mem| mem.inside)
+                .map(|mem| (mem.pos, mem.color, mem.style))
+            {
+                return Some((Some(tie), FigureItem::End(pos), style));
             }
         }
         None
     }
 }
+
+/// 实体之间的边，两端都存在且线段与视野相交时画出，即使两端都在视野之外
+///
+/// 每条边表示为从起点连到终点的 [`FigureItem::End`]。
+pub(super) fn edges<'a>(
+    entities: &BTreeMap<u32, Entry>,
+    edges: &BTreeMap<(u32, u32), (u8, u8)>,
+    color_map: &mut HashMap<u8, Color>,
+    style_map: &'a HashMap<u8, Style>,
+    center: Point,
+    aabb: AABB,
+) -> Vec<(Option<Tie<'a>>, FigureItem<'a>, &'a Style)> {
+    let mut items = Vec::new();
+    for ((from, to), (level, alpha)) in edges {
+        let (Some((_, v0, _)), Some((_, v1, _))) = (entities.get(from), entities.get(to)) else {
+            continue;
+        };
+        let p0 = Point { x: v0.x, y: v0.y };
+        let p1 = Point { x: v1.x, y: v1.y };
+        if *alpha == 0 || !AABB::foreach([p0, p1]).is_some_and(|it| it.intersect(aabb)) {
+            continue;
+        }
+        let mut color = *color_map.entry(*level).or_insert(Color::BLACK);
+        color.a *= *alpha as f32 / 255.0;
+        let style = style_map.get(level).unwrap_or(&Style::DEFAULT);
+        let tie = (convert(p0, center), color, style);
+        items.push((Some(tie), FigureItem::End(convert(p1, center)), style));
+    }
+    items
+}
+
+/// 按模长着色的色带，0 为蓝，经青、绿、黄到 1 为红
+#[inline]
+fn ramp(t: f32, alpha: f32) -> Color {
+    let channel = |center: f32| (1.5 - (4.0 * t - center).abs()).clamp(0.0, 1.0);
+    Color {
+        r: channel(3.0),
+        g: channel(2.0),
+        b: channel(1.0),
+        a: alpha,
+    }
+}
+
+#[test]
+fn test_edges() {
+    use crate::vertex;
+    use std::time::Instant;
+
+    let now = Instant::now();
+    let entities = [(0, -10, 0), (1, 10, 0), (2, 10, 20), (3, 20, 20)]
+        .into_iter()
+        .map(|(id, x, y)| (id, (now, vertex!(0; x, y; 0), None)))
+        .collect::<BTreeMap<_, _>>();
+    // 穿过视野的边、缺少端点的边、在视野之外的边、透明的边
+    let map = [
+        ((0, 1), (1, 255)),
+        ((0, 9), (1, 255)),
+        ((2, 3), (1, 255)),
+        ((1, 0), (1, 0)),
+    ]
+    .into_iter()
+    .collect::<BTreeMap<_, _>>();
+    let mut color_map = HashMap::from([(1, Color::WHITE)]);
+    let style_map = HashMap::new();
+    let aabb = AABB::foreach([Point { x: -1.0, y: -1.0 }, Point { x: 1.0, y: 1.0 }]).unwrap();
+    let items = edges(
+        &entities,
+        &map,
+        &mut color_map,
+        &style_map,
+        Point::ORIGIN,
+        aabb,
+    );
+    assert_eq!(items.len(), 1);
+    let (tie, item, _) = items[0];
+    let (p0, color, _) = tie.unwrap();
+    assert_eq!((p0, color), (Point { x: -10.0, y: 0.0 }, Color::WHITE));
+    assert!(matches!(item, FigureItem::End(p) if p == Point { x: 10.0, y: 0.0 }));
+}
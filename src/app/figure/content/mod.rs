@@ -1,36 +1,58 @@
-﻿use super::{aabb::AABB, View};
-use crate::Vertex;
-use iced::{canvas::Geometry, Color, Point};
-use std::time::Instant;
+﻿use super::{aabb::AABB, budget::Usage, View};
+use crate::{
+    protocol::{Attachment, Ellipse, EntityCommand, Label, Polygon, Rect, Style, Tile},
+    Vertex,
+};
+use iced::{
+    canvas::{Cache, Geometry},
+    Color, Point, Vector,
+};
+use std::{
+    mem::size_of,
+    time::{Duration, Instant},
+};
 
 mod buffer;
 mod cache;
 mod items;
+mod raster;
 
-pub(crate) use buffer::TopicBuffer;
+pub(crate) use buffer::{entity_size, entry_size, Entry, TopicBuffer};
 use cache::TopicCache;
-use items::Items;
+use items::{edges, Items};
+pub(crate) use raster::Raster;
 
 #[derive(Default)]
 pub(crate) struct TopicContent {
-    pub layer: String,   // 图层
-    buffer: TopicBuffer, // 话题的数据缓存
-    cache: TopicCache,   // 话题的图形缓存
+    pub layer: String,        // 图层
+    buffer: TopicBuffer,      // 话题的数据缓存
+    cache: TopicCache,        // 话题的图形缓存
+    entity_cache: TopicCache, // 实体的图形缓存，实体更新时顶点不必重画
+    raster_cache: Cache,      // 栅格的图形缓存
+    queue_bytes: usize,       // 队列估算占用的内存（字节）
+    entity_bytes: usize,      // 实体估算占用的内存（字节）
 }
 
 /// 单个绘图对象
 #[derive(Clone, Copy)]
-enum FigureItem {
+enum FigureItem<'a> {
     End(Point),
     Point(Point, Color),
     Arrow(Point, f32, Color),
     Circle(Point, f32, Color),
+    Text(Point, &'a Label, Color),
+    Rect(Point, f32, &'a Rect, Color),
+    Polygon(Point, f32, &'a Polygon, Color),
+    Ellipse(Point, f32, &'a Ellipse, Color),
+    Vector(Point, Vector, Color),
 }
 
 impl From<TopicBuffer> for TopicContent {
     #[inline]
     fn from(buffer: TopicBuffer) -> Self {
         Self {
+            queue_bytes: buffer.queue.iter().map(entry_size).sum(),
+            entity_bytes: buffer.entities.values().map(entity_size).sum(),
             buffer,
             ..Default::default()
         }
@@ -48,6 +70,8 @@ impl TopicContent {
     #[inline]
     pub fn redraw(&mut self) {
         self.cache.redraw();
+        self.entity_cache.redraw();
+        self.raster_cache.clear();
     }
 
     /// 设置队列容量
@@ -61,6 +85,12 @@ impl TopicContent {
         }
     }
 
+    /// 设置保留时长，`Duration::MAX` 表示不限
+    #[inline]
+    pub fn set_retention(&mut self, retention: Duration) {
+        self.buffer.retention = Some(retention).filter(|t| *t < Duration::MAX);
+    }
+
     /// 设置关注长度
     #[inline]
     pub fn set_focus(&mut self, len: usize) {
@@ -72,64 +102,304 @@ impl TopicContent {
     pub fn set_color(&mut self, i: u8, color: Color) {
         if Some(color) != self.buffer.color_map.insert(i, color) {
             self.cache.redraw();
+            self.entity_cache.redraw();
+        }
+    }
+
+    /// 设置级别样式
+    #[inline]
+    pub fn set_style(&mut self, i: u8, style: Style) {
+        if Some(style) != self.buffer.style_map.insert(i, style) {
+            self.cache.redraw();
+            self.entity_cache.redraw();
+        }
+    }
+
+    /// 队列容量
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity
+    }
+
+    /// 队列中第 `n` 旧的顶点的时间和估算占用的内存，从 0 开始
+    #[inline]
+    pub fn nth_oldest(&self, n: usize) -> Option<(Instant, usize)> {
+        let queue = &self.buffer.queue;
+        let i = queue.len().checked_sub(n + 1)?;
+        queue.get(i).map(|entry| (entry.0, entry_size(entry)))
+    }
+
+    /// 估算占用的内存（字节），包括队列、实体、边和栅格
+    #[inline]
+    pub fn memory(&self) -> usize {
+        let edges = self.buffer.edges.len() * size_of::<((u32, u32), (u8, u8))>();
+        self.queue_bytes + self.entity_bytes + edges + self.raster_memory()
+    }
+
+    /// 栅格估算占用的内存（字节）
+    #[inline]
+    pub fn raster_memory(&self) -> usize {
+        self.buffer.raster.as_ref().map_or(0, |r| {
+            r.cells.len() * r.cells.cell_len() + r.palette.len() * size_of::<(f32, Color)>()
+        })
+    }
+
+    /// 所有实体的最后更新时间、估算占用的内存和编号
+    #[inline]
+    pub fn entity_ages(&self) -> impl Iterator<Item = (Instant, usize, u32)> + '_ {
+        self.buffer
+            .entities
+            .iter()
+            .map(|(id, entry)| (entry.0, entity_size(entry), *id))
+    }
+
+    /// 消除队列中最旧的 `n` 个顶点
+    #[inline]
+    pub fn evict(&mut self, n: usize) {
+        self.truncate(self.buffer.queue.len().saturating_sub(n));
+    }
+
+    /// 删除栅格
+    #[inline]
+    pub fn drop_raster(&mut self) {
+        if self.buffer.raster.take().is_some() {
+            self.raster_cache.clear();
+        }
+    }
+
+    /// 删除一组实体，连接它们的边一并删除
+    pub fn remove_entities(&mut self, mut ids: Vec<u32>) {
+        ids.sort_unstable();
+        for id in &ids {
+            if let Some(entry) = self.buffer.entities.remove(id) {
+                self.entity_bytes -= entity_size(&entry);
+            }
+        }
+        let gone = |id: &u32| ids.binary_search(id).is_ok();
+        self.buffer
+            .edges
+            .retain(|(from, to), _| !gone(from) && !gone(to));
+        self.entity_cache.redraw();
+    }
+
+    /// 统计用量
+    pub fn usage(&self) -> Usage {
+        Usage {
+            bytes: self.memory(),
+            vertex: self.buffer.queue.len(),
+            capacity: self.buffer.capacity,
+            entities: self.buffer.entities.len(),
+            cells: self.buffer.raster.as_ref().map_or(0, |r| r.cells.len()),
+            retention: self.buffer.retention,
         }
     }
 
     /// 获取时间范围
     #[inline]
     pub fn begin(&self) -> Option<Instant> {
-        self.buffer.queue.back().map(|(t, _)| *t)
+        self.buffer.queue.back().map(|(t, _, _)| *t)
     }
 
-    /// 计算关注范围
+    /// 计算关注范围，包括所有实体和整个栅格
     #[inline]
     pub fn aabb(&mut self) -> Option<AABB> {
-        self.cache.aabb(self.buffer.queue.iter().map(|(_, v)| *v))
+        let vertex = self
+            .cache
+            .aabb(self.buffer.queue.iter().map(|(_, v, a)| (v, a.as_deref())));
+        let entities = AABB::foreach_vertex(
+            self.buffer
+                .entities
+                .values()
+                .map(|(_, v, a)| (v, a.as_deref())),
+        );
+        let raster = self.buffer.raster.as_ref().and_then(Raster::aabb);
+        [vertex, entities, raster]
+            .into_iter()
+            .flatten()
+            .reduce(|sum, it| sum + it)
     }
 
-    /// 画图
+    /// 画图，返回栅格、顶点和实体的图形
     #[inline]
-    pub fn draw(&mut self, view: View, aabb: AABB) -> Option<Geometry> {
-        Items::new(
-            &self.buffer.queue,
+    pub fn draw(&mut self, view: View, aabb: AABB) -> [Option<Geometry>; 3] {
+        let raster = self
+            .buffer
+            .raster
+            .as_ref()
+            .and_then(|raster| raster.draw(&self.raster_cache, view, aabb));
+        let vertex = Items::new(
+            self.buffer.queue.iter(),
             &mut self.buffer.color_map,
+            &self.buffer.style_map,
             view.center,
             aabb,
         )
-        .map(|items| self.cache.draw(items, view.size, view.scale))
+        .map(|items| self.cache.draw(items, view.size, view.scale));
+        // 边画在实体之下
+        let edges = edges(
+            &self.buffer.entities,
+            &self.buffer.edges,
+            &mut self.buffer.color_map,
+            &self.buffer.style_map,
+            view.center,
+            aabb,
+        );
+        let entities = Items::new(
+            self.buffer.entities.values(),
+            &mut self.buffer.color_map,
+            &self.buffer.style_map,
+            view.center,
+            aabb,
+        );
+        let entities = if edges.is_empty() && entities.is_none() {
+            None
+        } else {
+            let items = edges
+                .into_iter()
+                .chain(entities.into_iter().flat_map(Items::untied));
+            Some(self.entity_cache.draw(items, view.size, view.scale))
+        };
+        [raster, vertex, entities]
+    }
+
+    /// 写入一块栅格，几何或值类型改变时重建栅格
+    pub fn put_tile(&mut self, tile: Tile) {
+        let raster = match &mut self.buffer.raster {
+            Some(raster) if raster.fits(&tile) => raster,
+            slot => slot.insert(Raster::new(&tile)),
+        };
+        raster.put(tile);
+        self.raster_cache.clear();
     }
 
-    /// 向队列添加一组点
-    pub fn extend_from_slice(&mut self, time: Instant, v: &[Vertex]) {
-        for v in v {
-            if let Some((t, v0)) = self.buffer.queue.front_mut() {
-                if v0 == v {
+    /// 执行实体命令，只重画实体和边
+    pub fn update_entity(&mut self, time: Instant, command: EntityCommand) {
+        let entities = &mut self.buffer.entities;
+        let changed = match command {
+            EntityCommand::Put {
+                id,
+                vertex,
+                attachment,
+            } => {
+                let entry = (time, vertex, attachment.map(Box::new));
+                self.entity_bytes += entity_size(&entry);
+                if let Some(old) = entities.insert(id, entry) {
+                    self.entity_bytes -= entity_size(&old);
+                }
+                true
+            }
+            EntityCommand::Move { id, x, y, extra } => match entities.get_mut(&id) {
+                Some((t, v, _)) => {
+                    *t = time;
+                    v.x = x;
+                    v.y = y;
+                    if !extra.is_nan() {
+                        v.extra = extra;
+                    }
+                    true
+                }
+                None => false,
+            },
+            // 连接它的边一并删除
+            EntityCommand::Remove(id) => {
+                let edges = self.buffer.edges.len();
+                self.buffer
+                    .edges
+                    .retain(|(from, to), _| *from != id && *to != id);
+                let removed = entities.remove(&id).map(|entry| entity_size(&entry));
+                self.entity_bytes -= removed.unwrap_or(0);
+                removed.is_some() || self.buffer.edges.len() < edges
+            }
+            EntityCommand::PutEdge {
+                from,
+                to,
+                level,
+                alpha,
+            } => Some((level, alpha)) != self.buffer.edges.insert((from, to), (level, alpha)),
+            EntityCommand::RemoveEdge { from, to } => {
+                self.buffer.edges.remove(&(from, to)).is_some()
+            }
+        };
+        if changed {
+            self.entity_cache.redraw();
+        }
+    }
+
+    /// 向队列添加一组点及其附件
+    ///
+    /// 时刻早于队列中最新的点时按最新的点计，保持队列按时间有序。
+    pub fn extend(
+        &mut self,
+        time: Instant,
+        items: impl IntoIterator<Item = (Vertex, Option<Attachment>)>,
+    ) {
+        let time = self
+            .buffer
+            .queue
+            .front()
+            .map_or(time, |(t, _, _)| time.max(*t));
+        for (v, a) in items {
+            if let Some((t, v0, a0)) = self.buffer.queue.front_mut() {
+                if *v0 == v && a0.as_deref() == a.as_ref() {
                     *t = time;
                     continue;
                 }
             }
             if self.buffer.queue.len() >= self.buffer.capacity {
-                self.buffer.queue.pop_back();
+                if let Some(entry) = self.buffer.queue.pop_back() {
+                    self.queue_bytes -= entry_size(&entry);
+                }
             }
-            self.buffer.queue.push_front((time, *v));
+            let entry = (time, v, a.map(Box::new));
+            self.queue_bytes += entry_size(&entry);
+            self.buffer.queue.push_front(entry);
         }
         self.cache.clear();
     }
 
-    /// 从队列移除所有点
+    /// 从队列移除所有点，并移除所有实体和栅格
     pub fn clear(&mut self) {
         self.buffer.queue.clear();
+        self.queue_bytes = 0;
+        self.buffer.entities.clear();
+        self.entity_bytes = 0;
+        self.buffer.edges.clear();
+        self.buffer.raster = None;
         self.cache.clear();
+        self.entity_cache.redraw();
+        self.raster_cache.clear();
     }
 
-    /// 依时间范围同步
+    /// 按保留时长消除过期的数据
+    #[inline]
+    pub fn expire(&mut self, time: Instant) {
+        if let Some(deadline) = self
+            .buffer
+            .retention
+            .and_then(|retention| time.checked_sub(retention))
+        {
+            self.sync(deadline);
+        }
+    }
+
+    /// 依时间范围同步，实体按最后更新时间同步，连接过期实体的边一并删除
     pub fn sync(&mut self, deadline: Instant) {
+        let expired = self
+            .buffer
+            .entities
+            .iter()
+            .filter(|(_, (t, _, _))| *t < deadline)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        if !expired.is_empty() {
+            self.remove_entities(expired);
+        }
         let to_remove = self
             .buffer
             .queue
             .iter()
             .rev()
-            .take_while(|(t, _)| t < &deadline)
+            .take_while(|(t, _, _)| t < &deadline)
             .count();
         if to_remove > 0 {
             self.truncate(self.buffer.queue.len() - to_remove);
@@ -139,6 +409,8 @@ impl TopicContent {
     /// 移除部分数据并使缓存失效
     #[inline]
     fn truncate(&mut self, len: usize) {
+        let removed = self.buffer.queue.range(len.min(self.buffer.queue.len())..);
+        self.queue_bytes -= removed.map(entry_size).sum::<usize>();
         self.buffer.queue.truncate(len);
         self.cache.clear();
     }
@@ -0,0 +1,227 @@
+﻿use super::{super::convert, View, AABB};
+use crate::protocol::{Cells, Grid, Tile};
+use iced::{
+    canvas::{Cache, Geometry},
+    Color, Point, Size,
+};
+use palette::Pixel;
+
+/// 话题的栅格
+#[derive(Clone, Debug)]
+pub(crate) struct Raster {
+    pub grid: Grid,
+    pub palette: Vec<(f32, Color)>, // 色标，按值递增
+    pub cells: Cells,               // 整个栅格的值，按行优先排列
+}
+
+/// 单元小于这么多像素时隔行隔列取样
+const MIN_PIXELS: f32 = 2.0;
+
+/// 一次最多画这么多个取样单元
+const MAX_SAMPLES: usize = 250_000;
+
+impl Raster {
+    /// 按块的几何和值类型建立空栅格，u8 填 255，f32 填 NaN
+    pub fn new(tile: &Tile) -> Self {
+        let len = tile.grid.width as usize * tile.grid.height as usize;
+        Self {
+            grid: tile.grid,
+            palette: Vec::new(),
+            cells: match tile.cells {
+                Cells::U8(_) => Cells::U8(vec![u8::MAX; len]),
+                Cells::F32(_) => Cells::F32(vec![f32::NAN; len]),
+            },
+        }
+    }
+
+    /// 块能否直接写入
+    #[inline]
+    pub fn fits(&self, tile: &Tile) -> bool {
+        self.grid == tile.grid
+            && matches!(
+                (&self.cells, &tile.cells),
+                (Cells::U8(_), Cells::U8(_)) | (Cells::F32(_), Cells::F32(_))
+            )
+    }
+
+    /// 写入一块，更新调色板，超出栅格的部分被忽略
+    pub fn put(&mut self, tile: Tile) {
+        self.palette = tile
+            .palette
+            .iter()
+            .map(|(value, color)| {
+                let rgba: [f32; 4] = color.into_format().into_raw();
+                (*value, rgba.into())
+            })
+            .collect();
+        let width = self.grid.width as usize;
+        let height = self.grid.height as usize;
+        let column = tile.column as usize;
+        let row = tile.row as usize;
+        let len = (tile.width as usize).min(width.saturating_sub(column));
+        let rows = (tile.height() as usize).min(height.saturating_sub(row));
+        for i in 0..rows {
+            let src = i * tile.width as usize..i * tile.width as usize + len;
+            let dst = (row + i) * width + column;
+            match (&mut self.cells, &tile.cells) {
+                (Cells::U8(cells), Cells::U8(tile)) => {
+                    cells[dst..dst + len].copy_from_slice(&tile[src])
+                }
+                (Cells::F32(cells), Cells::F32(tile)) => {
+                    cells[dst..dst + len].copy_from_slice(&tile[src])
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// 栅格覆盖的范围
+    #[inline]
+    pub fn aabb(&self) -> Option<AABB> {
+        AABB::of_grid(&self.grid)
+    }
+
+    /// 画出视野内的单元，同色的相邻单元合并成一个矩形
+    pub fn draw(&self, cache: &Cache, view: View, aabb: AABB) -> Option<Geometry> {
+        self.aabb().filter(|it| it.intersect(aabb))?;
+        let Grid {
+            origin: [x0, y0],
+            resolution,
+            width,
+            height,
+        } = self.grid;
+        let (columns, rows) = aabb.cells(&self.grid);
+        // 取样步长，起点对齐步长，平移时取样位置不变
+        let mut step = (MIN_PIXELS / (resolution * view.scale)).ceil().max(1.0) as usize;
+        while (columns.len() / step + 1) * (rows.len() / step + 1) > MAX_SAMPLES {
+            step *= 2;
+        }
+        let columns = columns.start / step * step..columns.end;
+        let rows = rows.start / step * step..rows.end;
+        // u8 的颜色预先算好
+        let lut = match &self.cells {
+            Cells::U8(_) => (0..=u8::MAX)
+                .map(|v| color_of(&self.palette, v as f32))
+                .collect(),
+            Cells::F32(_) => Vec::new(),
+        };
+        let color_at = |i: usize| match &self.cells {
+            Cells::U8(cells) => lut[cells[i] as usize],
+            Cells::F32(cells) => color_of(&self.palette, cells[i]),
+        };
+        let x_of = |column: usize| x0 + column.min(width as usize) as f32 * resolution;
+        Some(cache.draw(view.size, |frame| {
+            frame.translate(frame.center() - Point::ORIGIN);
+            frame.scale(view.scale);
+            for row in rows.clone().step_by(step) {
+                let bottom = y0 + row as f32 * resolution;
+                let top = y0 + (row + step).min(height as usize) as f32 * resolution;
+                let base = row * width as usize;
+                let mut run: Option<(usize, Color)> = None;
+                let samples = columns.clone().step_by(step).map(Some).chain([None]);
+                for column in samples {
+                    let color = column.and_then(|c| color_at(base + c));
+                    match (run, color) {
+                        (Some((_, c0)), Some(c1)) if c0 == c1 => continue,
+                        (Some((begin, c0)), _) => {
+                            let end = column.unwrap_or(columns.end);
+                            let top_left = convert(
+                                Point {
+                                    x: x_of(begin),
+                                    y: top,
+                                },
+                                view.center,
+                            );
+                            let size = Size {
+                                width: x_of(end) - x_of(begin),
+                                height: top - bottom,
+                            };
+                            frame.fill_rectangle(top_left, size, c0);
+                        }
+                        (None, _) => {}
+                    }
+                    run = column.zip(color);
+                }
+            }
+        }))
+    }
+}
+
+/// 按色标插值，NaN 和完全透明的颜色不画
+///
+/// 没有色标时按 0 黑到 255 白的灰度显示。
+fn color_of(palette: &[(f32, Color)], value: f32) -> Option<Color> {
+    if value.is_nan() {
+        return None;
+    }
+    let color = match palette {
+        [] => {
+            let v = (value / 255.0).clamp(0.0, 1.0);
+            Color::from_rgb(v, v, v)
+        }
+        [(_, first), ..] if value <= palette[0].0 => *first,
+        [.., (_, last)] if value >= palette[palette.len() - 1].0 => *last,
+        _ => {
+            let i = palette.partition_point(|(v, _)| *v <= value);
+            if i == 0 || i == palette.len() {
+                return None;
+            }
+            let (v0, c0) = palette[i - 1];
+            let (v1, c1) = palette[i];
+            let t = (value - v0) / (v1 - v0);
+            let lerp = |a: f32, b: f32| a + (b - a) * t;
+            Color {
+                r: lerp(c0.r, c1.r),
+                g: lerp(c0.g, c1.g),
+                b: lerp(c0.b, c1.b),
+                a: lerp(c0.a, c1.a),
+            }
+        }
+    };
+    Some(color).filter(|c| c.a > 0.0)
+}
+
+#[test]
+fn test_put_and_color() {
+    use palette::Srgba;
+
+    let grid = Grid {
+        origin: [0.0, 0.0],
+        resolution: 1.0,
+        width: 4,
+        height: 3,
+    };
+    let palette = vec![
+        (0.0, Srgba::new(255, 255, 255, 255)),
+        (100.0, Srgba::new(0, 0, 0, 255)),
+        (101.0, Srgba::new(0, 0, 0, 0)),
+    ];
+    // 块超出栅格右边和上边的部分被忽略
+    let tile = Tile {
+        grid,
+        palette,
+        column: 2,
+        row: 1,
+        width: 3,
+        cells: Cells::U8(vec![0, 50, 7, 100, 255, 7, 9, 9, 9]),
+    };
+    let mut raster = Raster::new(&tile);
+    assert!(raster.fits(&tile));
+    raster.put(tile);
+    assert_eq!(
+        raster.cells,
+        Cells::U8(vec![
+            255, 255, 255, 255, //
+            255, 255, 0, 50, //
+            255, 255, 100, 255,
+        ])
+    );
+    let gray = color_of(&raster.palette, 50.0).unwrap();
+    assert!((gray.r - 0.5).abs() < 0.01 && gray.a == 1.0);
+    assert_eq!(color_of(&raster.palette, -1.0), Some(Color::WHITE));
+    assert_eq!(color_of(&raster.palette, 255.0), None);
+    assert_eq!(color_of(&raster.palette, f32::NAN), None);
+    // 非法的色标不能 panic
+    let nan = [(f32::NAN, Color::WHITE), (1.0, Color::BLACK)];
+    assert_eq!(color_of(&nan, 0.5), None);
+}
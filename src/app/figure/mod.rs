@@ -1,37 +1,47 @@
-﻿use async_std::task;
+﻿use crate::protocol::DecodeError;
+use async_std::task;
 use iced::{
     canvas::{Cache, Geometry},
     Color, Point, Rectangle, Size, Vector,
 };
 use std::{
     collections::{HashMap, HashSet},
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 
 mod aabb;
 mod border;
+mod budget;
 mod content;
 mod snapshot;
 
 use aabb::AABB;
 use border::{available_size, border};
+use budget::Budget;
 
 pub(super) use border::{as_available, mark_anchor, mark_cross};
+pub(crate) use budget::Eviction;
 pub(crate) use content::TopicContent;
 pub(crate) use snapshot::FigureSnapshot;
 
+/// 源时间的延迟超过最小延迟这么多时视为源时钟跳变（例如回放重新开始），重新对齐
+const MAX_LAG: Duration = Duration::from_secs(10);
+
 /// 画面
 pub(crate) struct Figure {
     update_time: Instant,
     print_time: bool,
+    malformed: usize,
 
     pub dark_mode: bool,
     pub auto_view: bool,
+    pub budget: Budget,
     view: View,
 
     topics: HashMap<String, Option<Box<TopicContent>>>,
     hidden_layers: HashSet<String>,
     sync_sets: HashMap<String, (HashSet<String>, Duration)>,
+    clocks: HashMap<String, f64>, // 每个来源的源时钟到本地时钟的最小延迟（秒）
 
     border_cache: Cache,
 }
@@ -58,14 +68,17 @@ impl Default for Figure {
         Self {
             update_time: Instant::now(),
             print_time: false,
+            malformed: 0,
 
             dark_mode: true,
             auto_view: false,
+            budget: Default::default(),
             view: View::DEFAULT,
 
             topics: Default::default(),
             hidden_layers: Default::default(),
             sync_sets: Default::default(),
+            clocks: Default::default(),
 
             border_cache: Default::default(),
         }
@@ -199,13 +212,15 @@ impl Figure {
                 border(frame, Color::WHITE, Color::BLACK);
             }
         }));
-        // 收集异步绘图结果
-        geometries.extend(tasks.into_iter().map(task::block_on).filter_map(
-            |(name, content, geometry)| {
-                *self.topics.get_mut(&name).unwrap() = Some(content);
-                geometry
-            },
-        ));
+        // 收集异步绘图结果，栅格画在所有顶点之下
+        let mut vertex = Vec::with_capacity(tasks.len());
+        for (name, content, [raster, geometry, entities]) in tasks.into_iter().map(task::block_on) {
+            *self.topics.get_mut(&name).unwrap() = Some(content);
+            geometries.extend(raster);
+            vertex.extend(geometry);
+            vertex.extend(entities);
+        }
+        geometries.append(&mut vertex);
         self.timer(time);
         (
             Rectangle {
@@ -234,7 +249,7 @@ impl Figure {
 
     /// 重新关联同步组
     pub fn update_sync_set(&mut self, sync_set: &str, topic: String) {
-        let set = &mut self.sync_sets.get_mut(sync_set).unwrap().0;
+        let set = &mut self.sync_sets.entry(sync_set.into()).or_default().0;
         if sync_set.is_empty() {
             set.remove(&topic);
         } else {
@@ -242,6 +257,67 @@ impl Figure {
         }
     }
 
+    /// 使视野适应话题的范围，并关闭自动视野
+    pub fn fit_topic(&mut self, topic: &str) {
+        if let Some(aabb) = self.get_topic(topic).and_then(|content| content.aabb()) {
+            self.auto_view = false;
+            self.set_view_by_aabb(aabb);
+        }
+    }
+
+    /// 删除话题
+    pub fn remove_topic(&mut self, topic: &str) {
+        self.topics.remove(topic);
+        for (set, _) in self.sync_sets.values_mut() {
+            set.remove(topic);
+        }
+    }
+
+    /// 删除图层及其中的所有话题
+    pub fn remove_layer(&mut self, layer: &str) {
+        let topics = self
+            .topics
+            .iter()
+            .filter(|(_, content)| unwrap!(content).layer == layer)
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        for topic in topics {
+            self.remove_topic(&topic);
+        }
+        self.hidden_layers.remove(layer);
+    }
+
+    /// 解散同步组，其中的话题保留
+    pub fn dissolve_sync_set(&mut self, sync_set: &str) {
+        self.sync_sets.remove(sync_set);
+    }
+
+    /// 把来源 `source` 的源时间换算为本地时刻
+    ///
+    /// 同一来源的所有话题共用一个时钟偏差：延迟最小的包按到达时刻计，其他包按多出的延迟提前，
+    /// 因此数据时刻不晚于到达时刻，也不受网络抖动影响。
+    pub fn stamp(
+        &mut self,
+        source: &str,
+        time: Instant,
+        received: SystemTime,
+        sent: SystemTime,
+    ) -> Instant {
+        let delay = match received.duration_since(sent) {
+            Ok(d) => d.as_secs_f64(),
+            Err(e) => -e.duration().as_secs_f64(),
+        };
+        let min = match self.clocks.get(source) {
+            Some(&min) if min <= delay && delay - min <= MAX_LAG.as_secs_f64() => min,
+            _ => {
+                self.clocks.insert(source.into(), delay);
+                delay
+            }
+        };
+        time.checked_sub(Duration::from_secs_f64(delay - min))
+            .unwrap_or(time)
+    }
+
     /// 获取话题对象
     pub fn put_topic(&mut self, topic: impl ToString) -> &mut TopicContent {
         unwrap!(mut; self.topics
@@ -259,6 +335,38 @@ impl Figure {
         self.print_time = value;
     }
 
+    /// 记录一个无法解码的包
+    pub fn report_malformed(&mut self, e: DecodeError) {
+        self.malformed += 1;
+        eprintln!("malformed packet #{}: {}", self.malformed, e);
+    }
+
+    /// 打印各话题的用量和全局预算，占用内存多的在前
+    pub fn print_usage(&self) {
+        let mut usage = self
+            .topics
+            .iter()
+            .map(|(name, content)| (name, unwrap!(content).usage()))
+            .collect::<Vec<_>>();
+        usage.sort_by(|(a, u), (b, v)| v.bytes.cmp(&u.bytes).then(a.cmp(b)));
+        for (name, usage) in &usage {
+            println!("{}: {}", name, usage);
+        }
+        let total = usage.iter().map(|(_, usage)| usage.bytes).sum::<usize>();
+        let limit = match self.budget.limit {
+            0 => "unlimited".to_string(),
+            n => n.to_string(),
+        };
+        println!(
+            "total: {}/{} bytes in {} topics, evict {:?}, {} vertices, rasters and entities evicted",
+            total,
+            limit,
+            usage.len(),
+            self.budget.eviction,
+            self.budget.evicted()
+        );
+    }
+
     /// 清空所有话题数据
     pub fn clean(&mut self) {
         self.topics.clear();
@@ -266,13 +374,18 @@ impl Figure {
 
     /// 同步
     fn sync(&mut self, time: Instant) {
+        // 各话题按自己的保留时长消除
+        for content in self.topics.values_mut() {
+            unwrap!(mut; content).expire(time);
+        }
         for (set, life_time) in self.sync_sets.values_mut() {
             // 按当前时间计算期限
             let deadline0 = time.checked_sub(*life_time);
             // 按数量消除并计算期限
             let deadline1 = set
                 .iter()
-                .filter_map(|t| unwrap!(self.topics[t]).begin())
+                .filter_map(|t| self.topics.get(t))
+                .filter_map(|content| unwrap!(content).begin())
                 .min();
             // 合并期限
             if let Some(deadline) = match (deadline0, deadline1) {
@@ -283,10 +396,15 @@ impl Figure {
             } {
                 // 按期限消除
                 for t in set.iter() {
-                    unwrap!(mut; self.topics.get_mut(t).unwrap()).sync(deadline)
+                    if let Some(content) = self.topics.get_mut(t) {
+                        unwrap!(mut; content).sync(deadline)
+                    }
                 }
             }
         }
+        // 按全局预算消除
+        self.budget
+            .enforce(self.topics.values_mut().map(|c| &mut **unwrap!(mut; c)));
     }
 
     /// 计算范围
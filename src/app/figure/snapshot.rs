@@ -1,13 +1,21 @@
-﻿use super::content::TopicBuffer;
-use crate::Vertex;
+﻿use super::content::{Raster, TopicBuffer};
+use crate::{
+    protocol::{
+        Anchor, Attachment, Cells, Ellipse, Grid, Label, Marker, Polygon, Rect, Style, Vector,
+    },
+    Shape, Vertex,
+};
 use async_std::{
     fs::{create_dir_all, File},
     io::{BufReader, WriteExt},
     path::PathBuf,
 };
-use iced::{futures::AsyncBufReadExt, Point};
+use iced::{futures::AsyncBufReadExt, Color, Point};
 use palette::{rgb::channels::Argb, Packed, Pixel, Srgba};
-use std::{collections::HashMap, time::Instant};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 pub struct FigureSnapshot(pub(crate) HashMap<String, TopicBuffer>);
 
@@ -53,6 +61,7 @@ impl FigureSnapshot {
             create_dir_all(dir).await?;
         }
         let mut file = File::create(path).await?;
+        let clock = (Instant::now(), SystemTime::now());
         for (topic, buffer) in self.0 {
             // 名字
             write_async!(str; format!("{}\n", topic) => file)?;
@@ -60,30 +69,60 @@ impl FigureSnapshot {
             write_async!(str; format!("colors[{}]\n", buffer.color_map.len()) => file)?;
             for level in 0u8..255 {
                 if let Some(color) = buffer.color_map.get(&level) {
-                    let color = Srgba::new(color.r, color.g, color.b, color.a);
-                    let color = Packed::<Argb>::from(color.into_format()).color;
-                    write_async!(str; format!("{:03}|{:#08x}\n", level, color) => file)?;
+                    write_async!(str; format!("{:03}|{:#08x}\n", level, argb(color)) => file)?;
+                }
+            }
+            // 样式
+            if !buffer.style_map.is_empty() {
+                write_async!(str; format!("styles[{}]\n", buffer.style_map.len()) => file)?;
+                for level in 0u8..=255 {
+                    if let Some(style) = buffer.style_map.get(&level) {
+                        write_async!(str; format!("{:03}|{}\n", level, write_style(style)) => file)?;
+                    }
+                }
+            }
+            // 栅格
+            if let Some(raster) = &buffer.raster {
+                write_async!(str; format!("raster[{}]\n", write_grid(raster)) => file)?;
+                write_async!(str; format!("palette[{}]\n", raster.palette.len()) => file)?;
+                for (value, color) in &raster.palette {
+                    write_async!(str; format!("{}|{:#08x}\n", value, argb(color)) => file)?;
+                }
+                let width = raster.grid.width as usize;
+                for row in 0..raster.grid.height as usize {
+                    let row = write_row(&raster.cells, row * width..(row + 1) * width);
+                    write_async!(str; format!("{}\n", row) => file)?;
+                }
+            }
+            // 实体
+            if !buffer.entities.is_empty() {
+                write_async!(str; format!("entities[{}]\n", buffer.entities.len()) => file)?;
+                for (id, (t, v, a)) in &buffer.entities {
+                    let item = write_item(wall_time(clock, *t), v, a.as_deref());
+                    write_async!(str; format!("{}:{}\n", id, item) => file)?;
+                }
+            }
+            // 边
+            if !buffer.edges.is_empty() {
+                write_async!(str; format!("edges[{}]\n", buffer.edges.len()) => file)?;
+                for ((from, to), (level, alpha)) in &buffer.edges {
+                    write_async!(str; format!("{}>{}|{:03}|{}\n", from, to, level, alpha) => file)?;
                 }
             }
             // 数据
-            write_async!(str; format!("items[{}/{}]\n", buffer.queue.len(), buffer.capacity) => file)?;
-            for (_, v) in buffer.queue.iter().rev() {
-                let Vertex {
-                    x,
-                    y,
-                    level,
-                    alpha,
-                    _zero: _,
-                    shape,
-                    extra,
-                } = v;
-                let alpha = *alpha as f32 / 2.55;
-                let bytes = unsafe { *(v as *const _ as *const u128) };
-                write_async!(str; format!("{:03}|{:10.3} {:10.3}|{} {:7.3}|{:3.0}% /{:032x}\n",
-                                           level, x, y, shape, extra, alpha, bytes) => file)?;
+            let (len, capacity) = (buffer.queue.len(), buffer.capacity);
+            match buffer.retention {
+                Some(t) => {
+                    write_async!(str; format!("items[{}/{}/{}]\n", len, capacity, t.as_secs_f64()) => file)?
+                }
+                None => write_async!(str; format!("items[{}/{}]\n", len, capacity) => file)?,
+            }
+            for (t, v, a) in buffer.queue.iter().rev() {
+                let item = write_item(wall_time(clock, *t), v, a.as_deref());
+                write_async!(str; format!("{}\n", item) => file)?;
             }
             // 空一行
-            write_async!(&[b'\n'] => file)?;
+            write_async!(b"\n" => file)?;
         }
         Ok(())
     }
@@ -94,7 +133,7 @@ impl FigureSnapshot {
 
         let mut result = HashMap::<String, TopicBuffer>::new();
         let mut line = String::new();
-        let now = Instant::now();
+        let clock = (Instant::now(), SystemTime::now());
         let mut cx: f32 = 0.0;
         let mut cy: f32 = 0.0;
         let mut cn: usize = 0;
@@ -108,31 +147,90 @@ impl FigureSnapshot {
                 for _ in 0..len {
                     let mut str = read_line!(reader => line).split('|');
                     let level = unwarp_or_break!(op; str.next().and_then(|s| s.parse::<u8>().ok()));
-                    let color: Option<[f32; 4]> = str
-                        .next()
-                        .and_then(|s| u32::from_str_radix(s.trim_start_matches("0x"), 16).ok())
-                        .map(|c| Srgba::from_u32::<Argb>(c).into_format().into_raw());
+                    let color = str.next().and_then(read_argb);
+                    topic.color_map.insert(level, unwarp_or_break!(op; color));
+                }
+            }
+            // 旧版快照没有样式
+            let mut header = read_line!(reader => line).to_string();
+            if let Some(str) = header.strip_prefix("styles[") {
+                let len: usize = unwarp_or_break!(re; str.trim_end_matches(']').parse());
+                for _ in 0..len {
+                    let style = read_line!(reader => line)
+                        .split_once('|')
+                        .and_then(|(level, s)| Some((level.parse::<u8>().ok()?, read_style(s)?)));
+                    let (level, style) = unwarp_or_break!(op; style);
+                    topic.style_map.insert(level, style);
+                }
+                header = read_line!(reader => line).to_string();
+            }
+            // 旧版快照没有栅格
+            if let Some(str) = header.strip_prefix("raster[") {
+                let mut raster = unwarp_or_break!(op; read_grid(str.trim_end_matches(']')));
+                let str = read_line!(reader => line);
+                let str = str.trim_start_matches("palette[").trim_end_matches(']');
+                let len: usize = unwarp_or_break!(re; str.parse());
+                for _ in 0..len {
+                    let stop = read_line!(reader => line)
+                        .split_once('|')
+                        .and_then(|(value, color)| Some((value.parse().ok()?, read_argb(color)?)));
+                    raster.palette.push(unwarp_or_break!(op; stop));
+                }
+                for _ in 0..raster.grid.height {
+                    let row = read_line!(reader => line);
+                    unwarp_or_break!(op; read_row(&mut raster.cells, row));
+                }
+                let len = raster.grid.width as usize * raster.grid.height as usize;
+                if raster.cells.len() == len {
+                    topic.raster = Some(raster);
+                }
+                header = read_line!(reader => line).to_string();
+            }
+            // 旧版快照没有实体
+            if let Some(str) = header.strip_prefix("entities[") {
+                let len: usize = unwarp_or_break!(re; str.trim_end_matches(']').parse());
+                for _ in 0..len {
+                    let entity = read_line!(reader => line)
+                        .split_once(':')
+                        .and_then(|(id, item)| Some((id.parse::<u32>().ok()?, read_item(item)?)));
+                    let (id, (time, data, attachment)) = unwarp_or_break!(op; entity);
                     topic
-                        .color_map
-                        .insert(level, unwarp_or_break!(op; color).into());
+                        .entities
+                        .insert(id, (local_time(clock, time), data, attachment));
+                    cx += data.x;
+                    cy += data.y;
+                    cn += 1;
+                }
+                header = read_line!(reader => line).to_string();
+            }
+            // 旧版快照没有边
+            if let Some(str) = header.strip_prefix("edges[") {
+                let len: usize = unwarp_or_break!(re; str.trim_end_matches(']').parse());
+                for _ in 0..len {
+                    let edge = read_edge(read_line!(reader => line));
+                    let (key, value) = unwarp_or_break!(op; edge);
+                    topic.edges.insert(key, value);
                 }
+                header = read_line!(reader => line).to_string();
             }
             {
-                let str = read_line!(reader => line);
-                let str = str.trim_start_matches("items[");
+                let str = header.trim_start_matches("items[");
                 let str = str.trim_end_matches(']');
                 let mut str = str.split('/');
                 let len: usize = unwarp_or_break!(op; str.next().and_then(|s| s.parse().ok()));
                 topic.capacity = unwarp_or_break!(op; str.next().and_then(|s| s.parse().ok()));
+                // 旧版快照没有保留时长
+                topic.retention = str
+                    .next()
+                    .and_then(|s| s.parse().ok())
+                    .and_then(|t| Duration::try_from_secs_f64(t).ok());
                 topic.queue.reserve(len);
                 for _ in 0..len {
-                    let data = read_line!(reader => line)
-                        .split('/')
-                        .last()
-                        .and_then(|s| u128::from_str_radix(s, 16).ok());
-                    let data = unwarp_or_break!(op; data);
-                    let data = unsafe { &*(&data as *const _ as *const Vertex) };
-                    topic.queue.push_front((now, *data));
+                    let item = read_item(read_line!(reader => line));
+                    let (time, data, attachment) = unwarp_or_break!(op; item);
+                    topic
+                        .queue
+                        .push_front((local_time(clock, time), data, attachment));
                     cx += data.x;
                     cy += data.y;
                     cn += 1;
@@ -149,3 +247,383 @@ impl FigureSnapshot {
         ))
     }
 }
+
+/// 本地时刻对应的系统时间
+fn wall_time((now, wall): (Instant, SystemTime), time: Instant) -> SystemTime {
+    wall.checked_sub(now.saturating_duration_since(time))
+        .unwrap_or(UNIX_EPOCH)
+}
+
+/// 系统时间对应的本地时刻，没有时间或晚于现在时取现在
+fn local_time((now, wall): (Instant, SystemTime), time: Option<SystemTime>) -> Instant {
+    time.and_then(|t| wall.duration_since(t).ok())
+        .and_then(|age| now.checked_sub(age))
+        .unwrap_or(now)
+}
+
+/// 顶点：`级别|x y|形状 补充数据|连线透明度% @时间 /顶点的十六进制`，之后是附件
+///
+/// 时间是自 UNIX 纪元的秒数。
+fn write_item(time: SystemTime, v: &Vertex, attachment: Option<&Attachment>) -> String {
+    let Vertex {
+        x,
+        y,
+        level,
+        alpha,
+        _zero: _,
+        shape,
+        extra,
+    } = v;
+    let alpha = *alpha as f32 / 2.55;
+    let bytes = u128::from_le_bytes(v.to_le_bytes());
+    let attachment = attachment.map_or_else(String::new, write_attachment);
+    let time = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!(
+        "{:03}|{:10.3} {:10.3}|{} {:7.3}|{:3.0}% @{:.6} /{:032x}{}",
+        level,
+        x,
+        y,
+        shape,
+        extra,
+        alpha,
+        time.as_secs_f64(),
+        bytes,
+        attachment
+    )
+}
+
+/// 解析时间、顶点及其附件，只读取 `@` 和 `/` 之后的部分，旧版快照没有时间
+fn read_item(str: &str) -> Option<(Option<SystemTime>, Vertex, Option<Box<Attachment>>)> {
+    let (head, str) = str.rsplit_once('/')?;
+    let time = head
+        .rsplit_once('@')
+        .and_then(|(_, t)| t.trim().parse().ok())
+        .and_then(|t| Duration::try_from_secs_f64(t).ok())
+        .map(|t| UNIX_EPOCH + t);
+    let (data, attachment) = str.split_once(" +").unwrap_or((str, ""));
+    let data = u128::from_str_radix(data, 16).ok()?;
+    let data = Vertex::from_le_bytes(data.to_le_bytes()).ok()?;
+    let attachment = read_attachment(data.shape, attachment)
+        .or_else(|| Attachment::default_for(data.shape))
+        .map(Box::new);
+    Some((time, data, attachment))
+}
+
+/// 解析边：`起点>终点|级别|透明度`
+fn read_edge(str: &str) -> Option<((u32, u32), (u8, u8))> {
+    let mut str = str.split('|');
+    let (from, to) = str.next()?.split_once('>')?;
+    let level = str.next()?.parse().ok()?;
+    let alpha = str.next()?.parse().ok()?;
+    Some(((from.parse().ok()?, to.parse().ok()?), (level, alpha)))
+}
+
+/// 颜色的 ARGB 值
+fn argb(color: &Color) -> u32 {
+    let color = Srgba::new(color.r, color.g, color.b, color.a);
+    Packed::<Argb>::from(color.into_format()).color
+}
+
+fn read_argb(str: &str) -> Option<Color> {
+    let argb = u32::from_str_radix(str.trim_start_matches("0x"), 16).ok()?;
+    let rgba: [f32; 4] = Srgba::from_u32::<Argb>(argb).into_format().into_raw();
+    Some(rgba.into())
+}
+
+/// 栅格：`原点 x 原点 y 分辨率 列数 行数 类型`，类型为 `u8` 或 `f32`
+fn write_grid(raster: &Raster) -> String {
+    let Grid {
+        origin: [x, y],
+        resolution,
+        width,
+        height,
+    } = raster.grid;
+    let kind = match raster.cells {
+        Cells::U8(_) => "u8",
+        Cells::F32(_) => "f32",
+    };
+    format!("{} {} {} {} {} {}", x, y, resolution, width, height, kind)
+}
+
+/// 解析栅格几何，得到还没有值的栅格
+fn read_grid(str: &str) -> Option<Raster> {
+    let mut str = str.split(' ');
+    let grid = Grid {
+        origin: [str.next()?.parse().ok()?, str.next()?.parse().ok()?],
+        resolution: str.next()?.parse().ok()?,
+        width: str.next()?.parse().ok()?,
+        height: str.next()?.parse().ok()?,
+    };
+    let cells = match str.next()? {
+        "u8" => Cells::U8(Vec::new()),
+        "f32" => Cells::F32(Vec::new()),
+        _ => return None,
+    };
+    Some(Raster {
+        grid,
+        palette: Vec::new(),
+        cells,
+    })
+}
+
+/// 一行单元的十六进制，f32 按位写出
+fn write_row(cells: &Cells, range: std::ops::Range<usize>) -> String {
+    match cells {
+        Cells::U8(cells) => cells[range].iter().map(|c| format!("{:02x}", c)).collect(),
+        Cells::F32(cells) => cells[range]
+            .iter()
+            .map(|c| format!("{:08x}", c.to_bits()))
+            .collect(),
+    }
+}
+
+/// 解析一行单元，追加到值的末尾
+fn read_row(cells: &mut Cells, str: &str) -> Option<()> {
+    let hex = |i: usize, n: usize| u32::from_str_radix(str.get(i * n..i * n + n)?, 16).ok();
+    match cells {
+        Cells::U8(cells) => {
+            for i in 0..str.len() / 2 {
+                cells.push(hex(i, 2)? as u8);
+            }
+        }
+        Cells::F32(cells) => {
+            for i in 0..str.len() / 8 {
+                cells.push(f32::from_bits(hex(i, 8)?));
+            }
+        }
+    }
+    Some(())
+}
+
+/// 样式：`点形状 点大小 线宽 虚线实段 虚线空段 箭头长度 箭头世界单位 向量缩放 着色模长下限 着色模长上限`
+fn write_style(style: &Style) -> String {
+    format!(
+        "{} {} {} {} {} {} {} {} {} {}",
+        style.marker as u8,
+        style.marker_size,
+        style.line_width,
+        style.dash[0],
+        style.dash[1],
+        style.arrow_length,
+        style.arrow_world as u8,
+        style.vector_scale,
+        style.magnitude[0],
+        style.magnitude[1]
+    )
+}
+
+fn read_style(str: &str) -> Option<Style> {
+    let mut str = str.split(' ');
+    let marker = Marker::try_from(str.next()?.parse::<u8>().ok()?).ok()?;
+    let mut f32 = [0.0f32; 5];
+    for x in &mut f32 {
+        *x = str.next()?.parse().ok()?;
+    }
+    let [marker_size, line_width, on, off, arrow_length] = f32;
+    let mut style = Style {
+        marker,
+        marker_size,
+        line_width,
+        dash: [on, off],
+        arrow_length,
+        arrow_world: str.next()? == "1",
+        ..Style::DEFAULT
+    };
+    // 旧版快照没有向量相关的字段
+    if let Some(scale) = str.next() {
+        style.vector_scale = scale.parse().ok()?;
+        style.magnitude = [str.next()?.parse().ok()?, str.next()?.parse().ok()?];
+    }
+    Some(style)
+}
+
+/// 附件写在顶点数据之后：
+///
+/// - 文字：` +锚点 字号 世界单位 内容的 UTF-8 十六进制`
+/// - 矩形：` +宽 高 填充透明度`
+/// - 多边形：` +填充透明度 x,y x,y ...`
+/// - 椭圆：` +半轴 a 半轴 b 填充透明度`
+/// - 向量：` +dx dy`
+fn write_attachment(attachment: &Attachment) -> String {
+    match attachment {
+        Attachment::Text(label) => {
+            let text = label.text.bytes().map(|b| format!("{:02x}", b));
+            format!(
+                " +{} {} {} {}",
+                label.anchor as u8,
+                label.size,
+                label.world as u8,
+                text.collect::<String>()
+            )
+        }
+        Attachment::Rect(rect) => format!(" +{} {} {}", rect.width, rect.height, rect.fill),
+        Attachment::Polygon(polygon) => {
+            let points = polygon.points.iter().map(|[x, y]| format!(" {},{}", x, y));
+            format!(" +{}{}", polygon.fill, points.collect::<String>())
+        }
+        Attachment::Ellipse(ellipse) => format!(" +{} {} {}", ellipse.a, ellipse.b, ellipse.fill),
+        Attachment::Vector(vector) => format!(" +{} {}", vector.dx, vector.dy),
+    }
+}
+
+/// 解析附件，旧版快照没有附件
+fn read_attachment(shape: Shape, str: &str) -> Option<Attachment> {
+    let mut str = str.split(' ');
+    match shape {
+        Shape::Arrow | Shape::Circle => None,
+        Shape::Text => {
+            let anchor = Anchor::try_from(str.next()?.parse::<u8>().ok()?).ok()?;
+            let size = str.next()?.parse().ok()?;
+            let world = str.next()? == "1";
+            let hex = str.next().unwrap_or_default();
+            let text = (0..hex.len() / 2)
+                .map(|i| u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok())
+                .collect::<Option<Vec<_>>>()?;
+            Some(Attachment::Text(Label {
+                text: String::from_utf8(text).ok()?,
+                anchor,
+                size,
+                world,
+            }))
+        }
+        Shape::Rect | Shape::OrientedBox => Some(Attachment::Rect(Rect {
+            width: str.next()?.parse().ok()?,
+            height: str.next()?.parse().ok()?,
+            fill: str.next()?.parse().ok()?,
+        })),
+        Shape::Polygon => {
+            let fill = str.next()?.parse().ok()?;
+            let points = str
+                .map(|s| {
+                    let (x, y) = s.split_once(',')?;
+                    Some([x.parse().ok()?, y.parse().ok()?])
+                })
+                .collect::<Option<_>>()?;
+            Some(Attachment::Polygon(Polygon { points, fill }))
+        }
+        Shape::Vector => Some(Attachment::Vector(Vector {
+            dx: str.next()?.parse().ok()?,
+            dy: str.next()?.parse().ok()?,
+        })),
+        Shape::Ellipse => Some(Attachment::Ellipse(Ellipse {
+            a: str.next()?.parse().ok()?,
+            b: str.next()?.parse().ok()?,
+            fill: str.next()?.parse().ok()?,
+        })),
+    }
+}
+
+#[test]
+fn style_line() {
+    let style = Style {
+        marker: Marker::Cross,
+        dash: [4.0, 2.5],
+        arrow_world: true,
+        vector_scale: 0.5,
+        magnitude: [0.0, 2.0],
+        ..Style::DEFAULT
+    };
+    assert_eq!(read_style(&write_style(&style)), Some(style));
+    assert_eq!(read_style("9 1 1 0 0 1 0"), None);
+    assert_eq!(read_style("0 3.5 1.5 0 0 15 0"), Some(Style::DEFAULT));
+    assert_eq!(read_style("0 3.5 1.5 0 0 15 0 1"), None);
+}
+
+#[test]
+fn attachment_line() {
+    let label = Attachment::Text(Label {
+        text: "路点 1".into(),
+        anchor: Anchor::BottomLeft,
+        size: 0.5,
+        world: true,
+    });
+    let line = write_attachment(&label);
+    let (_, str) = line.split_once(" +").unwrap();
+    assert_eq!(read_attachment(Shape::Text, str), Some(label));
+    assert_eq!(read_attachment(Shape::Text, ""), None);
+    let polygon = Attachment::Polygon(Polygon {
+        points: vec![[0.0, 0.0], [1.5, -2.0], [0.0, 1.0]],
+        fill: 128,
+    });
+    let line = write_attachment(&polygon);
+    let (_, str) = line.split_once(" +").unwrap();
+    assert_eq!(read_attachment(Shape::Polygon, str), Some(polygon));
+    let ellipse = Attachment::Ellipse(Ellipse {
+        a: 2.5,
+        b: 0.5,
+        fill: 32,
+    });
+    let line = write_attachment(&ellipse);
+    let (_, str) = line.split_once(" +").unwrap();
+    assert_eq!(read_attachment(Shape::Ellipse, str), Some(ellipse));
+    let vector = Attachment::Vector(Vector { dx: -1.5, dy: 0.25 });
+    let line = write_attachment(&vector);
+    let (_, str) = line.split_once(" +").unwrap();
+    assert_eq!(read_attachment(Shape::Vector, str), Some(vector));
+}
+
+#[test]
+fn item_line() {
+    let rect = Attachment::Rect(Rect {
+        width: 2.0,
+        height: 1.0,
+        fill: 64,
+    });
+    let vertex = crate::vertex!(3; 1.5, -2; OrientedBox, 0.5; 255);
+    let time = UNIX_EPOCH + Duration::from_micros(1_760_000_000_250_000);
+    let line = format!("7:{}", write_item(time, &vertex, Some(&rect)));
+    let (id, item) = line.split_once(':').unwrap();
+    assert_eq!(id, "7");
+    assert_eq!(
+        read_item(item),
+        Some((Some(time), vertex, Some(Box::new(rect))))
+    );
+    // 旧版快照没有时间和附件
+    let vertex = crate::vertex!(0; 0, 0; Text, 0.0; 0);
+    let line = write_item(time, &vertex, None);
+    let line = line.replace(" @1760000000.250000", "");
+    assert_eq!(
+        read_item(&line),
+        Some((None, vertex, Some(Box::new(Label::default().into()))))
+    );
+    assert_eq!(read_item("000|/zz"), None);
+    // 时间换算
+    let clock = (Instant::now(), SystemTime::now());
+    let t = clock.0.checked_sub(Duration::from_secs(3)).unwrap();
+    let wall = wall_time(clock, t);
+    assert_eq!(local_time(clock, Some(wall)), t);
+    assert_eq!(local_time(clock, None), clock.0);
+    assert_eq!(read_edge("3>12|007|255"), Some(((3, 12), (7, 255))));
+    assert_eq!(read_edge("3>12|007"), None);
+}
+
+#[test]
+fn raster_lines() {
+    let raster = Raster {
+        grid: Grid {
+            origin: [-1.5, 2.0],
+            resolution: 0.05,
+            width: 3,
+            height: 2,
+        },
+        palette: Vec::new(),
+        cells: Cells::F32(vec![0.0, -1.5, f32::NAN, 1e6, 2.0, 3.0]),
+    };
+    let mut loaded = read_grid(&write_grid(&raster)).unwrap();
+    assert_eq!(loaded.grid, raster.grid);
+    for row in 0..2 {
+        read_row(
+            &mut loaded.cells,
+            &write_row(&raster.cells, row * 3..row * 3 + 3),
+        )
+        .unwrap();
+    }
+    let (Cells::F32(a), Cells::F32(b)) = (&loaded.cells, &raster.cells) else {
+        panic!("cell type changed");
+    };
+    assert!(a.iter().zip(b).all(|(a, b)| a.to_bits() == b.to_bits()));
+    let mut cells = Cells::U8(Vec::new());
+    read_row(&mut cells, &write_row(&Cells::U8(vec![0, 100, 255]), 0..3)).unwrap();
+    assert_eq!(cells, Cells::U8(vec![0, 100, 255]));
+    assert_eq!(read_row(&mut cells, "0g"), None);
+}
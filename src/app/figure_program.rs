@@ -35,7 +35,9 @@ pub enum FigureEvent {
     ReadyForGrab,
     Grab(Vector),
     Select(Rectangle, Point, Point),
-    Packet(Instant, Vec<u8>),
+    Packet(Instant, String, Vec<Vec<u8>>),
+    #[cfg(feature = "json")]
+    Text(Instant, String, crate::protocol::Packet),
     Line(String),
 }
 
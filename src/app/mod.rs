@@ -1,6 +1,5 @@
 ﻿use async_std::{
     channel::{unbounded, Receiver, Sender},
-    net::UdpSocket,
     path::PathBuf,
     task,
 };
@@ -12,15 +11,24 @@ use iced::{
     Length::Fill,
     Rectangle, Settings, Subscription,
 };
-use std::{cell::Cell, time::Instant};
+use std::cell::Cell;
 
 mod cache_builder;
 mod figure;
 mod figure_program;
+mod reassembly;
+mod transport;
 
 use cache_builder::spawn_background as spawn_draw;
 use figure::FigureSnapshot;
 use figure_program::{CacheComplete, FigureEvent, FigureProgram};
+use transport::{spawn_tcp, spawn_udp};
+
+#[cfg(unix)]
+use transport::{spawn_shm, spawn_unix};
+
+#[cfg(feature = "json")]
+use transport::{send_line, spawn_text};
 
 pub(crate) use figure::Figure;
 
@@ -60,6 +68,14 @@ impl Application for Main {
             Flags::Realtime(title, port) => {
                 let (sender, receiver) = unbounded();
                 spawn_udp(port, sender.clone());
+                spawn_tcp(port, sender.clone());
+                #[cfg(unix)]
+                {
+                    spawn_unix(port, sender.clone());
+                    spawn_shm(crate::local::shm_path(port), sender.clone());
+                }
+                #[cfg(feature = "json")]
+                spawn_text(port.wrapping_add(1), sender.clone());
                 spawn_stdin(sender.clone());
                 (
                     Main {
@@ -133,28 +149,23 @@ fn load_icon(path: PathBuf) -> Option<Icon> {
     Icon::from_rgba(rgba, width, height).ok()
 }
 
-/// 启动命令行解析
+/// 启动命令行解析，以 `{` 开头的行按 JSON 文本解析
+#[cfg_attr(not(feature = "json"), allow(unused_variables))]
 fn spawn_stdin(sender: Sender<FigureEvent>) {
     task::spawn(async move {
-        loop {
+        for line_no in 1usize.. {
             let mut line = String::new();
-            let _ = match async_std::io::stdin().read_line(&mut line).await {
+            match async_std::io::stdin().read_line(&mut line).await {
                 Ok(0) | Err(_) => break,
-                Ok(_) => sender.send(FigureEvent::Line(line)).await,
+                #[cfg(feature = "json")]
+                Ok(_) if line.trim_start().starts_with('{') => {
+                    let at = format!("stdin line {}", line_no);
+                    send_line(&line, "stdin", &at, &sender).await;
+                }
+                Ok(_) => {
+                    let _ = sender.send(FigureEvent::Line(line)).await;
+                }
             };
         }
     });
 }
-
-/// 启动 UDP 接收
-fn spawn_udp(port: u16, sender: Sender<FigureEvent>) {
-    task::spawn(async move {
-        let socket = UdpSocket::bind(format!("0.0.0.0:{}", port)).await.unwrap();
-        let mut buf = Box::new([0u8; 65536]);
-        while let Ok((n, _)) = socket.recv_from(buf.as_mut()).await {
-            let _ = sender
-                .send(FigureEvent::Packet(Instant::now(), buf[..n].to_vec()))
-                .await;
-        }
-    });
-}
@@ -0,0 +1,206 @@
+﻿use crate::protocol::{Fragment, MAX_STREAM_PACKET};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    hash::Hash,
+    mem::size_of,
+    time::{Duration, Instant},
+};
+
+/// 未收齐的帧最长等待时间
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// 每个来源最多同时等待的帧数
+const MAX_FRAMES_PER_SOURCE: usize = 16;
+
+/// 所有来源最多同时等待的帧数
+const MAX_FRAMES: usize = 1024;
+
+/// 等待中的帧最多占用的字节数，包括分片表本身
+const MAX_BYTES: usize = 4 * MAX_STREAM_PACKET;
+
+/// 按来源和帧序号重组分片
+///
+/// 等待中的帧数和字节数有上限，超出时先丢弃最旧的帧，伪造的分片不能无限占用内存。
+pub(super) struct Reassembly<K> {
+    frames: HashMap<(K, u32), Pending>,
+    bytes: usize,
+}
+
+/// 未收齐的帧
+struct Pending {
+    time: Instant,
+    received: usize,
+    bytes: usize,
+    fragments: Vec<Option<Vec<u8>>>,
+}
+
+impl<K> Default for Reassembly<K> {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            frames: Default::default(),
+            bytes: 0,
+        }
+    }
+}
+
+impl Pending {
+    fn new(time: Instant, count: u16) -> Self {
+        Self {
+            time,
+            received: 0,
+            bytes: count as usize * size_of::<Option<Vec<u8>>>(),
+            fragments: vec![None; count as usize],
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone + Debug> Reassembly<K> {
+    /// 收入一个包，帧收齐时按分片顺序返回整帧
+    ///
+    /// 包头无法解析的包原样返回，由解码器报告错误。
+    pub fn push(&mut self, source: K, time: Instant, buf: Vec<u8>) -> Option<Vec<Vec<u8>>> {
+        self.expire(time);
+        let Fragment {
+            frame,
+            index,
+            count,
+        } = match Fragment::peek(&buf) {
+            Ok(fragment) if fragment.count > 1 => fragment,
+            _ => return Some(vec![buf]),
+        };
+        let key = (source.clone(), frame);
+        // 分片数不一致，说明是序号重复的新帧
+        if self
+            .frames
+            .get(&key)
+            .is_some_and(|pending| pending.fragments.len() != count as usize)
+        {
+            self.remove(&key);
+        }
+        if !self.frames.contains_key(&key) {
+            while self.frames.keys().filter(|(s, _)| *s == source).count() >= MAX_FRAMES_PER_SOURCE
+            {
+                self.drop_oldest(|s| *s == source);
+            }
+            while self.frames.len() >= MAX_FRAMES {
+                self.drop_oldest(|_| true);
+            }
+            let pending = Pending::new(time, count);
+            self.bytes += pending.bytes;
+            self.frames.insert(key.clone(), pending);
+        }
+        let pending = self.frames.get_mut(&key).unwrap();
+        let slot = &mut pending.fragments[index as usize];
+        match slot {
+            Some(old) => pending.bytes -= old.len(),
+            None => pending.received += 1,
+        }
+        pending.bytes += buf.len();
+        self.bytes = self.bytes - slot.as_ref().map_or(0, Vec::len) + buf.len();
+        *slot = Some(buf);
+        if pending.received == pending.fragments.len() {
+            let pending = self.remove(&key).unwrap();
+            return Some(pending.fragments.into_iter().map(Option::unwrap).collect());
+        }
+        while self.bytes > MAX_BYTES {
+            self.drop_oldest(|_| true);
+        }
+        None
+    }
+
+    /// 丢弃超时的帧
+    fn expire(&mut self, time: Instant) {
+        let expired = self
+            .frames
+            .iter()
+            .filter(|(_, pending)| time.saturating_duration_since(pending.time) >= TIMEOUT)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        for key in expired {
+            self.drop_frame(&key, "timed out");
+        }
+    }
+
+    /// 丢弃符合条件的来源中最旧的帧
+    fn drop_oldest(&mut self, filter: impl Fn(&K) -> bool) {
+        let oldest = self
+            .frames
+            .iter()
+            .filter(|((source, _), _)| filter(source))
+            .min_by_key(|(_, pending)| pending.time)
+            .map(|(key, _)| key.clone());
+        if let Some(key) = oldest {
+            self.drop_frame(&key, "too many pending");
+        }
+    }
+
+    /// 丢弃未收齐的帧并报告
+    fn drop_frame(&mut self, key: &(K, u32), reason: &str) {
+        if let Some(pending) = self.remove(key) {
+            eprintln!(
+                "incomplete frame {} from {:?} dropped ({}): {}/{} fragments",
+                key.1,
+                key.0,
+                reason,
+                pending.received,
+                pending.fragments.len(),
+            );
+        }
+    }
+
+    /// 移除帧并扣除其字节数
+    #[inline]
+    fn remove(&mut self, key: &(K, u32)) -> Option<Pending> {
+        let pending = self.frames.remove(key)?;
+        self.bytes -= pending.bytes;
+        Some(pending)
+    }
+}
+
+#[cfg(feature = "client")]
+#[test]
+fn reassemble() {
+    use crate::{vertex, Encoder};
+
+    let build = || {
+        let mut encoder = Encoder::default();
+        encoder
+            .topic("a")
+            .extend((0..1000).map(|i| vertex!(0; i, i; 0)));
+        encoder.encode_chunks(1400).unwrap()
+    };
+    let time = Instant::now();
+    let mut frames = Reassembly::default();
+    // 乱序到达，收齐后按序返回
+    let chunks = build();
+    assert!(chunks.len() > 2);
+    for chunk in chunks.iter().skip(1) {
+        assert_eq!(frames.push(0, time, chunk.clone()), None);
+    }
+    assert_eq!(frames.push(0, time, chunks[0].clone()), Some(chunks));
+    // 超时未收齐的帧被丢弃
+    let chunks = build();
+    assert_eq!(frames.push(0, time, chunks[0].clone()), None);
+    let later = time + TIMEOUT;
+    for chunk in chunks.iter().skip(1) {
+        assert_eq!(frames.push(0, later, chunk.clone()), None);
+    }
+    // 伪造的帧数受限，最旧的帧先被丢弃，字节数与等待的帧一致
+    let mut forged = chunks[0].clone();
+    forged[11..13].copy_from_slice(&u16::MAX.to_le_bytes());
+    for frame in 0..100u32 {
+        forged[5..9].copy_from_slice(&frame.to_le_bytes());
+        assert_eq!(frames.push(2, later, forged.clone()), None);
+    }
+    let forged = frames.frames.keys().filter(|(s, _)| *s == 2).count();
+    assert_eq!(forged, MAX_FRAMES_PER_SOURCE);
+    let bytes = frames.frames.values().map(|p| p.bytes).sum::<usize>();
+    assert_eq!(frames.bytes, bytes);
+    // 无法解析的包原样返回
+    assert_eq!(
+        frames.push(1, later, vec![1, 2, 3]),
+        Some(vec![vec![1, 2, 3]])
+    );
+}
@@ -0,0 +1,291 @@
+﻿use super::{figure_program::FigureEvent, reassembly::Reassembly};
+use crate::protocol::MAX_STREAM_PACKET;
+use async_std::{
+    channel::Sender,
+    io::{Read, ReadExt},
+    net::{TcpListener, UdpSocket},
+    task,
+};
+use std::{fmt::Debug, hash::Hash, time::Instant};
+
+/// 启动 UDP 接收
+pub(super) fn spawn_udp(port: u16, sender: Sender<FigureEvent>) {
+    task::spawn(async move {
+        let socket = UdpSocket::bind(format!("0.0.0.0:{}", port)).await.unwrap();
+        let mut buf = Box::new([0u8; 65536]);
+        let mut frames = Reassembly::default();
+        while let Ok((n, source)) = socket.recv_from(buf.as_mut()).await {
+            let time = Instant::now();
+            if let Some(frame) = frames.push(source, time, buf[..n].to_vec()) {
+                let source = format!("udp {}", source);
+                let _ = sender.send(FigureEvent::Packet(time, source, frame)).await;
+            }
+        }
+    });
+}
+
+/// 启动 TCP 接收
+pub(super) fn spawn_tcp(port: u16, sender: Sender<FigureEvent>) {
+    task::spawn(async move {
+        match TcpListener::bind(format!("0.0.0.0:{}", port)).await {
+            Ok(listener) => serve_tcp(listener, sender).await,
+            Err(e) => eprintln!("failed to listen on tcp port {}: {}", port, e),
+        }
+    });
+}
+
+/// 启动 Unix 流套接字和数据报套接字接收
+#[cfg(unix)]
+pub(super) fn spawn_unix(port: u16, sender: Sender<FigureEvent>) {
+    use crate::local::{unix_datagram_path, unix_stream_path};
+    use async_std::os::unix::net::{UnixDatagram, UnixListener};
+
+    let stream_sender = sender.clone();
+    task::spawn(async move {
+        let path = unix_stream_path(port);
+        let _ = std::fs::remove_file(&path);
+        let listener = match UnixListener::bind(&path).await {
+            Ok(listener) => listener,
+            Err(e) => return eprintln!("failed to bind {:?}: {}", path, e),
+        };
+        // 本地连接通常没有地址，用连接序号区分来源
+        for source in 0usize.. {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let name = format!("unix stream {}", source);
+                    task::spawn(read_stream(stream, source, name, stream_sender.clone()));
+                }
+                Err(e) => eprintln!("failed to accept unix connection: {}", e),
+            }
+        }
+    });
+    task::spawn(async move {
+        let path = unix_datagram_path(port);
+        let _ = std::fs::remove_file(&path);
+        let socket = match UnixDatagram::bind(&path).await {
+            Ok(socket) => socket,
+            Err(e) => return eprintln!("failed to bind {:?}: {}", path, e),
+        };
+        let mut buf = vec![0u8; 1 << 20];
+        let mut frames = Reassembly::default();
+        // 按发送端绑定的路径区分来源，未绑定的发送端共用一个来源
+        while let Ok((n, source)) = socket.recv_from(&mut buf).await {
+            let time = Instant::now();
+            let source = source.as_pathname().map(|path| path.to_path_buf());
+            let name = format!("unix datagram {:?}", source);
+            if let Some(frame) = frames.push(source, time, buf[..n].to_vec()) {
+                let _ = sender.send(FigureEvent::Packet(time, name, frame)).await;
+            }
+        }
+    });
+}
+
+/// 启动共享内存接收
+#[cfg(unix)]
+pub(super) fn spawn_shm(path: std::path::PathBuf, sender: Sender<FigureEvent>) {
+    use crate::local::Ring;
+    use std::time::Duration;
+
+    /// 共享内存数据区容量
+    const CAPACITY: u32 = 16 << 20;
+    /// 无数据时的最短和最长轮询周期，空闲时逐次加倍
+    const MIN_PERIOD: Duration = Duration::from_micros(50);
+    const MAX_PERIOD: Duration = Duration::from_millis(16);
+
+    task::spawn(async move {
+        let ring = match Ring::create(&path, CAPACITY) {
+            Ok(ring) => ring,
+            Err(e) => return eprintln!("failed to create {:?}: {}", path, e),
+        };
+        let mut frames = Reassembly::default();
+        let mut period = MIN_PERIOD;
+        loop {
+            match ring.pop() {
+                // 同一进程的帧序号不重复，按进程号区分来源
+                Some((pid, buf)) => {
+                    period = MIN_PERIOD;
+                    let time = Instant::now();
+                    if let Some(frame) = frames.push(pid, time, buf) {
+                        let source = format!("shm {}", pid);
+                        let event = FigureEvent::Packet(time, source, frame);
+                        if sender.send(event).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                None => {
+                    task::sleep(period).await;
+                    period = (period * 2).min(MAX_PERIOD);
+                }
+            }
+        }
+    });
+}
+
+/// 启动 JSON 文本行接收，UDP 每个包可含多行，TCP 每个连接按行读取
+#[cfg(feature = "json")]
+pub(super) fn spawn_text(port: u16, sender: Sender<FigureEvent>) {
+    use async_std::{io::BufReader, prelude::*};
+
+    let udp_sender = sender.clone();
+    task::spawn(async move {
+        let socket = match UdpSocket::bind(format!("0.0.0.0:{}", port)).await {
+            Ok(socket) => socket,
+            Err(e) => return eprintln!("failed to bind udp port {}: {}", port, e),
+        };
+        let mut buf = Box::new([0u8; 65536]);
+        while let Ok((n, source)) = socket.recv_from(buf.as_mut()).await {
+            let text = String::from_utf8_lossy(&buf[..n]);
+            let name = format!("text udp {}", source);
+            for (i, line) in text.lines().enumerate() {
+                let at = format!("{} line {}", source, i + 1);
+                send_line(line, &name, &at, &udp_sender).await;
+            }
+        }
+    });
+    task::spawn(async move {
+        let listener = match TcpListener::bind(format!("0.0.0.0:{}", port)).await {
+            Ok(listener) => listener,
+            Err(e) => return eprintln!("failed to listen on tcp port {}: {}", port, e),
+        };
+        loop {
+            let (stream, source) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    eprintln!("failed to accept tcp connection: {}", e);
+                    continue;
+                }
+            };
+            let sender = sender.clone();
+            task::spawn(async move {
+                let name = format!("text tcp {}", source);
+                let mut lines = BufReader::new(stream).lines();
+                let mut i = 0;
+                while let Some(Ok(line)) = lines.next().await {
+                    i += 1;
+                    let at = format!("{} line {}", source, i);
+                    send_line(&line, &name, &at, &sender).await;
+                }
+            });
+        }
+    });
+}
+
+/// 解析来自 `source` 的一行 JSON 并发送，空行忽略，错误按行位置 `at` 报告
+#[cfg(feature = "json")]
+pub(super) async fn send_line(line: &str, source: &str, at: &str, sender: &Sender<FigureEvent>) {
+    if line.trim().is_empty() {
+        return;
+    }
+    match crate::protocol::parse_line(line) {
+        Ok(packet) => {
+            let event = FigureEvent::Text(Instant::now(), source.into(), packet);
+            let _ = sender.send(event).await;
+        }
+        Err(e) => eprintln!("{}: {}", at, e),
+    }
+}
+
+/// 接受 TCP 连接，每个连接一个任务
+async fn serve_tcp(listener: TcpListener, sender: Sender<FigureEvent>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, source)) => {
+                let name = format!("tcp {}", source);
+                task::spawn(read_stream(stream, source, name, sender.clone()));
+            }
+            Err(e) => eprintln!("failed to accept tcp connection: {}", e),
+        }
+    }
+}
+
+/// 从流中读取带长度前缀的包：| 长度: u32 | 包 |，`name` 是报告给画面的来源
+async fn read_stream<R, K>(mut stream: R, source: K, name: String, sender: Sender<FigureEvent>)
+where
+    R: Read + Unpin,
+    K: Hash + Eq + Copy + Debug,
+{
+    let mut frames = Reassembly::default();
+    loop {
+        let mut len = [0u8; 4];
+        if stream.read_exact(&mut len).await.is_err() {
+            break;
+        }
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_STREAM_PACKET {
+            eprintln!("packet of {} bytes from {:?} is too large", len, source);
+            break;
+        }
+        let mut buf = vec![0u8; len];
+        if stream.read_exact(&mut buf).await.is_err() {
+            break;
+        }
+        let time = Instant::now();
+        if let Some(frame) = frames.push(source, time, buf) {
+            let event = FigureEvent::Packet(time, name.clone(), frame);
+            if sender.send(event).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+#[test]
+fn tcp_loopback() {
+    use crate::{vertex, Encoder, TcpClient};
+    use async_std::{channel::unbounded, future::timeout};
+    use std::time::Duration;
+
+    let (sender, receiver) = unbounded();
+    let listener = task::block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+    let addr = listener.local_addr().unwrap();
+    task::spawn(serve_tcp(listener, sender));
+
+    let mut client = TcpClient::connect(addr).unwrap();
+    let mut encoder = Encoder::default();
+    encoder
+        .topic("a")
+        .extend((0..1000).map(|i| vertex!(0; i, i; 0)));
+    let chunks = encoder.encode_chunks(1400).unwrap();
+    for chunk in &chunks {
+        client.send(chunk).unwrap();
+    }
+    // 分片收齐后作为一帧到达
+    let event = task::block_on(timeout(Duration::from_secs(5), receiver.recv()));
+    match event {
+        Ok(Ok(FigureEvent::Packet(_, _, frame))) => assert_eq!(frame, chunks),
+        _ => panic!("frame not received"),
+    }
+}
+
+#[cfg(all(unix, feature = "client"))]
+#[test]
+fn shm_loopback() {
+    use crate::{vertex, Encoder, ShmClient};
+    use async_std::{channel::unbounded, future::timeout};
+    use std::time::Duration;
+
+    // 每个测试进程用自己的路径，不与实际监视器和并行的测试冲突
+    let path = std::env::temp_dir().join(format!("monitor-tool-shm-test-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let (sender, receiver) = unbounded();
+    spawn_shm(path.clone(), sender);
+    let client = loop {
+        if let Ok(client) = ShmClient::open_path(&path) {
+            break client;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    let mut encoder = Encoder::default();
+    encoder
+        .topic("a")
+        .extend((0..10000).map(|i| vertex!(0; i, i; 0)));
+    client.send_encoder(encoder).unwrap();
+    let event = task::block_on(timeout(Duration::from_secs(5), receiver.recv()));
+    match event {
+        Ok(Ok(FigureEvent::Packet(_, _, frame))) => assert_eq!(frame.len(), 3),
+        _ => panic!("frame not received"),
+    }
+    let _ = std::fs::remove_file(path);
+}
@@ -0,0 +1,117 @@
+﻿use super::{length_prefix, MAX_DATAGRAM};
+use crate::{Encoder, MAX_STREAM_PACKET};
+use std::io::Result;
+
+/// 异步运行时提供的套接字操作
+pub(super) trait Runtime {
+    type Udp;
+    type Tcp;
+
+    /// 发送一个数据报
+    async fn send(socket: &Self::Udp, packet: &[u8]) -> Result<()>;
+    /// 写入流
+    async fn write_all(stream: &mut Self::Tcp, buf: &[u8]) -> Result<()>;
+    /// 发出流中缓冲的数据
+    async fn flush(stream: &mut Self::Tcp) -> Result<()>;
+    /// 关闭流的写端
+    async fn shutdown(stream: &mut Self::Tcp) -> Result<()>;
+}
+
+/// 与运行时无关的分帧发送，UDP 每包一个数据报，TCP 每包带长度前缀
+pub(super) enum Transport<R: Runtime> {
+    Udp(R::Udp),
+    Tcp(R::Tcp),
+}
+
+impl<R: Runtime> Transport<R> {
+    /// 发送一个编码好的包
+    pub async fn send(&mut self, packet: &[u8]) -> Result<()> {
+        self.write(packet).await?;
+        self.flush().await
+    }
+
+    /// 编码并作为一帧发送
+    pub async fn send_frame(&mut self, encoder: Encoder) -> Result<()> {
+        let max_len = match self {
+            Transport::Udp(_) => MAX_DATAGRAM,
+            Transport::Tcp(_) => MAX_STREAM_PACKET,
+        };
+        for chunk in encoder.encode_chunks(max_len)? {
+            self.write(&chunk).await?;
+        }
+        self.flush().await
+    }
+
+    /// 发出缓冲的数据后关闭连接
+    pub async fn close(mut self) -> Result<()> {
+        self.flush().await?;
+        match &mut self {
+            Transport::Udp(_) => Ok(()),
+            Transport::Tcp(stream) => R::shutdown(stream).await,
+        }
+    }
+
+    async fn write(&mut self, packet: &[u8]) -> Result<()> {
+        match self {
+            Transport::Udp(socket) => R::send(socket, packet).await,
+            Transport::Tcp(stream) => {
+                R::write_all(stream, &length_prefix(packet)?).await?;
+                R::write_all(stream, packet).await
+            }
+        }
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        match self {
+            Transport::Udp(_) => Ok(()),
+            Transport::Tcp(stream) => R::flush(stream).await,
+        }
+    }
+}
+
+/// 测试用的一帧
+#[cfg(test)]
+pub(super) fn test_frame() -> Encoder {
+    use crate::vertex;
+
+    let mut encoder = Encoder::default();
+    encoder.topic("a").push(vertex!(0; 1, 2 => 0.0; 0));
+    encoder
+}
+
+/// 用 `send` 经 UDP 和 TCP 各发送一次 [`test_frame`]，检查收到的内容
+#[cfg(test)]
+pub(super) fn check_transports(send: impl Fn(std::net::SocketAddr, bool)) {
+    use crate::{vertex, Packet};
+    use std::{
+        io::Read,
+        net::{TcpListener, UdpSocket},
+        thread,
+        time::Duration,
+    };
+
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    send(receiver.local_addr().unwrap(), false);
+    let mut buf = [0u8; 65536];
+    let n = receiver.recv(&mut buf).unwrap();
+    let packet = Packet::parse(&buf[..n]).unwrap();
+    assert_eq!(packet.topics[0].vertex, [vertex!(0; 1, 2 => 0.0; 0)]);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let mut buf = Vec::new();
+        listener.accept().unwrap().0.read_to_end(&mut buf).unwrap();
+        buf
+    });
+    send(addr, true);
+    // 关闭后服务端读到完整的一帧
+    let buf = server.join().unwrap();
+    let len = u32::from_le_bytes(buf[..4].try_into().unwrap()) as usize;
+    assert_eq!(buf.len(), 4 + len);
+    let packet = Packet::parse(&buf[4..]).unwrap();
+    assert_eq!(packet.topics[0].vertex, [vertex!(0; 1, 2 => 0.0; 0)]);
+}
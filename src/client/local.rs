@@ -0,0 +1,123 @@
+﻿use super::{write_packet, MAX_DATAGRAM};
+use crate::{
+    local::{shm_path, unix_datagram_path, unix_stream_path, Ring},
+    Encoder, MAX_STREAM_PACKET,
+};
+use std::{
+    io::{BufWriter, Error, ErrorKind, Result},
+    os::unix::net::{UnixDatagram, UnixStream},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+/// 通过 Unix 流套接字向同一主机上的监视器可靠地发送包
+pub struct UnixClient(BufWriter<UnixStream>);
+
+/// 通过 Unix 数据报套接字向同一主机上的监视器发送包
+///
+/// 套接字绑定到自己的路径，监视器据此区分来源重组分片，释放时删除。
+pub struct UnixDatagramClient {
+    socket: UnixDatagram,
+    path: PathBuf,
+}
+
+/// 通过共享内存环形缓冲区向同一主机上的监视器发送包
+///
+/// 缓冲区满时立即返回 [`ErrorKind::WouldBlock`]，不会阻塞调用者。
+pub struct ShmClient(Ring);
+
+impl UnixClient {
+    /// 连接到以 `port` 启动的监视器
+    #[inline]
+    pub fn connect(port: u16) -> Result<Self> {
+        UnixStream::connect(unix_stream_path(port)).map(|s| Self(BufWriter::new(s)))
+    }
+
+    /// 发送一个编码好的包
+    #[inline]
+    pub fn send(&mut self, packet: &[u8]) -> Result<()> {
+        write_packet(&mut self.0, packet)
+    }
+
+    /// 编码并发送
+    #[inline]
+    pub fn send_encoder(&mut self, encoder: Encoder) -> Result<()> {
+        for chunk in encoder.encode_chunks(MAX_STREAM_PACKET)? {
+            self.send(&chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl UnixDatagramClient {
+    /// 连接到以 `port` 启动的监视器
+    pub fn connect(port: u16) -> Result<Self> {
+        static NEXT: AtomicU32 = AtomicU32::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "monitor-tool-{}-{}-{}.dgram",
+            port,
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed),
+        ));
+        // 同号的旧进程可能留下文件
+        let _ = std::fs::remove_file(&path);
+        let socket = UnixDatagram::bind(&path)?;
+        let client = Self { socket, path };
+        client.socket.connect(unix_datagram_path(port))?;
+        Ok(client)
+    }
+
+    /// 发送一个编码好的包
+    #[inline]
+    pub fn send(&self, packet: &[u8]) -> Result<()> {
+        self.socket.send(packet).map(|_| ())
+    }
+
+    /// 编码并发送
+    #[inline]
+    pub fn send_encoder(&self, encoder: Encoder) -> Result<()> {
+        for chunk in encoder.encode_chunks(MAX_DATAGRAM)? {
+            self.send(&chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for UnixDatagramClient {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl ShmClient {
+    /// 打开以 `port` 启动的监视器创建的共享内存
+    #[inline]
+    pub fn open(port: u16) -> Result<Self> {
+        Self::open_path(shm_path(port))
+    }
+
+    /// 打开指定路径的共享内存，用于不按端口号命名的监视器
+    #[inline]
+    pub fn open_path(path: impl AsRef<Path>) -> Result<Self> {
+        Ring::open(path.as_ref()).map(Self)
+    }
+
+    /// 发送一个编码好的包
+    #[inline]
+    pub fn send(&self, packet: &[u8]) -> Result<()> {
+        if self.0.push(packet) {
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::WouldBlock, "shared memory is full"))
+        }
+    }
+
+    /// 编码并发送
+    #[inline]
+    pub fn send_encoder(&self, encoder: Encoder) -> Result<()> {
+        for chunk in encoder.encode_chunks(MAX_DATAGRAM)? {
+            self.send(&chunk)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,42 @@
+﻿use crate::MAX_STREAM_PACKET;
+use std::io::{Error, ErrorKind, Result, Write};
+
+#[cfg(feature = "client-async-std")]
+mod async_std;
+#[cfg(any(feature = "client-async-std", feature = "client-tokio"))]
+mod framed;
+#[cfg(unix)]
+mod local;
+mod monitor;
+mod tcp;
+#[cfg(feature = "client-tokio")]
+mod tokio;
+
+#[cfg(feature = "client-async-std")]
+pub use self::async_std::AsyncStdClient;
+#[cfg(feature = "client-tokio")]
+pub use self::tokio::TokioClient;
+#[cfg(unix)]
+pub use local::{ShmClient, UnixClient, UnixDatagramClient};
+pub use monitor::{ClientStats, MonitorClient};
+pub use tcp::TcpClient;
+
+/// 数据报传输时每个包的最大长度
+const MAX_DATAGRAM: usize = 65000;
+
+/// 向流写入带长度前缀的包：| 长度: u32 | 包 |
+fn write_packet(stream: &mut impl Write, packet: &[u8]) -> Result<()> {
+    stream.write_all(&length_prefix(packet)?)?;
+    stream.write_all(packet)?;
+    stream.flush()
+}
+
+/// 流传输时包的长度前缀
+#[inline]
+fn length_prefix(packet: &[u8]) -> Result<[u8; 4]> {
+    if packet.len() > MAX_STREAM_PACKET {
+        Err(Error::new(ErrorKind::InvalidInput, "packet too large"))
+    } else {
+        Ok((packet.len() as u32).to_le_bytes())
+    }
+}
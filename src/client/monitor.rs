@@ -0,0 +1,352 @@
+﻿use super::MAX_DATAGRAM;
+use crate::{Encoder, TopicEncoder};
+use std::{
+    io::{Error, ErrorKind, Result},
+    net::{ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// 默认发送周期
+const PERIOD: Duration = Duration::from_millis(20);
+/// 默认攒够这么多字节立即发送
+const THRESHOLD: usize = 1 << 20;
+/// 通道中最多积压的命令数
+const QUEUE: usize = 1024;
+
+/// 常驻的监视器客户端
+///
+/// 任意线程的写入经有界通道交给后台线程合并，按周期或攒够编码长度后编码为一帧发送。
+/// 写入只在本地构造编码器，不等待网络，适合在实时线程中调用；通道积压满时丢弃写入并计数。
+pub struct MonitorClient {
+    sender: Option<SyncSender<Command>>,
+    counters: Arc<Counters>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// 发送统计
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct ClientStats {
+    pub writes: u64,  // 写入次数
+    pub dropped: u64, // 通道积压满而丢弃的写入次数
+    pub frames: u64,  // 发送的帧数
+    pub packets: u64, // 发送成功的包数
+    pub bytes: u64,   // 发送成功的字节数
+    pub errors: u64,  // 发送失败的包数
+}
+
+enum Command {
+    Write(Box<Encoder>),
+    Flush,
+}
+
+#[derive(Default)]
+struct Counters {
+    writes: AtomicU64,
+    dropped: AtomicU64,
+    frames: AtomicU64,
+    packets: AtomicU64,
+    bytes: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl MonitorClient {
+    /// 连接到监视器，使用默认的发送周期和阈值
+    #[inline]
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+        Self::connect_with(addr, PERIOD, THRESHOLD)
+    }
+
+    /// 连接到监视器，每隔 `period` 或合并内容编码后超过 `threshold` 字节时发送一次
+    pub fn connect_with(
+        addr: impl ToSocketAddrs,
+        period: Duration,
+        threshold: usize,
+    ) -> Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(addr)?;
+        let (sender, receiver) = mpsc::sync_channel(QUEUE);
+        let counters = Arc::new(Counters::default());
+        let thread = {
+            let counters = counters.clone();
+            thread::Builder::new()
+                .name("monitor-client".into())
+                .spawn(move || run(socket, receiver, period, threshold, &counters))?
+        };
+        Ok(Self {
+            sender: Some(sender),
+            counters,
+            thread: Some(thread),
+        })
+    }
+
+    /// 写入
+    ///
+    /// 通道积压满时丢弃这次写入并计数，后台线程已经退出时返回错误。
+    pub fn write(&self, f: impl FnOnce(&mut Encoder)) -> Result<()> {
+        let mut encoder = Encoder::default();
+        f(&mut encoder);
+        if encoder.is_empty() {
+            return Ok(());
+        }
+        self.counters.writes.fetch_add(1, Ordering::Relaxed);
+        match self.send(Command::Write(Box::new(encoder))) {
+            Err(TrySendError::Full(_)) => {
+                self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            result => result.map_err(|_| disconnected()),
+        }
+    }
+
+    /// 写入一个话题
+    #[inline]
+    pub fn topic(&self, topic: impl ToString, f: impl FnOnce(TopicEncoder)) -> Result<()> {
+        self.write(|encoder| encoder.with_topic(topic, f))
+    }
+
+    /// 要求后台线程立即发送已合并的内容
+    ///
+    /// 通道积压满时后台线程正忙于处理，忽略这次请求；后台线程已经退出时返回错误。
+    #[inline]
+    pub fn flush(&self) -> Result<()> {
+        match self.send(Command::Flush) {
+            Err(TrySendError::Disconnected(_)) => Err(disconnected()),
+            _ => Ok(()),
+        }
+    }
+
+    /// 发送统计
+    pub fn stats(&self) -> ClientStats {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let counters = self.counters.as_ref();
+        ClientStats {
+            writes: load(&counters.writes),
+            dropped: load(&counters.dropped),
+            frames: load(&counters.frames),
+            packets: load(&counters.packets),
+            bytes: load(&counters.bytes),
+            errors: load(&counters.errors),
+        }
+    }
+
+    /// 不阻塞地放入通道
+    #[inline]
+    fn send(&self, command: Command) -> std::result::Result<(), TrySendError<Command>> {
+        match &self.sender {
+            Some(sender) => sender.try_send(command),
+            None => Err(TrySendError::Disconnected(command)),
+        }
+    }
+}
+
+impl Drop for MonitorClient {
+    /// 发送剩余内容后结束后台线程
+    fn drop(&mut self) {
+        self.sender = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// 后台线程已经退出
+#[inline]
+fn disconnected() -> Error {
+    Error::new(ErrorKind::BrokenPipe, "monitor client thread exited")
+}
+
+/// 后台线程：合并写入，到期或超过阈值时发送
+fn run(
+    socket: UdpSocket,
+    receiver: Receiver<Command>,
+    period: Duration,
+    threshold: usize,
+    counters: &Counters,
+) {
+    let mut pending = Encoder::default();
+    let mut len = 0;
+    let mut deadline = Instant::now() + period;
+    loop {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let flush = match receiver.recv_timeout(timeout) {
+            Ok(Command::Write(encoder)) => {
+                let n = encoder.content_len();
+                // 源时间不同的写入不能合并，先发出已合并的内容
+                if let Err(encoder) = pending.merge(*encoder) {
+                    send_frame(&socket, std::mem::replace(&mut pending, *encoder), counters);
+                    len = 0;
+                    deadline = Instant::now() + period;
+                }
+                len += n;
+                len >= threshold
+            }
+            Ok(Command::Flush) | Err(RecvTimeoutError::Timeout) => true,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        if flush {
+            send_frame(&socket, std::mem::take(&mut pending), counters);
+            len = 0;
+            deadline = Instant::now() + period;
+        }
+    }
+    send_frame(&socket, pending, counters);
+}
+
+/// 编码并发送一帧
+fn send_frame(socket: &UdpSocket, encoder: Encoder, counters: &Counters) {
+    if encoder.is_empty() {
+        return;
+    }
+    counters.frames.fetch_add(1, Ordering::Relaxed);
+    let Ok(chunks) = encoder.encode_chunks(MAX_DATAGRAM) else {
+        counters.errors.fetch_add(1, Ordering::Relaxed);
+        return;
+    };
+    for chunk in chunks {
+        match socket.send(&chunk) {
+            Ok(n) => {
+                counters.packets.fetch_add(1, Ordering::Relaxed);
+                counters.bytes.fetch_add(n as u64, Ordering::Relaxed);
+            }
+            Err(_) => {
+                counters.errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[test]
+fn batch_and_coalesce() {
+    use crate::{vertex, Packet};
+
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let client = MonitorClient::connect_with(
+        receiver.local_addr().unwrap(),
+        Duration::from_secs(3600),
+        usize::MAX,
+    )
+    .unwrap();
+    // 多个线程重复配置并写入顶点
+    thread::scope(|s| {
+        for t in 0..4 {
+            let client = &client;
+            s.spawn(move || {
+                for i in 0..100 {
+                    client
+                        .write(|encoder| {
+                            encoder.layer("layer", &["a"], Some(true));
+                            encoder.config_topic("a", 1000, 10, &[], |topic| {
+                                topic.push(vertex!(t; i, i; 0));
+                            });
+                        })
+                        .unwrap();
+                }
+            });
+        }
+    });
+    client.flush().unwrap();
+    let mut buf = [0u8; 65536];
+    let n = receiver.recv(&mut buf).unwrap();
+    let packet = Packet::parse(&buf[..n]).unwrap();
+    assert_eq!(packet.layers.len(), 1);
+    assert_eq!(packet.topics.len(), 1);
+    assert_eq!(packet.topics[0].layer.as_deref(), Some("layer"));
+    assert_eq!(packet.topics[0].capacity, 1000);
+    assert_eq!(packet.topics[0].vertex.len(), 400);
+    // 统计在发送后更新
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while client.stats().packets == 0 && Instant::now() < deadline {
+        thread::yield_now();
+    }
+    assert_eq!(
+        client.stats(),
+        ClientStats {
+            writes: 400,
+            dropped: 0,
+            frames: 1,
+            packets: 1,
+            bytes: n as u64,
+            errors: 0,
+        }
+    );
+    // 源时间不同的写入分帧发送，各自保留源时间
+    let times = [1, 2].map(|s| std::time::UNIX_EPOCH + Duration::from_secs(s));
+    for time in times {
+        client
+            .write(|encoder| {
+                encoder.set_time(time);
+                encoder.topic("a").push(vertex!(0; 0, 0; 0));
+            })
+            .unwrap();
+    }
+    client.flush().unwrap();
+    for time in times {
+        let n = receiver.recv(&mut buf).unwrap();
+        assert_eq!(Packet::parse(&buf[..n]).unwrap().time, Some(time));
+    }
+}
+
+#[test]
+fn flush_by_encoded_len() {
+    use crate::{Grid, Packet, Tile};
+
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let client = MonitorClient::connect_with(
+        receiver.local_addr().unwrap(),
+        Duration::from_secs(3600),
+        1000,
+    )
+    .unwrap();
+    // 没有顶点的栅格块也按编码长度触发发送
+    let grid = Grid {
+        origin: [0.0, 0.0],
+        resolution: 0.1,
+        width: 40,
+        height: 40,
+    };
+    client
+        .topic("map", |mut topic| {
+            topic.push_tile(Tile::whole(grid, vec![], vec![0u8; 1600]))
+        })
+        .unwrap();
+    let mut buf = [0u8; 65536];
+    let n = receiver.recv(&mut buf).unwrap();
+    let packet = Packet::parse(&buf[..n]).unwrap();
+    assert_eq!(
+        packet.topics.iter().map(|t| t.tiles.len()).sum::<usize>(),
+        1
+    );
+}
+
+#[test]
+fn drop_or_fail() {
+    use crate::vertex;
+
+    let (sender, receiver) = mpsc::sync_channel(1);
+    let client = MonitorClient {
+        sender: Some(sender),
+        counters: Default::default(),
+        thread: None,
+    };
+    // 通道满时丢弃并计数，后台线程退出后报告错误
+    let write = || client.topic("a", |mut topic| topic.push(vertex!(0; 0, 0; 0)));
+    write().unwrap();
+    write().unwrap();
+    assert_eq!(client.stats().writes, 2);
+    assert_eq!(client.stats().dropped, 1);
+    drop(receiver);
+    assert_eq!(write().unwrap_err().kind(), ErrorKind::BrokenPipe);
+    assert_eq!(client.flush().unwrap_err().kind(), ErrorKind::BrokenPipe);
+}
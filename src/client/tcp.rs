@@ -0,0 +1,35 @@
+﻿use super::write_packet;
+use crate::{Encoder, MAX_STREAM_PACKET};
+use std::{
+    io::{BufWriter, Result},
+    net::{TcpStream, ToSocketAddrs},
+};
+
+/// 通过 TCP 连接可靠地发送包
+///
+/// 每个包前加长度前缀：| 长度: u32 | 包 |
+pub struct TcpClient(BufWriter<TcpStream>);
+
+impl TcpClient {
+    /// 连接到监视器
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(Self(BufWriter::new(stream)))
+    }
+
+    /// 发送一个编码好的包
+    #[inline]
+    pub fn send(&mut self, packet: &[u8]) -> Result<()> {
+        write_packet(&mut self.0, packet)
+    }
+
+    /// 编码并发送
+    #[inline]
+    pub fn send_encoder(&mut self, encoder: Encoder) -> Result<()> {
+        for chunk in encoder.encode_chunks(MAX_STREAM_PACKET)? {
+            self.send(&chunk)?;
+        }
+        Ok(())
+    }
+}
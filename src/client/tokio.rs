@@ -0,0 +1,91 @@
+﻿use super::framed::{Runtime, Transport};
+use crate::Encoder;
+use std::io::Result;
+use tokio::{
+    io::{AsyncWriteExt, BufWriter},
+    net::{TcpStream, ToSocketAddrs, UdpSocket},
+};
+
+/// 基于 tokio 的异步客户端
+///
+/// 每次 [`send_frame`](Self::send_frame) 把一个编码器作为一帧发出，
+/// 写入完成才返回，发送跟不上时调用者自然被阻挡。
+pub struct TokioClient(Transport<Tokio>);
+
+enum Tokio {}
+
+impl Runtime for Tokio {
+    type Udp = UdpSocket;
+    type Tcp = BufWriter<TcpStream>;
+
+    async fn send(socket: &UdpSocket, packet: &[u8]) -> Result<()> {
+        socket.send(packet).await.map(|_| ())
+    }
+
+    async fn write_all(stream: &mut Self::Tcp, buf: &[u8]) -> Result<()> {
+        stream.write_all(buf).await
+    }
+
+    async fn flush(stream: &mut Self::Tcp) -> Result<()> {
+        stream.flush().await
+    }
+
+    async fn shutdown(stream: &mut Self::Tcp) -> Result<()> {
+        stream.shutdown().await
+    }
+}
+
+impl TokioClient {
+    /// 通过 UDP 连接到监视器
+    pub async fn connect_udp(addr: impl ToSocketAddrs) -> Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.connect(addr).await?;
+        Ok(Self(Transport::Udp(socket)))
+    }
+
+    /// 通过 TCP 连接到监视器
+    pub async fn connect_tcp(addr: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        Ok(Self(Transport::Tcp(BufWriter::new(stream))))
+    }
+
+    /// 发送一个编码好的包
+    #[inline]
+    pub async fn send(&mut self, packet: &[u8]) -> Result<()> {
+        self.0.send(packet).await
+    }
+
+    /// 编码并作为一帧发送
+    #[inline]
+    pub async fn send_frame(&mut self, encoder: Encoder) -> Result<()> {
+        self.0.send_frame(encoder).await
+    }
+
+    /// 发出缓冲的数据后关闭连接
+    #[inline]
+    pub async fn close(self) -> Result<()> {
+        self.0.close().await
+    }
+}
+
+#[test]
+fn tokio_client() {
+    use super::framed::{check_transports, test_frame};
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build()
+        .unwrap();
+    check_transports(|addr, tcp| {
+        runtime.block_on(async {
+            let mut client = if tcp {
+                TokioClient::connect_tcp(addr).await.unwrap()
+            } else {
+                TokioClient::connect_udp(addr).await.unwrap()
+            };
+            client.send_frame(test_frame()).await.unwrap();
+            client.close().await.unwrap();
+        })
+    });
+}
@@ -11,6 +11,18 @@ mod protocol;
 #[cfg(feature = "client")]
 pub use protocol::*;
 
+#[cfg(unix)]
+mod local;
+
+#[cfg(all(unix, feature = "client"))]
+pub use local::{shm_path, unix_datagram_path, unix_stream_path};
+
+#[cfg(feature = "client")]
+mod client;
+
+#[cfg(feature = "client")]
+pub use client::*;
+
 #[cfg(feature = "client")]
 pub extern crate palette;
 
@@ -58,16 +70,18 @@ pub struct Vertex {
     pub extra: f32,   // 补充数据
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+#[repr(u8)]
 pub enum Shape {
-    Arrow,
-    Circle,
-}
-
-impl Default for Shape {
-    fn default() -> Self {
-        Self::Arrow
-    }
+    #[default]
+    Arrow = 0,
+    Circle = 1,
+    Text = 2,
+    Rect = 3,
+    OrientedBox = 4,
+    Polygon = 5,
+    Ellipse = 6,
+    Vector = 7,
 }
 
 impl std::fmt::Display for Shape {
@@ -75,6 +89,12 @@ impl std::fmt::Display for Shape {
         match *self {
             Self::Arrow => write!(f, "arrow "),
             Self::Circle => write!(f, "circle"),
+            Self::Text => write!(f, "text  "),
+            Self::Rect => write!(f, "rect  "),
+            Self::OrientedBox => write!(f, "obb   "),
+            Self::Polygon => write!(f, "poly  "),
+            Self::Ellipse => write!(f, "oval  "),
+            Self::Vector => write!(f, "vector"),
         }
     }
 }
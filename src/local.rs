@@ -0,0 +1,305 @@
+﻿use std::{
+    fs::{File, OpenOptions},
+    io::{Error, Result},
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+};
+
+/// 共享内存魔数
+const MAGIC: [u8; 4] = *b"MTSH";
+
+/// 头部长度，之后是数据区
+const HEAD_LEN: usize = 64;
+
+/// 每条记录的长度和进程号
+const RECORD_HEAD_LEN: usize = 8;
+
+/// 取写锁的最多尝试次数，之后放弃或接管已退出进程持有的锁
+#[cfg(feature = "client")]
+const LOCK_TRIES: usize = 1000;
+
+/// Unix 流套接字路径
+#[inline]
+pub fn unix_stream_path(port: u16) -> PathBuf {
+    std::env::temp_dir().join(format!("monitor-tool-{}.sock", port))
+}
+
+/// Unix 数据报套接字路径
+#[inline]
+pub fn unix_datagram_path(port: u16) -> PathBuf {
+    std::env::temp_dir().join(format!("monitor-tool-{}.dgram", port))
+}
+
+/// 共享内存环形缓冲区路径
+pub fn shm_path(port: u16) -> PathBuf {
+    let dir = Path::new("/dev/shm");
+    let dir = if dir.is_dir() {
+        dir.to_path_buf()
+    } else {
+        std::env::temp_dir()
+    };
+    dir.join(format!("monitor-tool-{}", port))
+}
+
+/// 映射到共享内存的多生产者、单消费者字节环
+///
+/// 头部：| 魔数: [u8; 4] | 容量: u32 | 写位置: u64 | 读位置: u64 | 写锁: u32 |
+///
+/// 数据区中每条记录为 | 长度: u32 | 生产者进程号: u32 | 包 |，可能跨越数据区末尾。
+/// 同一进程的帧序号不重复，监视器按进程号区分来源重组分片。
+/// 读写位置单调递增，对容量取模得到数据区偏移。
+/// 写锁为 0 或持锁进程的进程号；头部在共享内存中，读写两端都不信任其中的值。
+pub(crate) struct Ring {
+    ptr: *mut u8,
+    capacity: usize,
+    _file: File,
+}
+
+unsafe impl Send for Ring {}
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    /// 创建或重置环，由监视器调用
+    #[cfg(feature = "app")]
+    pub fn create(path: &Path, capacity: u32) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        file.set_len((HEAD_LEN + capacity as usize) as u64)?;
+        let ring = Self::map(file, capacity as usize)?;
+        ring.head().store(0, Ordering::SeqCst);
+        ring.tail().store(0, Ordering::SeqCst);
+        ring.lock().store(0, Ordering::SeqCst);
+        unsafe {
+            ring.ptr.add(4).cast::<u32>().write(capacity.to_le());
+            ring.ptr.cast::<[u8; 4]>().write(MAGIC);
+        }
+        Ok(ring)
+    }
+
+    /// 打开监视器创建的环，由生产者调用
+    #[cfg(feature = "client")]
+    pub fn open(path: &Path) -> Result<Self> {
+        use std::io::ErrorKind::InvalidData;
+
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len <= HEAD_LEN {
+            return Err(Error::new(InvalidData, "shared memory too small"));
+        }
+        let ring = Self::map(file, len - HEAD_LEN)?;
+        let (magic, capacity) = unsafe {
+            (
+                ring.ptr.cast::<[u8; 4]>().read(),
+                u32::from_le(ring.ptr.add(4).cast::<u32>().read()) as usize,
+            )
+        };
+        if magic != MAGIC || capacity != ring.capacity {
+            return Err(Error::new(InvalidData, "not a monitor-tool ring"));
+        }
+        Ok(ring)
+    }
+
+    /// 写入一个包，空间不足、取不到写锁或位置损坏时返回 `false` 而不等待
+    #[cfg(feature = "client")]
+    pub fn push(&self, packet: &[u8]) -> bool {
+        let len = RECORD_HEAD_LEN + packet.len();
+        if len > self.capacity || !self.try_lock() {
+            return false;
+        }
+        let head = self.head().load(Ordering::Relaxed);
+        let tail = self.tail().load(Ordering::Acquire);
+        // 位置损坏时不写入，由监视器重置
+        let pushed = self
+            .used(head, tail)
+            .is_some_and(|used| self.capacity - used >= len);
+        if pushed {
+            self.write(head, &(packet.len() as u32).to_le_bytes());
+            self.write(head + 4, &std::process::id().to_le_bytes());
+            self.write(head + RECORD_HEAD_LEN as u64, packet);
+            self.head().store(head + len as u64, Ordering::Release);
+        }
+        self.lock().store(0, Ordering::Release);
+        pushed
+    }
+
+    /// 读出一个包及其生产者的进程号，环为空时返回 `None`
+    #[cfg(feature = "app")]
+    pub fn pop(&self) -> Option<(u32, Vec<u8>)> {
+        let tail = self.tail().load(Ordering::Relaxed);
+        let head = self.head().load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        // 数据损坏时丢弃所有未读数据
+        const HEAD: u64 = RECORD_HEAD_LEN as u64;
+        let used = match self.used(head, tail) {
+            Some(used) if used >= RECORD_HEAD_LEN => used as u64,
+            _ => return self.reset(head, tail),
+        };
+        let mut record = [0u8; RECORD_HEAD_LEN];
+        self.read(tail, &mut record);
+        let len = u32::from_le_bytes(record[..4].try_into().unwrap()) as u64;
+        let pid = u32::from_le_bytes(record[4..].try_into().unwrap());
+        if len > self.capacity as u64 - HEAD || used < HEAD + len {
+            return self.reset(head, tail);
+        }
+        let mut buf = vec![0u8; len as usize];
+        self.read(tail + HEAD, &mut buf);
+        self.tail().store(tail + HEAD + len, Ordering::Release);
+        Some((pid, buf))
+    }
+
+    /// 丢弃所有未读数据
+    #[cfg(feature = "app")]
+    fn reset(&self, head: u64, tail: u64) -> Option<(u32, Vec<u8>)> {
+        eprintln!(
+            "shared memory ring corrupted (head {}, tail {}), unread data dropped",
+            head, tail
+        );
+        self.tail().store(head, Ordering::Release);
+        None
+    }
+
+    /// 未读的字节数，位置损坏时返回 `None`
+    #[inline]
+    fn used(&self, head: u64, tail: u64) -> Option<usize> {
+        head.checked_sub(tail)
+            .filter(|used| *used <= self.capacity as u64)
+            .map(|used| used as usize)
+    }
+
+    /// 有限次地尝试取写锁，持锁的进程已经退出时接管
+    #[cfg(feature = "client")]
+    fn try_lock(&self) -> bool {
+        let me = std::process::id();
+        for i in 0..LOCK_TRIES {
+            match self
+                .lock()
+                .compare_exchange_weak(0, me, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => return true,
+                Err(_) if i < LOCK_TRIES / 10 => std::hint::spin_loop(),
+                Err(_) => std::thread::yield_now(),
+            }
+        }
+        let holder = self.lock().load(Ordering::Relaxed);
+        let dead = (1..=i32::MAX as u32).contains(&holder)
+            && unsafe { libc::kill(holder as libc::pid_t, 0) } != 0
+            && Error::last_os_error().raw_os_error() == Some(libc::ESRCH);
+        dead && self
+            .lock()
+            .compare_exchange(holder, me, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    fn map(file: File, capacity: usize) -> Result<Self> {
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                HEAD_LEN + capacity,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            Err(Error::last_os_error())
+        } else {
+            Ok(Self {
+                ptr: ptr.cast(),
+                capacity,
+                _file: file,
+            })
+        }
+    }
+
+    #[inline]
+    fn head(&self) -> &AtomicU64 {
+        unsafe { &*self.ptr.add(8).cast() }
+    }
+
+    #[inline]
+    fn tail(&self) -> &AtomicU64 {
+        unsafe { &*self.ptr.add(16).cast() }
+    }
+
+    #[inline]
+    fn lock(&self) -> &AtomicU32 {
+        unsafe { &*self.ptr.add(24).cast() }
+    }
+
+    /// 从位置 `pos` 起写入，可能跨越数据区末尾
+    #[cfg(feature = "client")]
+    fn write(&self, pos: u64, bytes: &[u8]) {
+        let offset = (pos % self.capacity as u64) as usize;
+        let first = bytes.len().min(self.capacity - offset);
+        unsafe {
+            let data = self.ptr.add(HEAD_LEN);
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), data.add(offset), first);
+            std::ptr::copy_nonoverlapping(bytes[first..].as_ptr(), data, bytes.len() - first);
+        }
+    }
+
+    /// 从位置 `pos` 起读出，可能跨越数据区末尾
+    #[cfg(feature = "app")]
+    fn read(&self, pos: u64, bytes: &mut [u8]) {
+        let offset = (pos % self.capacity as u64) as usize;
+        let first = bytes.len().min(self.capacity - offset);
+        unsafe {
+            let data = self.ptr.add(HEAD_LEN);
+            std::ptr::copy_nonoverlapping(data.add(offset), bytes.as_mut_ptr(), first);
+            let rest = bytes.len() - first;
+            std::ptr::copy_nonoverlapping(data, bytes[first..].as_mut_ptr(), rest);
+        }
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr.cast(), HEAD_LEN + self.capacity) };
+    }
+}
+
+#[cfg(all(feature = "app", feature = "client"))]
+#[test]
+fn ring_wrap_around() {
+    let path = std::env::temp_dir().join(format!("monitor-tool-test-{}", std::process::id()));
+    let consumer = Ring::create(&path, 64).unwrap();
+    let producer = Ring::open(&path).unwrap();
+    let pid = std::process::id();
+    for i in 0..100u8 {
+        let packet = vec![i; (i % 20) as usize];
+        assert!(producer.push(&packet));
+        assert_eq!(consumer.pop(), Some((pid, packet)));
+    }
+    assert_eq!(consumer.pop(), None);
+    // 空间不足时不阻塞
+    assert!(producer.push(&[0; 40]));
+    assert!(!producer.push(&[0; 40]));
+    // 损坏的位置和长度不会越界读写
+    consumer.head().store(1000, Ordering::SeqCst);
+    assert!(!producer.push(&[0; 4]));
+    assert_eq!(consumer.pop(), None);
+    assert!(producer.push(&[1; 4]));
+    consumer.write(
+        consumer.tail().load(Ordering::SeqCst),
+        &u32::MAX.to_le_bytes(),
+    );
+    assert_eq!(consumer.pop(), None);
+    consumer.tail().store(2000, Ordering::SeqCst);
+    assert_eq!(consumer.pop(), None);
+    assert!(producer.push(&[2; 4]));
+    assert_eq!(consumer.pop(), Some((pid, vec![2; 4])));
+    // 持锁的进程退出后接管写锁，仍被持有时放弃
+    consumer.lock().store(1 << 30, Ordering::SeqCst);
+    assert!(producer.push(&[3; 4]));
+    consumer.lock().store(1, Ordering::SeqCst);
+    assert!(!producer.push(&[3; 4]));
+    let _ = std::fs::remove_file(path);
+}
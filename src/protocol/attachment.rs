@@ -0,0 +1,294 @@
+﻿use crate::{Shape, Vertex};
+
+/// 顶点附件，依次跟随需要附加数据的顶点
+#[derive(Clone, PartialEq, Debug)]
+pub enum Attachment {
+    Text(Label),      // 文字标签
+    Rect(Rect),       // 矩形，顶点为中心
+    Polygon(Polygon), // 多边形
+    Ellipse(Ellipse), // 椭圆，顶点为中心
+    Vector(Vector),   // 向量，顶点为起点
+}
+
+/// 文字标签
+#[derive(Clone, PartialEq, Debug)]
+pub struct Label {
+    pub text: String,   // 内容，编码时截断到 65535 字节
+    pub anchor: Anchor, // 顶点位于文字框的哪个位置
+    pub size: f32,      // 字号
+    pub world: bool,    // 字号以世界单位计，否则以像素计
+}
+
+/// 矩形尺寸
+///
+/// 形状为 [`Shape::Rect`] 时与坐标轴对齐，为 [`Shape::OrientedBox`] 时按顶点的补充数据（弧度）旋转。
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct Rect {
+    pub width: f32,
+    pub height: f32,
+    pub fill: u8, // 填充透明度，0 表示不填充
+}
+
+/// 多边形
+///
+/// 各点是相对顶点的坐标，顶点的补充数据有限时按其旋转（弧度）。
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct Polygon {
+    pub points: Vec<[f32; 2]>, // 编码时截断到 65535 个
+    pub fill: u8,              // 填充透明度，0 表示不填充
+}
+
+/// 椭圆半轴
+///
+/// 按顶点的补充数据（弧度）旋转，`a` 为旋转后沿 x 轴的半轴。
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct Ellipse {
+    pub a: f32,
+    pub b: f32,
+    pub fill: u8, // 填充透明度，0 表示不填充
+}
+
+/// 向量，以世界单位计
+///
+/// 画出的长度和颜色由级别样式的 `vector_scale` 和 `magnitude` 决定。
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct Vector {
+    pub dx: f32,
+    pub dy: f32,
+}
+
+/// 锚点
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[repr(u8)]
+pub enum Anchor {
+    TopLeft = 0,
+    Top = 1,
+    TopRight = 2,
+    Left = 3,
+    #[default]
+    Center = 4,
+    Right = 5,
+    BottomLeft = 6,
+    Bottom = 7,
+    BottomRight = 8,
+}
+
+impl Default for Label {
+    #[inline]
+    fn default() -> Self {
+        Self::screen("", 16.0)
+    }
+}
+
+impl Label {
+    /// 以像素为字号的标签，不随视野缩放
+    #[inline]
+    pub fn screen(text: impl ToString, size: f32) -> Self {
+        Self {
+            text: text.to_string(),
+            anchor: Anchor::Center,
+            size,
+            world: false,
+        }
+    }
+
+    /// 以世界单位为字号的标签，随视野缩放
+    #[cfg(feature = "client")]
+    #[inline]
+    pub fn world(text: impl ToString, size: f32) -> Self {
+        Self {
+            world: true,
+            ..Self::screen(text, size)
+        }
+    }
+
+    /// 设置锚点
+    #[cfg(feature = "client")]
+    #[inline]
+    pub fn with_anchor(self, anchor: Anchor) -> Self {
+        Self { anchor, ..self }
+    }
+}
+
+impl Rect {
+    /// 以中心为原点的四个角，逆时针
+    #[cfg(feature = "app")]
+    #[inline]
+    pub(crate) fn corners(&self) -> [[f32; 2]; 4] {
+        let x = self.width / 2.0;
+        let y = self.height / 2.0;
+        [[-x, -y], [x, -y], [x, y], [-x, y]]
+    }
+}
+
+impl Ellipse {
+    /// 由协方差矩阵 `[[xx, xy], [xy, yy]]` 计算 `sigma` 倍标准差的椭圆，返回椭圆和转角
+    ///
+    /// 非正定的协方差按 0 方差处理。
+    #[cfg(feature = "client")]
+    pub fn from_covariance([xx, xy, yy]: [f32; 3], sigma: f32) -> (Self, f32) {
+        let mean = (xx + yy) / 2.0;
+        let d = ((xx - yy) / 2.0).hypot(xy);
+        let ellipse = Self {
+            a: sigma * (mean + d).max(0.0).sqrt(),
+            b: sigma * (mean - d).max(0.0).sqrt(),
+            fill: 0,
+        };
+        (ellipse, 0.5 * (2.0 * xy).atan2(xx - yy))
+    }
+
+    /// 按转角旋转后的外接盒半宽和半高
+    #[cfg(feature = "app")]
+    #[inline]
+    pub(crate) fn half_extent(&self, angle: f32) -> [f32; 2] {
+        let (sin, cos) = angle.sin_cos();
+        [
+            (self.a * cos).hypot(self.b * sin),
+            (self.a * sin).hypot(self.b * cos),
+        ]
+    }
+}
+
+impl Vector {
+    /// 模长
+    #[inline]
+    pub fn magnitude(&self) -> f32 {
+        self.dx.hypot(self.dy)
+    }
+}
+
+impl Anchor {
+    /// 锚点所在的列和行，0 为左、上
+    #[cfg(feature = "app")]
+    #[inline]
+    pub(crate) fn grid(self) -> (u8, u8) {
+        (self as u8 % 3, self as u8 / 3)
+    }
+}
+
+impl TryFrom<u8> for Anchor {
+    type Error = u8;
+
+    #[inline]
+    fn try_from(b: u8) -> Result<Self, Self::Error> {
+        match b {
+            0 => Ok(Self::TopLeft),
+            1 => Ok(Self::Top),
+            2 => Ok(Self::TopRight),
+            3 => Ok(Self::Left),
+            4 => Ok(Self::Center),
+            5 => Ok(Self::Right),
+            6 => Ok(Self::BottomLeft),
+            7 => Ok(Self::Bottom),
+            8 => Ok(Self::BottomRight),
+            b => Err(b),
+        }
+    }
+}
+
+impl From<Label> for Attachment {
+    #[inline]
+    fn from(label: Label) -> Self {
+        Self::Text(label)
+    }
+}
+
+impl From<Rect> for Attachment {
+    #[inline]
+    fn from(rect: Rect) -> Self {
+        Self::Rect(rect)
+    }
+}
+
+impl From<Polygon> for Attachment {
+    #[inline]
+    fn from(polygon: Polygon) -> Self {
+        Self::Polygon(polygon)
+    }
+}
+
+impl From<Ellipse> for Attachment {
+    #[inline]
+    fn from(ellipse: Ellipse) -> Self {
+        Self::Ellipse(ellipse)
+    }
+}
+
+impl From<Vector> for Attachment {
+    #[inline]
+    fn from(vector: Vector) -> Self {
+        Self::Vector(vector)
+    }
+}
+
+impl Attachment {
+    /// 附件对应的顶点形状，矩形默认与坐标轴对齐
+    #[inline]
+    pub fn shape(&self) -> Shape {
+        match self {
+            Self::Text(_) => Shape::Text,
+            Self::Rect(_) => Shape::Rect,
+            Self::Polygon(_) => Shape::Polygon,
+            Self::Ellipse(_) => Shape::Ellipse,
+            Self::Vector(_) => Shape::Vector,
+        }
+    }
+
+    /// 能否跟随这种形状的顶点
+    #[inline]
+    pub fn fits(&self, shape: Shape) -> bool {
+        match self {
+            Self::Rect(_) => matches!(shape, Shape::Rect | Shape::OrientedBox),
+            _ => self.shape() == shape,
+        }
+    }
+
+    /// 形状的缺省附件，不需要附件的形状返回 `None`
+    #[inline]
+    pub(crate) fn default_for(shape: Shape) -> Option<Self> {
+        match shape {
+            Shape::Arrow | Shape::Circle => None,
+            Shape::Text => Some(Self::Text(Default::default())),
+            Shape::Rect | Shape::OrientedBox => Some(Self::Rect(Default::default())),
+            Shape::Polygon => Some(Self::Polygon(Default::default())),
+            Shape::Ellipse => Some(Self::Ellipse(Default::default())),
+            Shape::Vector => Some(Self::Vector(Default::default())),
+        }
+    }
+}
+
+/// 为需要附件的顶点依次配上附件，附件不足或形状不符时使用缺省附件
+pub(crate) fn attach(
+    vertex: impl IntoIterator<Item = Vertex>,
+    attachments: impl IntoIterator<Item = Attachment>,
+) -> impl Iterator<Item = (Vertex, Option<Attachment>)> {
+    let mut attachments = attachments.into_iter();
+    vertex.into_iter().map(move |v| {
+        let attachment = if v.shape.needs_attachment() {
+            attachments
+                .next()
+                .filter(|a| a.fits(v.shape))
+                .or_else(|| Attachment::default_for(v.shape))
+        } else {
+            None
+        };
+        (v, attachment)
+    })
+}
+
+#[cfg(feature = "client")]
+#[test]
+fn covariance() {
+    use std::f32::consts::FRAC_PI_4;
+
+    let (ellipse, angle) = Ellipse::from_covariance([4.0, 0.0, 1.0], 2.0);
+    assert_eq!((ellipse.a, ellipse.b, angle), (4.0, 2.0, 0.0));
+    // 长轴沿 y 轴
+    let (ellipse, angle) = Ellipse::from_covariance([1.0, 0.0, 4.0], 1.0);
+    assert_eq!((ellipse.a, ellipse.b), (2.0, 1.0));
+    assert!((angle.abs() - 2.0 * FRAC_PI_4).abs() < 1e-6);
+    // 沿对角线相关
+    let (ellipse, angle) = Ellipse::from_covariance([1.0, 1.0, 1.0], 1.0);
+    assert!((ellipse.a - 2f32.sqrt()).abs() < 1e-6 && ellipse.b == 0.0);
+    assert!((angle - FRAC_PI_4).abs() < 1e-6);
+}
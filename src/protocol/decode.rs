@@ -1,155 +1,892 @@
-﻿use super::Visibility;
-use crate::{Figure, Vertex};
-use palette::rgb::channels::Argb;
-use palette::{Pixel, Srgba};
-use std::alloc::Layout;
-use std::time::{Duration, Instant};
+﻿use super::{
+    Anchor, Attachment, Cells, Ellipse, EntityCommand, Fragment, Grid, Label, Layer, Marker,
+    Packet, Polygon, Rect, Removal, Style, SyncSet, Tile, Topic, Vector, ViewCommand, Visibility,
+    MAGIC, MAX_CELLS, VERSION,
+};
+use crate::Shape;
+use palette::{rgb::channels::Argb, Srgba};
 
+mod reader;
 mod sync_sets_and_layers;
 
+use reader::Reader;
 use sync_sets_and_layers::*;
 
-fn read_n<T>(mut buf: &[u8], n: usize) -> Option<(&[u8], &[T])> {
-    let layout = Layout::array::<T>(n).unwrap();
-    let rest = buf.as_ptr() as usize % layout.align();
-    if rest > 0 {
-        buf = &buf[layout.align() - rest..];
+/// 解码错误
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+    /// 不是本协议的包
+    Magic,
+    /// 不支持的协议版本
+    Version(u8),
+    /// 分片序号不小于分片数
+    Fragment(u16, u16),
+    /// 数据不完整
+    Truncated,
+    /// 名字不是合法的 UTF-8
+    Utf8,
+    /// 非法的图层可见性
+    Visibility(u8),
+    /// 非法的顶点形状
+    Shape(u8),
+    /// 非法的标志位
+    Flag(u8),
+    /// 非法的时长
+    Duration,
+    /// 同步组序号越界
+    SyncSetIndex(u16),
+    /// 图层序号越界
+    LayerIndex(u16),
+    /// 尾部位置表不递增
+    Tails,
+    /// 非法的删除命令类型
+    Removal(u8),
+    /// 非法的视野命令类型
+    ViewCommand(u8),
+    /// 非法的文字锚点
+    Anchor(u8),
+    /// 非法的点形状
+    Marker(u8),
+    /// 非法的栅格值类型
+    Cells(u8),
+    /// 栅格几何非法、栅格过大或块超出栅格
+    Grid,
+    /// 色标不是有限值或不递增
+    Palette,
+    /// 非法的实体命令类型
+    EntityCommand(u8),
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Magic => write!(f, "not a monitor-tool packet"),
+            Self::Version(v) => write!(f, "unsupported protocol version {}", v),
+            Self::Fragment(i, n) => write!(f, "fragment {} of {} out of range", i, n),
+            Self::Truncated => write!(f, "truncated packet"),
+            Self::Utf8 => write!(f, "name is not valid UTF-8"),
+            Self::Visibility(b) => write!(f, "invalid visibility byte {:#04x}", b),
+            Self::Shape(b) => write!(f, "invalid shape byte {:#04x}", b),
+            Self::Flag(b) => write!(f, "invalid flag byte {:#04x}", b),
+            Self::Duration => write!(f, "invalid duration"),
+            Self::SyncSetIndex(i) => write!(f, "sync set index {} out of range", i),
+            Self::LayerIndex(i) => write!(f, "layer index {} out of range", i),
+            Self::Tails => write!(f, "tail offsets are not increasing"),
+            Self::Removal(b) => write!(f, "invalid removal kind {:#04x}", b),
+            Self::ViewCommand(b) => write!(f, "invalid view command {:#04x}", b),
+            Self::Anchor(b) => write!(f, "invalid text anchor {:#04x}", b),
+            Self::Marker(b) => write!(f, "invalid marker {:#04x}", b),
+            Self::Cells(b) => write!(f, "invalid cell type {:#04x}", b),
+            Self::Grid => write!(f, "raster grid invalid or too large, or tile out of grid"),
+            Self::Palette => write!(f, "palette values are not finite and increasing"),
+            Self::EntityCommand(b) => write!(f, "invalid entity command {:#04x}", b),
+        }
     }
-    if buf.len() >= layout.size() {
-        Some((&buf[layout.size()..], unsafe {
-            std::slice::from_raw_parts(buf.as_ptr() as *const T, n)
-        }))
-    } else {
-        None
+}
+
+impl std::error::Error for DecodeError {}
+
+/// 解码来自 `source` 的一帧并应用到画面，帧内任何一个包有错误时画面保持不变
+///
+/// 包格式见 `Encoder::encode`，所有字段均为小端序、无填充。
+#[cfg(feature = "app")]
+pub(crate) fn decode<'a>(
+    figure: &mut crate::Figure,
+    time: std::time::Instant,
+    source: &str,
+    frame: impl IntoIterator<Item = &'a [u8]>,
+) -> Result<(), DecodeError> {
+    let packets = frame
+        .into_iter()
+        .map(Packet::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+    // 视野命令等整帧的话题更新之后再执行
+    let mut views = Vec::new();
+    for mut packet in packets {
+        views.append(&mut packet.views);
+        packet.apply(figure, time, source);
     }
+    apply_views(figure, views);
+    Ok(())
 }
 
-macro_rules! read {
-    ($buf:expr => $ty:ty) => {{
-        if let Some((slice, val)) = read_n::<$ty>($buf, 1) {
-            $buf = slice;
-            Some(&val[0])
-        } else {
-            None
+/// 只解析包头
+#[inline]
+pub(super) fn parse_head(buf: &[u8]) -> Result<Fragment, DecodeError> {
+    read_head(&mut Reader::new(buf)).map(|(_, fragment)| fragment)
+}
+
+/// 解析一个包
+pub(super) fn parse(buf: &[u8]) -> Result<Packet, DecodeError> {
+    let mut reader = Reader::new(buf);
+    let (version, fragment) = read_head(&mut reader)?;
+    let mut packet = parse_body(reader, version)?;
+    packet.fragment = fragment;
+    Ok(packet)
+}
+
+/// 解析包头，按版本分派，返回版本号和分片信息
+fn read_head(reader: &mut Reader) -> Result<(u8, Fragment), DecodeError> {
+    if reader.array() != Ok(MAGIC) {
+        return Err(DecodeError::Magic);
+    }
+    let version = reader.u8()?;
+    match version {
+        // 版本 1 没有分片信息
+        1 => Ok((version, Fragment::default())),
+        VERSION => {
+            let frame = reader.u32()?;
+            let index = reader.u16()?;
+            let count = reader.u16()?;
+            if index < count {
+                let fragment = Fragment {
+                    frame,
+                    index,
+                    count,
+                };
+                Ok((version, fragment))
+            } else {
+                Err(DecodeError::Fragment(index, count))
+            }
         }
-    }};
-    ($buf:expr => $ty:ty; $n:expr) => {{
-        if let Some((slice, val)) = read_n::<$ty>($buf, $n as _) {
-            $buf = slice;
-            Some(val)
-        } else {
+        v => Err(DecodeError::Version(v)),
+    }
+}
+
+/// 解析包体
+///
+/// 版本 1 只有同步组表、图层表和话题的配置、颜色、顶点；
+/// 当前版本在图层表之后有源时间、删除表和视野命令表，关注数量之后有保留时长，
+/// 颜色之后有样式，顶点之后依次有附件、栅格块和实体命令。
+fn parse_body(mut reader: Reader, version: u8) -> Result<Packet, DecodeError> {
+    let legacy = version < VERSION;
+    // 解析同步组
+    let sync_sets = read_sync_sets(&mut reader)?;
+    // 解析图层
+    let layers = read_layers(&mut reader)?;
+    // 解析源时间、删除命令和视野命令
+    let (time, removals, views) = if legacy {
+        (None, Vec::new(), Vec::new())
+    } else {
+        let time = Some(reader.duration()?)
+            .filter(|t| !t.is_zero())
+            .map(|t| std::time::UNIX_EPOCH + t);
+        (time, read_removals(&mut reader)?, read_views(&mut reader)?)
+    };
+    // 解析话题
+    let mut topics = Vec::new();
+    while !reader.is_empty() {
+        // 构造话题标题
+        let len = reader.u16()?;
+        let name = reader.str(len as _)?.to_string();
+        // 同步组
+        let sync_set = match reader.u16()? {
+            0 => None,
+            i => match sync_sets.get(i as usize - 1) {
+                Some((name, _)) => Some(name.to_string()),
+                None => return Err(DecodeError::SyncSetIndex(i)),
+            },
+        };
+        // 图层
+        let layer = match reader.u16()? {
+            0 => None,
+            i => match layers.get(i as usize - 1) {
+                Some((name, _)) => Some(name.to_string()),
+                None => return Err(DecodeError::LayerIndex(i)),
+            },
+        };
+        // 清除缓存、容量、关注数量、保留时长
+        let clear = reader.bool()?;
+        let capacity = reader.u32()?;
+        let focus = reader.u32()?;
+        let retention = if legacy {
             None
+        } else {
+            Some(reader.duration()?).filter(|t| !t.is_zero())
+        };
+        // 颜色
+        let n = reader.u16()?;
+        let mut colors = Vec::with_capacity(n as _);
+        for _ in 0..n {
+            let level = reader.u8()?;
+            let argb = reader.u32()?;
+            colors.push((level, Srgba::from_u32::<Argb>(argb)));
         }
-    }};
+        // 样式
+        let mut styles = Vec::new();
+        if !legacy {
+            let n = reader.u16()?;
+            styles.reserve(n as _);
+            for _ in 0..n {
+                let level = reader.u8()?;
+                styles.push((level, read_style(&mut reader)?));
+            }
+        }
+        // 点
+        let n = reader.u16()?;
+        let mut vertex = Vec::with_capacity(n as _);
+        for _ in 0..n {
+            vertex.push(reader.vertex()?);
+        }
+        // 附件
+        let mut attachments = Vec::new();
+        for v in vertex.iter().filter(|v| v.shape.needs_attachment()) {
+            if legacy {
+                return Err(DecodeError::Shape(v.shape as u8));
+            }
+            attachments.push(read_attachment(&mut reader, v.shape)?);
+        }
+        // 栅格块
+        let mut tiles = Vec::new();
+        if !legacy {
+            let n = reader.u16()?;
+            for _ in 0..n {
+                tiles.push(read_tile(&mut reader)?);
+            }
+        }
+        // 实体命令
+        let mut entities = Vec::new();
+        if !legacy {
+            let n = reader.u16()?;
+            for _ in 0..n {
+                entities.push(read_entity(&mut reader)?);
+            }
+        }
+        topics.push(Topic {
+            name,
+            sync_set,
+            layer,
+            clear,
+            capacity,
+            focus,
+            retention,
+            colors,
+            styles,
+            vertex,
+            attachments,
+            tiles,
+            entities,
+        });
+    }
+    Ok(Packet {
+        fragment: Default::default(),
+        sync_sets: sync_sets
+            .into_iter()
+            .map(|(name, life_time)| SyncSet {
+                name: name.to_string(),
+                life_time: Some(life_time).filter(|t| !t.is_zero()),
+            })
+            .collect(),
+        layers: layers
+            .into_iter()
+            .map(|(name, visible)| Layer {
+                name: name.to_string(),
+                visible: match visible {
+                    Visibility::NothingToDo => None,
+                    Visibility::Visible => Some(true),
+                    Visibility::Invisible => Some(false),
+                },
+            })
+            .collect(),
+        removals,
+        views,
+        time,
+        topics,
+    })
 }
 
-macro_rules! read_by_tails {
-    ($buf:expr => $ty:expr) => {
-        match read!($buf => u16) {
-            Some(0) => $ty(&[], &[]),
-            Some(n) => match read!($buf => u16; *n) {
-                Some(tails) => match read!($buf => u8; tails[*n as usize - 1]) {
-                    Some(slice) => $ty(tails, slice),
-                    None => return,
-                },
-                None => return,
+/// 解析删除表：| 数量 n: u16 | (类型: u8, 名字长度: u16, 名字: UTF-8) × n |
+fn read_removals(reader: &mut Reader) -> Result<Vec<Removal>, DecodeError> {
+    let n = reader.u16()?;
+    let mut removals = Vec::with_capacity(n as _);
+    for _ in 0..n {
+        let kind = reader.u8()?;
+        let len = reader.u16()?;
+        let name = reader.str(len as _)?.to_string();
+        removals.push(match kind {
+            0 => Removal::Topic(name),
+            1 => Removal::Layer(name),
+            2 => Removal::SyncSet(name),
+            b => return Err(DecodeError::Removal(b)),
+        });
+    }
+    Ok(removals)
+}
+
+/// 解析视野命令表：| 数量 n: u16 | (类型: u8, 参数) × n |
+fn read_views(reader: &mut Reader) -> Result<Vec<ViewCommand>, DecodeError> {
+    let n = reader.u16()?;
+    let mut views = Vec::with_capacity(n as _);
+    for _ in 0..n {
+        views.push(match reader.u8()? {
+            0 => ViewCommand::Look {
+                x: reader.f32()?,
+                y: reader.f32()?,
+                scale: reader.f32()?,
             },
-            None => return,
+            1 => {
+                let len = reader.u16()?;
+                ViewCommand::Fit(reader.str(len as _)?.to_string())
+            }
+            2 => ViewCommand::Auto(reader.bool()?),
+            b => return Err(DecodeError::ViewCommand(b)),
+        });
+    }
+    Ok(views)
+}
+
+/// 解析样式
+///
+/// | 点形状: u8 | 点大小: f32 | 线宽: f32 | 虚线实段: f32 | 虚线空段: f32 | 箭头长度: f32 | 箭头世界单位: u8 |
+/// | 向量缩放: f32 | 着色模长下限: f32 | 着色模长上限: f32 |
+fn read_style(reader: &mut Reader) -> Result<Style, DecodeError> {
+    let marker = reader.u8()?;
+    Ok(Style {
+        marker: Marker::try_from(marker).map_err(DecodeError::Marker)?,
+        marker_size: reader.f32()?,
+        line_width: reader.f32()?,
+        dash: [reader.f32()?, reader.f32()?],
+        arrow_length: reader.f32()?,
+        arrow_world: reader.bool()?,
+        vector_scale: reader.f32()?,
+        magnitude: [reader.f32()?, reader.f32()?],
+    })
+}
+
+/// 解析附件
+///
+/// 文字：  | 锚点: u8 | 世界单位: u8 | 字号: f32 | 长度: u16 | 内容: UTF-8 |
+/// 矩形：  | 宽: f32 | 高: f32 | 填充透明度: u8 |
+/// 多边形：| 填充透明度: u8 | 点数: u16 | (x: f32, y: f32) × n |
+/// 椭圆：  | 半轴 a: f32 | 半轴 b: f32 | 填充透明度: u8 |
+/// 向量：  | dx: f32 | dy: f32 |
+fn read_attachment(reader: &mut Reader, shape: Shape) -> Result<Attachment, DecodeError> {
+    match shape {
+        Shape::Text => {
+            let anchor = reader.u8()?;
+            let anchor = Anchor::try_from(anchor).map_err(DecodeError::Anchor)?;
+            let world = reader.bool()?;
+            let size = reader.f32()?;
+            let len = reader.u16()?;
+            let text = reader.str(len as _)?.to_string();
+            Ok(Attachment::Text(Label {
+                text,
+                anchor,
+                size,
+                world,
+            }))
+        }
+        Shape::Rect | Shape::OrientedBox => Ok(Attachment::Rect(Rect {
+            width: reader.f32()?,
+            height: reader.f32()?,
+            fill: reader.u8()?,
+        })),
+        Shape::Ellipse => Ok(Attachment::Ellipse(Ellipse {
+            a: reader.f32()?,
+            b: reader.f32()?,
+            fill: reader.u8()?,
+        })),
+        Shape::Vector => Ok(Attachment::Vector(Vector {
+            dx: reader.f32()?,
+            dy: reader.f32()?,
+        })),
+        Shape::Polygon => {
+            let fill = reader.u8()?;
+            let n = reader.u16()?;
+            let mut points = Vec::with_capacity(n as _);
+            for _ in 0..n {
+                points.push([reader.f32()?, reader.f32()?]);
+            }
+            Ok(Attachment::Polygon(Polygon { points, fill }))
+        }
+        shape => Err(DecodeError::Shape(shape as u8)),
+    }
+}
+
+/// 解析栅格块
+///
+/// | 原点 x: f32 | 原点 y: f32 | 分辨率: f32 | 列数: u32 | 行数: u32 |
+/// | 色标数: u16 | (值: f32, ARGB: u32) × n |
+/// | 起始列: u32 | 起始行: u32 | 块列数: u32 | 块行数: u32 | 类型: u8 (0 u8, 1 f32) | 值 × 块列数 × 块行数 |
+fn read_tile(reader: &mut Reader) -> Result<Tile, DecodeError> {
+    let grid = Grid {
+        origin: [reader.f32()?, reader.f32()?],
+        resolution: reader.f32()?,
+        width: reader.u32()?,
+        height: reader.u32()?,
+    };
+    if !grid.is_valid() || grid.width as u64 * grid.height as u64 > MAX_CELLS as u64 {
+        return Err(DecodeError::Grid);
+    }
+    let n = reader.u16()?;
+    let mut palette = Vec::with_capacity(n as _);
+    for _ in 0..n {
+        let value = reader.f32()?;
+        let argb = reader.u32()?;
+        palette.push((value, Srgba::from_u32::<Argb>(argb)));
+    }
+    let sorted = palette.windows(2).all(|w| w[0].0 <= w[1].0);
+    if !sorted || palette.iter().any(|(v, _)| !v.is_finite()) {
+        return Err(DecodeError::Palette);
+    }
+    let column = reader.u32()?;
+    let row = reader.u32()?;
+    let width = reader.u32()?;
+    let height = reader.u32()?;
+    // 块必须在栅格之内，因此值的数量不超过栅格的单元数
+    let inside = |begin: u32, len: u32, end: u32| begin.checked_add(len).is_some_and(|e| e <= end);
+    if !inside(column, width, grid.width) || !inside(row, height, grid.height) {
+        return Err(DecodeError::Grid);
+    }
+    let n = width as usize * height as usize;
+    let cells = match reader.u8()? {
+        0 => Cells::U8(reader.take(n)?.to_vec()),
+        1 => {
+            let bytes = reader.take(n.checked_mul(4).ok_or(DecodeError::Truncated)?)?;
+            let cells = bytes.chunks_exact(4);
+            Cells::F32(
+                cells
+                    .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                    .collect(),
+            )
         }
+        b => return Err(DecodeError::Cells(b)),
     };
+    Ok(Tile {
+        grid,
+        palette,
+        column,
+        row,
+        width,
+        cells,
+    })
 }
 
-pub(crate) fn decode(figure: &mut Figure, time: Instant, mut buf: &[u8]) {
-    // 解析同步组
-    let sync_sets = read_by_tails!(buf => SyncSets);
-    for i in 0..sync_sets.len() {
-        let (sync_set, life_time) = sync_sets.get(i);
-        if life_time != &Duration::ZERO {
-            figure.set_life_time(sync_set, *life_time);
+/// 解析实体命令
+///
+/// 写入：| 0: u8 | 编号: u32 | 顶点 | 附件 |
+/// 移动：| 1: u8 | 编号: u32 | x: f32 | y: f32 | 补充数据: f32 |
+/// 删除：| 2: u8 | 编号: u32 |
+/// 写入边：| 3: u8 | 起点编号: u32 | 终点编号: u32 | 级别: u8 | 透明度: u8 |
+/// 删除边：| 4: u8 | 起点编号: u32 | 终点编号: u32 |
+fn read_entity(reader: &mut Reader) -> Result<EntityCommand, DecodeError> {
+    let kind = reader.u8()?;
+    let id = reader.u32()?;
+    match kind {
+        0 => {
+            let vertex = reader.vertex()?;
+            let attachment = if vertex.shape.needs_attachment() {
+                Some(read_attachment(reader, vertex.shape)?)
+            } else {
+                None
+            };
+            Ok(EntityCommand::Put {
+                id,
+                vertex,
+                attachment,
+            })
         }
+        1 => Ok(EntityCommand::Move {
+            id,
+            x: reader.f32()?,
+            y: reader.f32()?,
+            extra: reader.f32()?,
+        }),
+        2 => Ok(EntityCommand::Remove(id)),
+        3 => Ok(EntityCommand::PutEdge {
+            from: id,
+            to: reader.u32()?,
+            level: reader.u8()?,
+            alpha: reader.u8()?,
+        }),
+        4 => Ok(EntityCommand::RemoveEdge {
+            from: id,
+            to: reader.u32()?,
+        }),
+        b => Err(DecodeError::EntityCommand(b)),
     }
-    // 解析图层
-    let layers = read_by_tails!(buf => Layers);
-    for i in 0..layers.len() {
-        let (layer, visible) = layers.get(i);
-        match *visible {
-            Visibility::NothingToDo => {}
-            Visibility::Visible => figure.set_visible(layer, true),
-            Visibility::Invisible => figure.set_visible(layer, false),
+}
+
+/// 执行视野命令
+#[cfg(feature = "app")]
+fn apply_views(figure: &mut crate::Figure, views: Vec<ViewCommand>) {
+    for view in views {
+        match view {
+            ViewCommand::Look { x, y, scale } => figure.set_view(x, y, scale, scale),
+            ViewCommand::Fit(topic) => figure.fit_topic(&topic),
+            ViewCommand::Auto(auto) => figure.auto_view = auto,
         }
     }
-    // 解析话题
-    loop {
-        // 构造话题标题
-        let title = {
-            let len = match read!(buf => u16) {
-                Some(len) => *len as usize,
-                None => return,
-            };
-            match read!(buf => u8; len) {
-                Some(slice) => unsafe { std::str::from_utf8_unchecked(slice) },
-                None => return,
+}
+
+#[cfg(feature = "app")]
+impl Packet {
+    /// 应用来自 `source` 的包到画面
+    pub(crate) fn apply(self, figure: &mut crate::Figure, time: std::time::Instant, source: &str) {
+        use palette::Pixel;
+
+        // 先执行删除
+        for removal in self.removals {
+            match removal {
+                Removal::Topic(name) => figure.remove_topic(&name),
+                Removal::Layer(name) => figure.remove_layer(&name),
+                Removal::SyncSet(name) => figure.dissolve_sync_set(&name),
             }
-        };
+        }
         // 更新同步组
-        match read!(buf => u16) {
-            Some(0) => {}
-            Some(i) => {
-                let (sync_set, _) = sync_sets.get(*i as usize - 1);
-                figure.update_sync_set(sync_set, title.to_string());
+        for SyncSet { name, life_time } in self.sync_sets {
+            if let Some(life_time) = life_time {
+                figure.set_life_time(name, life_time);
             }
-            None => return,
         }
-        // 更新话题内容
-        let topic = figure.put_topic(title.to_string());
         // 更新图层
-        match read!(buf => u16) {
-            Some(0) => {}
-            Some(i) => topic.layer = layers.get(*i as usize - 1).0.to_string(),
-            None => return,
-        }
-        // 清除缓存
-        match read!(buf => bool) {
-            Some(false) => {}
-            Some(true) => topic.clear(),
-            None => return,
-        }
-        // 更新容量
-        match read!(buf => u32) {
-            Some(0) => {}
-            Some(n) => topic.set_capacity(*n as usize),
-            None => return,
-        }
-        // 更新关注数量
-        match read!(buf => u32) {
-            Some(0) => {}
-            Some(n) => topic.set_focus(*n as usize),
-            None => return,
-        }
-        // 更新颜色
-        match read!(buf => u16) {
-            Some(0) => {}
-            Some(n) => match read!(buf => [u32;2]; *n) {
-                Some(colors) => {
-                    for color in colors {
-                        let level = color[0];
-                        let rgba: [f32; 4] =
-                            Srgba::from_u32::<Argb>(color[1]).into_format().into_raw();
-                        topic.set_color(level as _, rgba.into());
-                    }
-                }
-                None => return,
-            },
-            None => return,
-        }
-        // 存入点
-        match read!(buf => u16) {
-            Some(0) => {}
-            Some(n) => match read!(buf => Vertex; *n) {
-                Some(vertexs) => topic.extend_from_slice(time, vertexs),
-                None => return,
+        for Layer { name, visible } in self.layers {
+            if let Some(visible) = visible {
+                figure.set_visible(name, visible);
+            }
+        }
+        // 有源时间时按来源换算到本地时钟
+        let received = std::time::SystemTime::now().checked_sub(time.elapsed());
+        let time = match self.time.zip(received) {
+            Some((sent, received)) => figure.stamp(source, time, received, sent),
+            None => time,
+        };
+        // 更新话题
+        for t in self.topics {
+            if let Some(sync_set) = t.sync_set {
+                figure.update_sync_set(&sync_set, t.name.clone());
+            }
+            let topic = figure.put_topic(t.name);
+            if let Some(layer) = t.layer {
+                topic.layer = layer;
+            }
+            if t.clear {
+                topic.clear();
+            }
+            if t.capacity > 0 {
+                topic.set_capacity(t.capacity as usize);
+            }
+            if t.focus > 0 {
+                topic.set_focus(t.focus as usize);
+            }
+            if let Some(retention) = t.retention {
+                topic.set_retention(retention);
+            }
+            for (level, color) in t.colors {
+                let rgba: [f32; 4] = color.into_format().into_raw();
+                topic.set_color(level, rgba.into());
+            }
+            for (level, style) in t.styles {
+                topic.set_style(level, style);
+            }
+            if !t.vertex.is_empty() {
+                topic.extend(time, super::attach(t.vertex, t.attachments));
+            }
+            for tile in t.tiles {
+                topic.put_tile(tile);
+            }
+            for command in t.entities {
+                topic.update_entity(time, command);
+            }
+        }
+        apply_views(figure, self.views);
+    }
+}
+
+#[cfg(feature = "client")]
+#[test]
+fn reject_malformed() {
+    use crate::{vertex, Encoder};
+
+    let buf = Encoder::with(|encoder| {
+        encoder.sync_set("set", &["a"], None);
+        encoder.topic("a").push(vertex!(1; 2, 3; 255));
+    });
+    // 任意截断都不能 panic
+    for len in 0..buf.len() {
+        let _ = parse(&buf[..len]);
+    }
+    assert_eq!(
+        parse(&buf[..buf.len() - 1]).err(),
+        Some(DecodeError::Truncated)
+    );
+    // 同步组序号越界：| 包头 | 同步组 0 | 图层 0 | 源时间 | 删除 0 | 视野 0 | 名字长度 | "a" | 同步组序号 |
+    let mut bad = Encoder::with(|encoder| encoder.topic("a").clear());
+    bad[36..38].copy_from_slice(&7u16.to_le_bytes());
+    assert_eq!(parse(&bad).err(), Some(DecodeError::SyncSetIndex(7)));
+    // 过大的栅格、超出栅格的块和非法的色标
+    let tile = |width, column, value| {
+        let grid = Grid {
+            origin: [0.0, 0.0],
+            resolution: 1.0,
+            width,
+            height: width,
+        };
+        let palette = vec![(value, Srgba::new(0, 0, 0, 255))];
+        let tile = Tile {
+            column,
+            ..Tile::whole(grid, palette, vec![0u8; 4])
+        };
+        Encoder::with(|encoder| encoder.topic("a").push_tile(tile))
+    };
+    assert!(parse(&tile(4, 0, 0.0)).is_ok());
+    assert_eq!(
+        parse(&tile(u32::MAX, 0, 0.0)).err(),
+        Some(DecodeError::Grid)
+    );
+    assert_eq!(parse(&tile(4, 1, 0.0)).err(), Some(DecodeError::Grid));
+    assert_eq!(
+        parse(&tile(4, 0, f32::NAN)).err(),
+        Some(DecodeError::Palette)
+    );
+    // 非有限的原点和非正的单元边长
+    for (origin, resolution) in [
+        ([f32::INFINITY, 0.0], 1.0),
+        ([0.0, 0.0], 0.0),
+        ([0.0, 0.0], -1.0),
+    ] {
+        let grid = Grid {
+            origin,
+            resolution,
+            width: 2,
+            height: 2,
+        };
+        let tile = Tile::whole(grid, vec![], vec![0u8; 4]);
+        let buf = Encoder::with(|encoder| encoder.topic("a").push_tile(tile));
+        assert_eq!(parse(&buf).err(), Some(DecodeError::Grid));
+    }
+    // 外来的包和不支持的版本
+    assert_eq!(parse(b"GET / HTTP/1.1").err(), Some(DecodeError::Magic));
+    bad[4] = VERSION + 1;
+    assert_eq!(parse(&bad).err(), Some(DecodeError::Version(VERSION + 1)));
+    // 版本 1 的包：| 包头 | 同步组 0 | 图层 0 | 名字长度 | "a" | 同步组 | 图层 | 清除 | 容量 | 关注 | 颜色 0 | 顶点 0 |
+    let mut v1 = b"MTRS\x01".to_vec();
+    v1.extend_from_slice(&[0, 0, 0, 0, 1, 0, b'a', 0, 0, 0, 0, 1]);
+    v1.extend_from_slice(&[0; 4 + 4 + 2 + 2]);
+    let packet = parse(&v1).unwrap();
+    assert_eq!(packet.topics.len(), 1);
+    assert!(packet.topics[0].clear);
+    assert!(packet.time.is_none() && packet.topics[0].retention.is_none());
+}
+
+#[cfg(all(feature = "app", feature = "client"))]
+#[test]
+fn chunks_equal_whole() {
+    use crate::{vertex, Encoder, Figure};
+    use std::time::{Duration, Instant};
+
+    let build = || {
+        let mut encoder = Encoder::default();
+        encoder.sync_set("set", &["a", "b"], Some(Duration::from_secs(1)));
+        encoder.config_topic(
+            "a",
+            100000,
+            10,
+            &[(1, Srgba::new(1.0, 0.0, 0.0, 1.0))],
+            |t| {
+                t.clear();
+                t.extend((0..70000).map(|i| vertex!(1; i, -i => 0.0; 255)));
             },
-            None => return,
+        );
+        encoder.topic("b").push(vertex!(2; 0, 0 => 0.0; 0));
+        encoder
+    };
+    let time = Instant::now();
+    let old = Encoder::with(|encoder| encoder.topic("a").push(vertex!(0; 1, 1 => 0.0; 0)));
+    // 一个大包
+    let mut whole = Figure::default();
+    decode(&mut whole, time, "", [old.as_slice()]).unwrap();
+    decode(&mut whole, time, "", [build().encode().as_slice()]).unwrap();
+    // 拆成多个小包
+    let mut chunked = Figure::default();
+    decode(&mut chunked, time, "", [old.as_slice()]).unwrap();
+    let chunks = build().encode_chunks(1400).unwrap();
+    assert!(chunks.len() > 1);
+    for chunk in chunks {
+        assert!(chunk.len() <= 1400);
+        decode(&mut chunked, time, "", [chunk.as_slice()]).unwrap();
+    }
+    let whole = whole.snapshot().0;
+    let chunked = chunked.snapshot().0;
+    assert_eq!(whole.len(), chunked.len());
+    for (name, a) in whole {
+        let b = &chunked[&name];
+        assert_eq!(a.capacity, b.capacity);
+        assert_eq!(a.color_map, b.color_map);
+        assert_eq!(a.queue, b.queue);
+    }
+}
+
+#[cfg(all(feature = "app", feature = "client"))]
+#[test]
+fn apply_entities() {
+    use crate::{vertex, Encoder, Figure};
+    use std::time::Instant;
+
+    let mut figure = Figure::default();
+    let time = Instant::now();
+    let apply = |figure: &mut Figure, buf: Vec<u8>| decode(figure, time, "", [buf.as_slice()]);
+    let buf = Encoder::with(|encoder| {
+        let mut topic = encoder.topic("a");
+        topic.set_capacity(1);
+        topic.push(vertex!(0; 9, 9; 0));
+        for i in 0..3 {
+            topic.put_entity(i, vertex!(0; i, 0 => 0.5; 0));
+        }
+    });
+    apply(&mut figure, buf).unwrap();
+    // 同号实体原地更新，不受容量限制
+    let buf = Encoder::with(|encoder| {
+        let mut topic = encoder.topic("a");
+        topic.put_entity_attached(0, vertex!(1; 5, 5 => 0.0; 0), Rect::default());
+        topic.move_entity(1, 1.0, 2.0, f32::NAN);
+        topic.move_entity(7, 0.0, 0.0, 0.0);
+        topic.put_edge(2, 0, 2, 255);
+        topic.remove_entity(2);
+        topic.put_edge(0, 1, 2, 255);
+        topic.put_edge(1, 2, 2, 255);
+        topic.remove_edge(1, 2);
+    });
+    apply(&mut figure, buf).unwrap();
+    let snapshot = figure.snapshot().0;
+    let a = &snapshot["a"];
+    assert_eq!(a.queue.len(), 1);
+    assert_eq!(a.edges.iter().collect::<Vec<_>>(), [(&(0, 1), &(2, 255))]);
+    let entities = a
+        .entities
+        .iter()
+        .map(|(id, (_, v, a))| (*id, *v, a.as_deref().cloned()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        entities,
+        [
+            (
+                0,
+                vertex!(1; 5, 5; Rect, 0.0; 0),
+                Some(Rect::default().into())
+            ),
+            (1, vertex!(0; 1, 2 => 0.5; 0), None),
+        ]
+    );
+    // 清空话题时实体和边一并删除
+    apply(
+        &mut figure,
+        Encoder::with(|encoder| encoder.topic("a").clear()),
+    )
+    .unwrap();
+    let snapshot = figure.snapshot().0;
+    assert!(snapshot["a"].entities.is_empty() && snapshot["a"].edges.is_empty());
+}
+
+#[cfg(all(feature = "app", feature = "client"))]
+#[test]
+fn apply_source_time() {
+    use crate::{vertex, Encoder, Figure};
+    use std::time::{Duration, Instant, SystemTime};
+
+    let mut figure = Figure::default();
+    let time = Instant::now();
+    let source = SystemTime::now();
+    let mut apply = |from: &str, topic: &str, x: f32, sent: SystemTime| {
+        let buf = Encoder::with(|encoder| {
+            encoder.set_time(sent);
+            encoder.topic(topic).push(vertex!(0; x, 0 => 0.0; 0));
+        });
+        decode(&mut figure, time, from, [buf.as_slice()]).unwrap();
+        let stamp = figure.snapshot().0[topic].queue[0].0;
+        time.saturating_duration_since(stamp).as_secs_f32()
+    };
+    let near = |age: f32, expected: f32| (age - expected).abs() < 0.01;
+    // 第一个包按到达时刻计，同一来源同时到达的更早的数据按源时间提前，不论话题
+    assert!(near(apply("p", "a", 0.0, source), 0.0));
+    assert!(near(
+        apply("p", "b", 1.0, source - Duration::from_secs(2)),
+        2.0
+    ));
+    // 不早于话题中最新的点
+    assert!(near(
+        apply("p", "a", 2.0, source - Duration::from_secs(2)),
+        0.0
+    ));
+    // 其他来源单独对齐
+    assert!(near(
+        apply("q", "c", 3.0, source - Duration::from_secs(5)),
+        0.0
+    ));
+    // 源时钟大幅倒退时重新对齐
+    assert!(near(
+        apply("p", "d", 4.0, source - Duration::from_secs(60)),
+        0.0
+    ));
+    assert!(near(
+        apply("p", "e", 5.0, source - Duration::from_secs(61)),
+        1.0
+    ));
+}
+
+#[cfg(all(feature = "app", feature = "client"))]
+#[test]
+fn apply_retention() {
+    use crate::{vertex, Encoder, Figure};
+    use std::time::{Duration, Instant};
+
+    let mut figure = Figure::default();
+    let now = Instant::now();
+    let old = now.checked_sub(Duration::from_secs(10)).unwrap();
+    let buf = Encoder::with(|encoder| {
+        let mut topic = encoder.topic("a");
+        topic.set_retention(Some(Duration::from_secs(5)));
+        topic.push(vertex!(0; 0, 0; 0));
+        topic.put_entity(0, vertex!(0; 0, 0; 0));
+        encoder.topic("b").push(vertex!(0; 0, 0; 0));
+    });
+    decode(&mut figure, old, "", [buf.as_slice()]).unwrap();
+    let buf = Encoder::with(|encoder| encoder.topic("a").push(vertex!(0; 1, 1; 0)));
+    decode(&mut figure, now, "", [buf.as_slice()]).unwrap();
+    // 不在同步组中也按保留时长消除，其他话题不受影响
+    figure.draw();
+    let snapshot = figure.snapshot().0;
+    assert_eq!(snapshot["a"].queue.len(), 1);
+    assert!(snapshot["a"].entities.is_empty());
+    assert_eq!(snapshot["b"].queue.len(), 1);
+}
+
+#[cfg(all(feature = "app", feature = "client"))]
+#[test]
+fn apply_removals() {
+    use crate::{vertex, Encoder, Figure};
+    use std::time::Instant;
+
+    let mut figure = Figure::default();
+    let time = Instant::now();
+    let apply = |figure: &mut Figure, buf: Vec<u8>| decode(figure, time, "", [buf.as_slice()]);
+    let buf = Encoder::with(|encoder| {
+        encoder.layer("l", &["a", "b"], None);
+        encoder.sync_set("s", &["c"], None);
+        for topic in ["a", "b", "c", "d"] {
+            encoder.topic(topic).push(vertex!(0; 0, 0; 0));
         }
+    });
+    apply(&mut figure, buf).unwrap();
+    // 编码器中删除之前写入的内容一并丢弃
+    let buf = Encoder::with(|encoder| {
+        encoder.topic("d").push(vertex!(0; 1, 1; 0));
+        encoder.remove_topic("d");
+        encoder.remove_layer("l");
+        encoder.dissolve_sync_set("s");
+        encoder.topic("e").push(vertex!(0; 0, 0; 0));
+    });
+    let packet = Packet::parse(&buf).unwrap();
+    assert_eq!(packet.removals.len(), 3);
+    assert_eq!(packet.topics.len(), 1);
+    apply(&mut figure, buf).unwrap();
+    for (topic, exists) in [
+        ("a", false),
+        ("b", false),
+        ("c", true),
+        ("d", false),
+        ("e", true),
+    ] {
+        assert_eq!(figure.get_topic(topic).is_some(), exists, "{}", topic);
     }
 }
@@ -0,0 +1,103 @@
+﻿use super::DecodeError;
+use crate::Vertex;
+use std::time::Duration;
+
+/// 带边界检查的小端序读取器
+#[derive(Clone)]
+pub(super) struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+macro_rules! read_le {
+    ($name:ident => $ty:ty) => {
+        #[inline]
+        pub fn $name(&mut self) -> Result<$ty, DecodeError> {
+            self.array().map(<$ty>::from_le_bytes)
+        }
+    };
+}
+
+impl<'a> Reader<'a> {
+    #[inline]
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// 是否已读完
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    /// 读取 `n` 个字节
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        match self.pos.checked_add(n) {
+            Some(end) if end <= self.buf.len() => {
+                let slice = &self.buf[self.pos..end];
+                self.pos = end;
+                Ok(slice)
+            }
+            _ => Err(DecodeError::Truncated),
+        }
+    }
+
+    /// 读取定长字节数组
+    #[inline]
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        self.take(N).map(|slice| slice.try_into().unwrap())
+    }
+
+    /// 截取接下来 `n` 个字节作为子读取器
+    #[inline]
+    pub fn split(&mut self, n: usize) -> Result<Self, DecodeError> {
+        self.take(n).map(Self::new)
+    }
+
+    /// 读取剩余的所有字节
+    #[inline]
+    pub fn rest(&mut self) -> &'a [u8] {
+        let begin = self.pos.min(self.buf.len());
+        self.pos = self.buf.len();
+        &self.buf[begin..]
+    }
+
+    read_le!(u8  => u8 );
+    read_le!(u16 => u16);
+    read_le!(u32 => u32);
+    read_le!(u64 => u64);
+    read_le!(f32 => f32);
+
+    /// 读取长度为 `n` 的 UTF-8 字符串
+    #[inline]
+    pub fn str(&mut self, n: usize) -> Result<&'a str, DecodeError> {
+        std::str::from_utf8(self.take(n)?).map_err(|_| DecodeError::Utf8)
+    }
+
+    /// 读取布尔值
+    #[inline]
+    pub fn bool(&mut self) -> Result<bool, DecodeError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(DecodeError::Flag(b)),
+        }
+    }
+
+    /// 读取时长：| 秒: u64 | 纳秒: u32 |
+    pub fn duration(&mut self) -> Result<Duration, DecodeError> {
+        let secs = self.u64()?;
+        let nanos = self.u32()?;
+        if nanos < 1_000_000_000 {
+            Ok(Duration::new(secs, nanos))
+        } else {
+            Err(DecodeError::Duration)
+        }
+    }
+
+    /// 读取顶点
+    #[inline]
+    pub fn vertex(&mut self) -> Result<Vertex, DecodeError> {
+        Vertex::from_le_bytes(self.array()?).map_err(DecodeError::Shape)
+    }
+}
@@ -1,55 +1,46 @@
-﻿use super::Visibility;
+﻿use super::{reader::Reader, DecodeError};
+use crate::protocol::Visibility;
 use std::time::Duration;
 
-#[derive(Clone, Copy)]
-pub(super) struct SyncSets<'a>(pub &'a [u16], pub &'a [u8]);
+pub(super) type SyncSets<'a> = Vec<(&'a str, Duration)>;
+pub(super) type Layers<'a> = Vec<(&'a str, Visibility)>;
 
-#[derive(Clone, Copy)]
-pub(super) struct Layers<'a>(pub &'a [u16], pub &'a [u8]);
-
-macro_rules! size_of {
-    ($ty:ty) => {
-        std::mem::size_of::<$ty>()
-    };
-}
-
-macro_rules! get_item_slice {
-    ($obj:expr, $i:expr) => {{
-        let begin = if $i == 0 { 0 } else { $obj.0[$i - 1] as usize };
-        &$obj.1[begin..$obj.0[$i] as usize]
-    }};
+/// 解析同步组表
+#[inline]
+pub(super) fn read_sync_sets<'a>(reader: &mut Reader<'a>) -> Result<SyncSets<'a>, DecodeError> {
+    read_by_tails(reader, Reader::duration)
 }
 
-impl<'a> SyncSets<'a> {
-    #[inline]
-    pub fn len(&self) -> usize {
-        self.0.len()
-    }
-
-    pub fn get(&self, i: usize) -> (&'a str, &'a Duration) {
-        let slice = get_item_slice!(self, i);
-        unsafe {
-            (
-                std::str::from_utf8_unchecked(&slice[size_of!(Duration)..]),
-                &*(slice.as_ptr() as *const Duration),
-            )
-        }
-    }
+/// 解析图层表
+#[inline]
+pub(super) fn read_layers<'a>(reader: &mut Reader<'a>) -> Result<Layers<'a>, DecodeError> {
+    read_by_tails(reader, |item| {
+        Visibility::try_from(item.u8()?).map_err(DecodeError::Visibility)
+    })
 }
 
-impl<'a> Layers<'a> {
-    #[inline]
-    pub fn len(&self) -> usize {
-        self.0.len()
+/// 解析：| 数量 n | 每个尾部位置 × n | 逐个编码 |
+fn read_by_tails<'a, T>(
+    reader: &mut Reader<'a>,
+    mut f: impl FnMut(&mut Reader<'a>) -> Result<T, DecodeError>,
+) -> Result<Vec<(&'a str, T)>, DecodeError> {
+    let n = reader.u16()? as usize;
+    let mut tails = Vec::with_capacity(n);
+    for _ in 0..n {
+        tails.push(reader.u16()? as usize);
     }
-
-    pub fn get(&self, i: usize) -> (&'a str, &'a Visibility) {
-        let slice = get_item_slice!(self, i);
-        unsafe {
-            (
-                std::str::from_utf8_unchecked(&slice[size_of!(Visibility)..]),
-                &*(slice.as_ptr() as *const Visibility),
-            )
+    let mut content = reader.split(tails.last().copied().unwrap_or(0))?;
+    let mut result = Vec::with_capacity(n);
+    let mut begin = 0;
+    for tail in tails {
+        if tail < begin {
+            return Err(DecodeError::Tails);
         }
+        let mut item = content.split(tail - begin)?;
+        let value = f(&mut item)?;
+        let name = std::str::from_utf8(item.rest()).map_err(|_| DecodeError::Utf8)?;
+        result.push((name, value));
+        begin = tail;
     }
+    Ok(result)
 }
@@ -1,12 +1,36 @@
-﻿use super::Visibility;
-use crate::Vertex;
+﻿use super::{
+    attach, Attachment, Cells, Ellipse, EntityCommand, Layer, Packet, Removal, Style, SyncSet,
+    Tile, ViewCommand, Visibility, MAGIC, VERSION,
+};
+use crate::{Shape, Vertex};
 use palette::{rgb::channels::Argb, Packed, Srgba};
-use std::{alloc::Layout, collections::HashMap, time::Duration};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        OnceLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// 帧序号
+static FRAME: AtomicU32 = AtomicU32::new(0);
+
+/// 产生帧序号，从随机值开始，同一主机上的多个生产者不易冲突
+#[inline]
+fn next_frame() -> u32 {
+    static SEED: OnceLock<u32> = OnceLock::new();
+    SEED.get_or_init(rand::random)
+        .wrapping_add(FRAME.fetch_add(1, Ordering::Relaxed))
+}
 
 #[derive(Default)]
 pub struct Encoder {
     sync_sets: HashMap<String, WithIndex<Duration>>,
     layers: HashMap<String, WithIndex<Visibility>>,
+    removals: Vec<Removal>,
+    views: Vec<ViewCommand>,
+    time: Option<SystemTime>,
     topics: HashMap<String, TopicBody>,
 }
 
@@ -25,25 +49,233 @@ struct TopicBody {
     clear: bool,
     capacity: u32,
     focus: u32,
+    retention: Duration,
     colors: HashMap<u8, u32>,
+    styles: HashMap<u8, Style>,
     vertex: Vec<Vertex>,
+    attachments: Vec<Attachment>,
+    tiles: Vec<Tile>,
+    entities: Vec<EntityCommand>,
 }
 
-fn encode_val<T: Copy>(val: &T, buf: &mut Vec<u8>) {
-    let layout = Layout::for_value(val);
-    let rest = buf.len() % layout.align();
-    buf.resize(
-        buf.len() + layout.size() + if rest > 0 { layout.align() - rest } else { 0 },
-        0,
-    );
-    let offset = buf.len() - layout.size();
-    unsafe { (buf[offset..].as_mut_ptr() as *mut T).write(*val) };
+/// 按小端序编码的值
+trait EncodeLe {
+    fn encode_le(&self, buf: &mut Vec<u8>);
+}
+
+macro_rules! impl_encode_le {
+    ($($ty:ty)+) => {
+        $(
+            impl EncodeLe for $ty {
+                #[inline]
+                fn encode_le(&self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_le_bytes());
+                }
+            }
+        )+
+    };
+}
+
+impl_encode_le!(u8 u16 u32 u64 f32);
+
+impl EncodeLe for bool {
+    #[inline]
+    fn encode_le(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+}
+
+impl EncodeLe for Duration {
+    /// | 秒: u64 | 纳秒: u32 |
+    #[inline]
+    fn encode_le(&self, buf: &mut Vec<u8>) {
+        self.as_secs().encode_le(buf);
+        self.subsec_nanos().encode_le(buf);
+    }
+}
+
+impl EncodeLe for Visibility {
+    #[inline]
+    fn encode_le(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+}
+
+impl EncodeLe for Vertex {
+    #[inline]
+    fn encode_le(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
 }
 
 macro_rules! extend {
-    (     $value:expr => $vec:expr) => { encode_val(&$value, $vec) };
+    (     $value:expr => $vec:expr) => { EncodeLe::encode_le(&$value, $vec) };
     (str; $value:expr => $vec:expr) => { $vec.extend_from_slice($value.as_bytes()) };
-    (len; $value:expr => $vec:expr) => { extend!($value as u16 => $vec) };
+    (len; $value:expr => $vec:expr) => { extend!(u16::try_from($value).expect("length exceeds u16") => $vec) };
+}
+
+impl EncodeLe for Style {
+    /// | 点形状: u8 | 点大小: f32 | 线宽: f32 | 虚线实段: f32 | 虚线空段: f32 | 箭头长度: f32 | 箭头世界单位: u8 |
+    /// | 向量缩放: f32 | 着色模长下限: f32 | 着色模长上限: f32 |
+    fn encode_le(&self, buf: &mut Vec<u8>) {
+        extend!(self.marker as u8 => buf);
+        extend!(self.marker_size  => buf);
+        extend!(self.line_width   => buf);
+        extend!(self.dash[0]      => buf);
+        extend!(self.dash[1]      => buf);
+        extend!(self.arrow_length => buf);
+        extend!(self.arrow_world  => buf);
+        extend!(self.vector_scale => buf);
+        extend!(self.magnitude[0] => buf);
+        extend!(self.magnitude[1] => buf);
+    }
+}
+
+impl EncodeLe for Attachment {
+    /// 文字：  | 锚点: u8 | 世界单位: u8 | 字号: f32 | 长度: u16 | 内容: UTF-8 |
+    /// 矩形：  | 宽: f32 | 高: f32 | 填充透明度: u8 |
+    /// 多边形：| 填充透明度: u8 | 点数: u16 | (x: f32, y: f32) × n |
+    /// 椭圆：  | 半轴 a: f32 | 半轴 b: f32 | 填充透明度: u8 |
+    /// 向量：  | dx: f32 | dy: f32 |
+    fn encode_le(&self, buf: &mut Vec<u8>) {
+        match self {
+            Attachment::Text(label) => {
+                let text = truncate(&label.text);
+                extend!(     label.anchor as u8 => buf);
+                extend!(     label.world        => buf);
+                extend!(     label.size         => buf);
+                extend!(len; text.len()         => buf);
+                extend!(str; text               => buf);
+            }
+            Attachment::Rect(rect) => {
+                extend!(rect.width  => buf);
+                extend!(rect.height => buf);
+                extend!(rect.fill   => buf);
+            }
+            Attachment::Ellipse(ellipse) => {
+                extend!(ellipse.a    => buf);
+                extend!(ellipse.b    => buf);
+                extend!(ellipse.fill => buf);
+            }
+            Attachment::Vector(vector) => {
+                extend!(vector.dx => buf);
+                extend!(vector.dy => buf);
+            }
+            Attachment::Polygon(polygon) => {
+                let points = &polygon.points[..polygon.points.len().min(u16::MAX as usize)];
+                extend!(     polygon.fill => buf);
+                extend!(len; points.len() => buf);
+                for [x, y] in points {
+                    extend!(*x => buf);
+                    extend!(*y => buf);
+                }
+            }
+        }
+    }
+}
+
+impl EncodeLe for EntityCommand {
+    /// 写入：| 0: u8 | 编号: u32 | 顶点 | 附件 |
+    /// 移动：| 1: u8 | 编号: u32 | x: f32 | y: f32 | 补充数据: f32 |
+    /// 删除：| 2: u8 | 编号: u32 |
+    /// 写入边：| 3: u8 | 起点编号: u32 | 终点编号: u32 | 级别: u8 | 透明度: u8 |
+    /// 删除边：| 4: u8 | 起点编号: u32 | 终点编号: u32 |
+    fn encode_le(&self, buf: &mut Vec<u8>) {
+        match self {
+            EntityCommand::Put {
+                id,
+                vertex,
+                attachment,
+            } => {
+                extend!(0u8     => buf);
+                extend!(*id     => buf);
+                extend!(*vertex => buf);
+                if let Some(attachment) = attachment {
+                    extend!(*attachment => buf);
+                }
+            }
+            EntityCommand::Move { id, x, y, extra } => {
+                extend!(1u8    => buf);
+                extend!(*id    => buf);
+                extend!(*x     => buf);
+                extend!(*y     => buf);
+                extend!(*extra => buf);
+            }
+            EntityCommand::Remove(id) => {
+                extend!(2u8 => buf);
+                extend!(*id => buf);
+            }
+            EntityCommand::PutEdge {
+                from,
+                to,
+                level,
+                alpha,
+            } => {
+                extend!(3u8    => buf);
+                extend!(*from  => buf);
+                extend!(*to    => buf);
+                extend!(*level => buf);
+                extend!(*alpha => buf);
+            }
+            EntityCommand::RemoveEdge { from, to } => {
+                extend!(4u8   => buf);
+                extend!(*from => buf);
+                extend!(*to   => buf);
+            }
+        }
+    }
+}
+
+impl EncodeLe for Tile {
+    #[inline]
+    fn encode_le(&self, buf: &mut Vec<u8>) {
+        self.encode_rows(0..self.height(), buf);
+    }
+}
+
+impl Tile {
+    /// 块头部长度
+    #[inline]
+    fn head_len(&self) -> usize {
+        4 + 4 + 4 + 4 + 4 + 2 + 8 * self.palette.len().min(u16::MAX as usize) + 4 + 4 + 4 + 4 + 1
+    }
+
+    /// 只编码块中的几行
+    ///
+    /// | 原点 x: f32 | 原点 y: f32 | 分辨率: f32 | 列数: u32 | 行数: u32 |
+    /// | 色标数: u16 | (值: f32, ARGB: u32) × n |
+    /// | 起始列: u32 | 起始行: u32 | 块列数: u32 | 块行数: u32 | 类型: u8 (0 u8, 1 f32) | 值 × 块列数 × 块行数 |
+    fn encode_rows(&self, rows: std::ops::Range<u32>, buf: &mut Vec<u8>) {
+        let palette = &self.palette[..self.palette.len().min(u16::MAX as usize)];
+        extend!(     self.grid.origin[0]  => buf);
+        extend!(     self.grid.origin[1]  => buf);
+        extend!(     self.grid.resolution => buf);
+        extend!(     self.grid.width      => buf);
+        extend!(     self.grid.height     => buf);
+        extend!(len; palette.len()        => buf);
+        for (value, color) in palette {
+            extend!(*value                         => buf);
+            extend!(Packed::<Argb>::from(*color).color => buf);
+        }
+        extend!(self.column           => buf);
+        extend!(self.row + rows.start => buf);
+        extend!(self.width            => buf);
+        extend!(rows.len() as u32     => buf);
+        let width = self.width as usize;
+        let range = rows.start as usize * width..rows.end as usize * width;
+        match &self.cells {
+            Cells::U8(cells) => {
+                extend!(0u8 => buf);
+                buf.extend_from_slice(&cells[range]);
+            }
+            Cells::F32(cells) => {
+                extend!(1u8 => buf);
+                for x in &cells[range] {
+                    extend!(*x => buf);
+                }
+            }
+        }
+    }
 }
 
 impl Encoder {
@@ -58,7 +290,7 @@ impl Encoder {
     /// 立即编码
     #[inline]
     pub fn with_topic(&mut self, topic: impl ToString, f: impl FnOnce(TopicEncoder)) {
-        f(self.topic(topic.to_string()));
+        f(self.topic(topic));
     }
 
     /// 配置话题
@@ -70,7 +302,7 @@ impl Encoder {
         colors: &[(u8, Srgba)],
         f: impl FnOnce(&mut TopicEncoder),
     ) {
-        let mut encoder = self.topic(topic.to_string());
+        let mut encoder = self.topic(topic);
         encoder.set_capacity(capacity);
         encoder.set_focus(focus);
         for (level, color) in colors {
@@ -82,7 +314,7 @@ impl Encoder {
     /// 构造话题编码器
     #[inline]
     pub fn topic(&mut self, topic: impl ToString) -> TopicEncoder<'_> {
-        TopicEncoder(self.topics.entry(topic.to_string()).or_default())
+        TopicEncoder(self.topics.entry(name(topic)).or_default())
     }
 
     /// 更新同步组
@@ -99,7 +331,7 @@ impl Encoder {
         let next = self.sync_sets.len() as u16;
         let body = self
             .sync_sets
-            .entry(set.to_string())
+            .entry(table_name(set))
             .or_insert_with(|| WithIndex {
                 index: next + 1,
                 value: Default::default(),
@@ -108,7 +340,7 @@ impl Encoder {
             body.value = life_time;
         }
         // 更新序号
-        for topic in topics.iter().map(|it| it.to_string()) {
+        for topic in topics.iter().map(|it| name(it.to_string())) {
             self.topics.entry(topic).or_default().sync_set = body.index;
         }
     }
@@ -122,48 +354,607 @@ impl Encoder {
         let next = self.layers.len() as u16;
         let body = self
             .layers
-            .entry(layer.to_string())
+            .entry(table_name(layer))
             .or_insert_with(|| WithIndex {
                 index: next + 1,
                 value: Default::default(),
             });
-        body.value = visible.into();
+        if visible.is_some() {
+            body.value = visible.into();
+        }
         // 更新序号
-        for topic in topics.iter().map(|it| it.to_string()) {
+        for topic in topics.iter().map(|it| name(it.to_string())) {
             self.topics.entry(topic).or_default().layer = body.index;
         }
     }
 
+    /// 删除话题
+    ///
+    /// 删除命令在包内先于其他内容执行，因此之前写入这个话题的内容一并丢弃，之后的写入重建话题。
+    pub fn remove_topic(&mut self, topic: impl ToString) {
+        let topic = name(topic);
+        self.topics.remove(&topic);
+        self.removals.push(Removal::Topic(topic));
+    }
+
+    /// 删除图层及其中的所有话题
+    ///
+    /// 之前写入这个图层的话题和可见性一并丢弃。
+    pub fn remove_layer(&mut self, layer: impl ToString) {
+        let layer = table_name(layer);
+        if let Some(index) = remove_indexed(&mut self.layers, &layer) {
+            self.topics.retain(|_, body| body.layer != index);
+            for body in self.topics.values_mut() {
+                if body.layer > index {
+                    body.layer -= 1;
+                }
+            }
+        }
+        self.removals.push(Removal::Layer(layer));
+    }
+
+    /// 解散同步组，其中的话题保留
+    ///
+    /// 之前写入的时限和成员关系一并丢弃。
+    pub fn dissolve_sync_set(&mut self, set: impl ToString) {
+        let set = table_name(set);
+        if let Some(index) = remove_indexed(&mut self.sync_sets, &set) {
+            for body in self.topics.values_mut() {
+                if body.sync_set == index {
+                    body.sync_set = 0;
+                } else if body.sync_set > index {
+                    body.sync_set -= 1;
+                }
+            }
+        }
+        self.removals.push(Removal::SyncSet(set));
+    }
+
+    /// 设置视野中心和比例，非有限值表示不修改，比例为 0 表示开启自动视野
+    #[inline]
+    pub fn set_view(&mut self, x: f32, y: f32, scale: f32) {
+        self.views.push(ViewCommand::Look { x, y, scale });
+    }
+
+    /// 使视野适应话题的范围，并关闭自动视野
+    #[inline]
+    pub fn fit_topic(&mut self, topic: impl ToString) {
+        self.views.push(ViewCommand::Fit(name(topic)));
+    }
+
+    /// 开启或关闭自动视野
+    #[inline]
+    pub fn set_auto_view(&mut self, auto: bool) {
+        self.views.push(ViewCommand::Auto(auto));
+    }
+
+    /// 设置源时间，即包内数据产生的时刻
+    ///
+    /// 接收端按源时间而不是到达时间决定同步和过期；早于 UNIX 纪元的时间视为未设置。
+    #[inline]
+    pub fn set_time(&mut self, time: SystemTime) {
+        self.time = Some(time).filter(|t| *t > UNIX_EPOCH);
+    }
+
+    /// 合并另一个编码器
+    ///
+    /// 后合并的配置覆盖先前的配置，顶点依次追加，效果与依次解码两者相同。
+    /// 一个包只有一个源时间，因此当前编码器不空且两者的源时间不同时不合并，原样退回 `other`。
+    pub fn merge(&mut self, other: Encoder) -> Result<(), Box<Encoder>> {
+        if self.time != other.time {
+            if !self.is_empty() {
+                return Err(Box::new(other));
+            }
+            self.time = other.time;
+        }
+        let Encoder {
+            sync_sets,
+            layers,
+            removals,
+            views,
+            time: _,
+            topics,
+        } = other;
+        for removal in removals {
+            self.remove(removal);
+        }
+        self.views.extend(views);
+        for (name, body) in &sync_sets {
+            let life_time = Some(body.value).filter(|t| !t.is_zero());
+            self.sync_set(name, &[] as &[&str], life_time);
+        }
+        for (name, body) in &layers {
+            let visible = match body.value {
+                Visibility::NothingToDo => None,
+                Visibility::Visible => Some(true),
+                Visibility::Invisible => Some(false),
+            };
+            self.layer(name, &[] as &[&str], visible);
+        }
+        // 序号换成名字再重新登记
+        let sync_sets = names_by_index(&sync_sets);
+        let layers = names_by_index(&layers);
+        for (name, body) in topics {
+            if let Some(i) = body.sync_set.checked_sub(1) {
+                self.sync_set(sync_sets[i as usize], &[&name], None);
+            }
+            if let Some(i) = body.layer.checked_sub(1) {
+                self.layer(layers[i as usize], &[&name], None);
+            }
+            let mut topic = self.topic(name);
+            if body.clear {
+                topic.clear();
+            }
+            if body.capacity > 0 {
+                topic.set_capacity(body.capacity);
+            }
+            if body.focus > 0 {
+                topic.set_focus(body.focus);
+            }
+            if !body.retention.is_zero() {
+                topic.0.retention = body.retention;
+            }
+            topic.0.colors.extend(body.colors);
+            topic.0.styles.extend(body.styles);
+            topic.0.append(body.vertex, body.attachments);
+            topic.0.tiles.extend(body.tiles);
+            topic.0.entities.extend(body.entities);
+        }
+        Ok(())
+    }
+
+    /// 是否没有任何内容
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.sync_sets.is_empty()
+            && self.layers.is_empty()
+            && self.removals.is_empty()
+            && self.views.is_empty()
+            && self.topics.is_empty()
+    }
+
+    /// 执行删除命令
+    fn remove(&mut self, removal: Removal) {
+        match removal {
+            Removal::Topic(name) => self.remove_topic(name),
+            Removal::Layer(name) => self.remove_layer(name),
+            Removal::SyncSet(name) => self.dissolve_sync_set(name),
+        }
+    }
+
+    /// 话题内容编码后的大致长度，包括顶点、附件、栅格块和实体命令
+    #[inline]
+    pub(crate) fn content_len(&self) -> usize {
+        self.topics
+            .iter()
+            .map(|(name, body)| body.content_len(name))
+            .sum()
+    }
+
     /// 编码
+    ///
+    /// 所有字段均为小端序，字段之间没有填充：
+    ///
+    /// ```text
+    /// | 魔数: b"MTRS" | 版本: u8 | 帧序号: u32 | 分片序号: u16 | 分片数: u16 |
+    /// | 同步组表 | 图层表 | 源时间秒: u64 | 源时间纳秒: u32 | 删除表 | 视野命令表 | 话题 × n |
+    ///
+    /// 表：     | 数量 n: u16 | 每项尾部位置: u16 × n | 项 × n |
+    /// 同步组项：| 时限秒: u64 | 时限纳秒: u32 | 名字: UTF-8 |
+    /// 图层项：  | 可见性: u8 (0 不变, 0x55 显示, 0xaa 隐藏) | 名字: UTF-8 |
+    /// 删除表：  | 数量 n: u16 | (类型: u8 (0 话题, 1 图层, 2 同步组), 名字长度: u16, 名字: UTF-8) × n |
+    /// 视野命令表：| 数量 n: u16 | 命令 × n |
+    /// 视野命令：| 0: u8 | x: f32 | y: f32 | 比例: f32 |
+    ///          | 1: u8 | 话题名字长度: u16 | 话题名字: UTF-8 |（适应话题范围）
+    ///          | 2: u8 | 自动视野: u8 |
+    ///
+    /// 话题：| 名字长度: u16 | 名字: UTF-8 | 同步组序号: u16 | 图层序号: u16 |
+    ///       | 清空: u8 | 容量: u32 | 关注数量: u32 | 保留时长秒: u64 | 保留时长纳秒: u32 |
+    ///       | 颜色数: u16 | (级别: u8, ARGB: u32) × n |
+    ///       | 样式数: u16 | (级别: u8, 样式) × n |
+    ///       | 顶点数: u16 | 顶点 × n | 附件 × m | 栅格块数: u16 | 栅格块 × k |
+    ///       | 实体命令数: u16 | 实体命令 × l |
+    ///
+    /// 顶点：| x: f32 | y: f32 | 级别: u8 | 连线透明度: u8 | 0: u8 | 形状: u8 | 补充数据: f32 |
+    /// 样式：| 点形状: u8 (0 方块, 1 圆点, 2 叉) | 点大小: f32 | 线宽: f32 |
+    ///      | 虚线实段: f32 | 虚线空段: f32 | 箭头长度: f32 | 箭头世界单位: u8 |
+    ///      | 向量缩放: f32 | 着色模长下限: f32 | 着色模长上限: f32 |
+    /// 附件：| 文字锚点: u8 | 世界单位: u8 | 字号: f32 | 长度: u16 | 内容: UTF-8 |
+    ///      | 矩形宽: f32 | 高: f32 | 填充透明度: u8 |
+    ///      | 多边形填充透明度: u8 | 点数: u16 | (x: f32, y: f32) × n |
+    ///      | 椭圆半轴 a: f32 | 半轴 b: f32 | 填充透明度: u8 |
+    ///      | 向量 dx: f32 | dy: f32 |
+    /// 栅格块：| 原点 x: f32 | 原点 y: f32 | 分辨率: f32 | 列数: u32 | 行数: u32 |
+    ///        | 色标数: u16 | (值: f32, ARGB: u32) × n |
+    ///        | 起始列: u32 | 起始行: u32 | 块列数: u32 | 块行数: u32 | 类型: u8 (0 u8, 1 f32) | 值 × 块列数 × 块行数 |
+    /// 实体命令：| 0: u8 | 编号: u32 | 顶点 | 附件 |（写入）
+    ///          | 1: u8 | 编号: u32 | x: f32 | y: f32 | 补充数据: f32 |（移动）
+    ///          | 2: u8 | 编号: u32 |（删除）
+    ///          | 3: u8 | 起点编号: u32 | 终点编号: u32 | 级别: u8 | 透明度: u8 |（写入边）
+    ///          | 4: u8 | 起点编号: u32 | 终点编号: u32 |（删除边）
+    /// ```
+    ///
+    /// 尾部位置从表内容起点计；序号从 1 开始，0 表示不修改。
+    /// 名字在写入编码器时于字符边界截断，话题名字不超过 65535 字节，同步组和图层名字不超过 65523 字节。
+    /// 源时间从 UNIX 纪元计，全为 0 表示没有源时间，接收端使用到达时间。
+    /// 保留时长为 0 表示不修改，秒和纳秒取最大值表示不限。
+    /// 删除命令先于包内其他内容执行，视野命令在整帧的话题更新之后执行。
+    /// 附件依次对应段内需要附件的顶点（文字、矩形、多边形、椭圆、向量）。
+    /// 顶点超过 65535 个的话题拆成多段连续编码，后续段不带配置。
+    /// 栅格块在顶点之后单独成段，放不进一个包的块按行拆开；实体命令在栅格块之后单独成段。
+    #[inline]
     pub fn encode(self) -> Vec<u8> {
-        let mut buf = Vec::new();
-        let ref_buf: &mut Vec<u8> = &mut buf;
+        // 不限包长时不会换包，所有内容都在最后一个包里
+        let (_, mut packet) = self.split(usize::MAX);
+        set_fragment(&mut packet, 0, 1);
+        packet
+    }
+
+    /// 编码为多个不超过 `max_datagram` 字节的包
+    ///
+    /// 每个包都带有完整的包头、同步组表、图层表和源时间，可以单独解码；删除表和视野命令表只随第一个包发送。
+    /// 话题的清空、容量、关注数量、保留时长、颜色和样式只随话题的第一段发送，
+    /// 因此按顺序解码所有包与解码一个大包得到的画面相同。
+    /// 同一次编码的所有包属于同一帧，接收端收齐后一次性应用。
+    /// 如果包头和表或单个话题头部已经超过 `max_datagram`，这个包会超限。
+    /// 分片数超过 65535 时返回错误。
+    pub fn encode_chunks(self, max_datagram: usize) -> Result<Vec<Vec<u8>>, EncodeError> {
+        let (mut chunks, last) = self.split(max_datagram);
+        if !last.is_empty() {
+            chunks.push(last);
+        }
+        let count =
+            u16::try_from(chunks.len()).map_err(|_| EncodeError::Fragments(chunks.len()))?;
+        for (i, chunk) in chunks.iter_mut().enumerate() {
+            set_fragment(chunk, i as u16, count);
+        }
+        Ok(chunks)
+    }
+
+    /// 按包长拆分，返回已装满的包和最后一个包，分片信息留空
+    ///
+    /// 最后一个包没有新内容时为空；没有换过包时总是返回最后一个包。
+    fn split(self, max_datagram: usize) -> (Vec<Vec<u8>>, Vec<u8>) {
+        let mut head = Vec::new();
+        // 编码包头，分片序号和分片数最后填写
+        head.extend_from_slice(&MAGIC);
+        extend!(VERSION      => &mut head);
+        extend!(next_frame() => &mut head);
+        extend!(0u32         => &mut head);
         // 编码同步组
-        sort_and_encode(&self.sync_sets, ref_buf);
+        sort_and_encode(&self.sync_sets, &mut head);
         // 编码图层
-        sort_and_encode(&self.layers, ref_buf);
+        sort_and_encode(&self.layers, &mut head);
+        // 编码源时间
+        let time = self.time.map_or(Duration::ZERO, |t| {
+            t.duration_since(UNIX_EPOCH).unwrap_or_default()
+        });
+        extend!(time => &mut head);
+        // 编码删除命令，只放在第一个包
+        let mut buf = head.clone();
+        extend!(len; self.removals.len() => &mut buf);
+        for removal in &self.removals {
+            let (kind, name) = match removal {
+                Removal::Topic(name) => (0u8, name),
+                Removal::Layer(name) => (1, name),
+                Removal::SyncSet(name) => (2, name),
+            };
+            extend!(     kind       => &mut buf);
+            extend!(len; name.len() => &mut buf);
+            extend!(str; name       => &mut buf);
+        }
+        // 编码视野命令，只放在第一个包
+        extend!(len; self.views.len() => &mut buf);
+        for view in &self.views {
+            match view {
+                ViewCommand::Look { x, y, scale } => {
+                    extend!(0u8    => &mut buf);
+                    extend!(*x     => &mut buf);
+                    extend!(*y     => &mut buf);
+                    extend!(*scale => &mut buf);
+                }
+                ViewCommand::Fit(topic) => {
+                    extend!(1u8         => &mut buf);
+                    extend!(len; topic.len() => &mut buf);
+                    extend!(str; topic       => &mut buf);
+                }
+                ViewCommand::Auto(auto) => {
+                    extend!(2u8   => &mut buf);
+                    extend!(*auto => &mut buf);
+                }
+            }
+        }
+        extend!(0u16 => &mut head);
+        extend!(0u16 => &mut head);
         // 编码话题
+        let mut chunks = Vec::new();
+        let mut base = buf.len();
         for (name, body) in self.topics {
-            extend!(len; name.len()    => ref_buf);
-            extend!(str; name          => ref_buf);
-            extend!(     body.sync_set => ref_buf);
-            extend!(     body.layer    => ref_buf);
-            extend!(     body.clear    => ref_buf);
-            extend!(     body.capacity => ref_buf);
-            extend!(     body.focus    => ref_buf);
+            let mut vertex = body.vertex.as_slice();
+            let mut attachments = body.attachments.as_slice();
+            let mut first = true;
+            loop {
+                let len_head = body.head_len(&name, first);
+                // 顶点连同附件的长度
+                let mut items = vertex.iter().scan(attachments, |attachments, v| {
+                    let mut len = Vertex::ENCODED_LEN;
+                    if v.shape.needs_attachment() {
+                        len += encoded_len(&attachments[0]);
+                        *attachments = &attachments[1..];
+                    }
+                    Some(len)
+                });
+                let min_len = len_head + items.clone().next().unwrap_or(0);
+                // 当前包放不下，换新包
+                if buf.len().saturating_add(min_len) > max_datagram && buf.len() > base {
+                    chunks.push(std::mem::replace(&mut buf, head.clone()));
+                    base = head.len();
+                    continue;
+                }
+                let room = max_datagram.saturating_sub(buf.len() + len_head);
+                let mut n = items.next().map_or(0, |_| 1);
+                let mut len = min_len - len_head;
+                for item in items.take(u16::MAX as usize - 1) {
+                    len += item;
+                    if len > room {
+                        break;
+                    }
+                    n += 1;
+                }
+                body.encode_head(&name, first, n, &mut buf);
+                let m = vertex[..n]
+                    .iter()
+                    .filter(|v| v.shape.needs_attachment())
+                    .count();
+                for v in &vertex[..n] {
+                    extend!(*v => &mut buf);
+                }
+                for a in &attachments[..m] {
+                    extend!(*a => &mut buf);
+                }
+                extend!(0u16 => &mut buf);
+                extend!(0u16 => &mut buf);
+                vertex = &vertex[n..];
+                attachments = &attachments[m..];
+                first = false;
+                if vertex.is_empty() {
+                    break;
+                }
+            }
+            // 每个栅格块单独成段
+            for tile in &body.tiles {
+                let height = tile.height();
+                let row_len = tile.width as usize * tile.cells.cell_len();
+                let mut row = 0;
+                loop {
+                    let len_head = body.head_len(&name, false) + tile.head_len();
+                    // 当前包放不下一行，换新包
+                    if buf.len().saturating_add(len_head + row_len) > max_datagram
+                        && buf.len() > base
+                    {
+                        chunks.push(std::mem::replace(&mut buf, head.clone()));
+                        base = head.len();
+                        continue;
+                    }
+                    let room = max_datagram.saturating_sub(buf.len() + len_head);
+                    let n = match room.checked_div(row_len) {
+                        Some(n) => n.clamp(1, u32::MAX as usize) as u32,
+                        None => height,
+                    }
+                    .min(height - row);
+                    body.encode_head(&name, false, 0, &mut buf);
+                    extend!(1u16 => &mut buf);
+                    tile.encode_rows(row..row + n, &mut buf);
+                    extend!(0u16 => &mut buf);
+                    row += n;
+                    if row >= height {
+                        break;
+                    }
+                }
+            }
+            // 实体命令单独成段
+            let mut entities = body.entities.as_slice();
+            while let Some(first) = entities.first() {
+                let len_head = body.head_len(&name, false);
+                let min_len = len_head + command_len(first);
+                // 当前包放不下，换新包
+                if buf.len().saturating_add(min_len) > max_datagram && buf.len() > base {
+                    chunks.push(std::mem::replace(&mut buf, head.clone()));
+                    base = head.len();
+                    continue;
+                }
+                let room = max_datagram.saturating_sub(buf.len() + len_head);
+                let mut n = 1;
+                let mut len = min_len - len_head;
+                for command in entities[1..].iter().take(u16::MAX as usize - 1) {
+                    len += command_len(command);
+                    if len > room {
+                        break;
+                    }
+                    n += 1;
+                }
+                body.encode_head(&name, false, 0, &mut buf);
+                extend!(0u16 => &mut buf);
+                extend!(len; n => &mut buf);
+                for command in &entities[..n] {
+                    extend!(*command => &mut buf);
+                }
+                entities = &entities[n..];
+            }
+        }
+        if !chunks.is_empty() && buf.len() == base {
+            buf.clear();
+        }
+        (chunks, buf)
+    }
+}
+
+/// 填写包头中的分片序号和分片数
+#[inline]
+fn set_fragment(packet: &mut [u8], index: u16, count: u16) {
+    const FRAGMENT_OFFSET: usize = MAGIC.len() + 1 + 4;
+    packet[FRAGMENT_OFFSET..][..2].copy_from_slice(&index.to_le_bytes());
+    packet[FRAGMENT_OFFSET + 2..][..2].copy_from_slice(&count.to_le_bytes());
+}
+
+/// 编码错误
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EncodeError {
+    /// 分片数超过 65535
+    Fragments(usize),
+}
+
+impl std::fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Fragments(n) => write!(f, "{} fragments exceed the limit of {}", n, u16::MAX),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+impl From<EncodeError> for std::io::Error {
+    #[inline]
+    fn from(e: EncodeError) -> Self {
+        Self::new(std::io::ErrorKind::InvalidInput, e)
+    }
+}
+
+impl TopicBody {
+    /// 保存顶点，需要附件的顶点缺少附件时使用缺省附件
+    #[inline]
+    fn push(&mut self, vertex: Vertex, attachment: Option<Attachment>) {
+        if vertex.shape.needs_attachment() {
+            self.attachments.extend(
+                attachment
+                    .filter(|a| a.fits(vertex.shape))
+                    .or_else(|| Attachment::default_for(vertex.shape)),
+            );
+        }
+        self.vertex.push(vertex);
+    }
+
+    /// 追加一组顶点和对应的附件
+    #[inline]
+    fn append(&mut self, vertex: Vec<Vertex>, attachments: Vec<Attachment>) {
+        for (v, a) in attach(vertex, attachments) {
+            self.push(v, a);
+        }
+    }
+
+    /// 话题编码后的大致长度，不计拆段重复的头部
+    fn content_len(&self, name: &str) -> usize {
+        let vertex = self.vertex.len() * Vertex::ENCODED_LEN;
+        let attachments: usize = self.attachments.iter().map(encoded_len).sum();
+        let tiles: usize = self
+            .tiles
+            .iter()
+            .map(|tile| {
+                let cells = tile.width as usize * tile.height() as usize;
+                tile.head_len() + cells * tile.cells.cell_len()
+            })
+            .sum();
+        let entities: usize = self.entities.iter().map(command_len).sum();
+        self.head_len(name, true) + vertex + attachments + tiles + entities
+    }
+
+    /// 话题头部连同栅格块数和实体命令数的长度
+    #[inline]
+    fn head_len(&self, name: &str, first: bool) -> usize {
+        let (colors, styles) = if first {
+            (self.colors.len(), self.styles.len())
+        } else {
+            (0, 0)
+        };
+        2 + name.len() + 2 + 2 + 1 + 4 + 4 + 12 + 2 + colors * 5 + 2 + styles * 35 + 2 + 2 + 2
+    }
+
+    /// 编码话题头部，非第一段不带配置
+    fn encode_head(&self, name: &str, first: bool, n: usize, buf: &mut Vec<u8>) {
+        extend!(len; name.len()    => buf);
+        extend!(str; name          => buf);
+        extend!(     self.sync_set => buf);
+        extend!(     self.layer    => buf);
+        if first {
+            extend!(self.clear    => buf);
+            extend!(self.capacity => buf);
+            extend!(self.focus    => buf);
+            extend!(self.retention => buf);
             // 编码颜色
-            extend!(len; body.colors.len() => ref_buf);
-            for (level, rgba) in body.colors {
-                extend!(level as u32 => ref_buf);
-                extend!(rgba         => ref_buf);
+            extend!(len; self.colors.len() => buf);
+            for (level, rgba) in &self.colors {
+                extend!(*level => buf);
+                extend!(*rgba  => buf);
             }
-            // 编码顶点
-            extend!(len; body.vertex.len() => ref_buf);
-            for v in body.vertex {
-                extend!(v => ref_buf);
+            // 编码样式
+            extend!(len; self.styles.len() => buf);
+            for (level, style) in &self.styles {
+                extend!(*level => buf);
+                extend!(*style => buf);
             }
+        } else {
+            extend!(false => buf);
+            extend!(0u32  => buf);
+            extend!(0u32  => buf);
+            extend!(Duration::ZERO => buf);
+            extend!(0u16  => buf);
+            extend!(0u16  => buf);
         }
-        buf
+        // 顶点数
+        extend!(len; n => buf);
+    }
+}
+
+impl From<Packet> for Encoder {
+    /// 从解析完成的包恢复编码器，再次编码后效果相同
+    fn from(packet: Packet) -> Self {
+        let mut encoder = Self::default();
+        for removal in packet.removals {
+            encoder.remove(removal);
+        }
+        encoder.views = packet.views;
+        encoder.time = packet.time;
+        for SyncSet { name, life_time } in packet.sync_sets {
+            encoder.sync_set(name, &[] as &[&str], life_time);
+        }
+        for topic in packet.topics {
+            if let Some(sync_set) = &topic.sync_set {
+                encoder.sync_set(sync_set, &[&topic.name], None);
+            }
+            if let Some(layer) = &topic.layer {
+                encoder.layer(layer, &[&topic.name], None);
+            }
+            let mut encoder = encoder.topic(&topic.name);
+            if topic.clear {
+                encoder.clear();
+            }
+            if topic.capacity > 0 {
+                encoder.set_capacity(topic.capacity);
+            }
+            if topic.focus > 0 {
+                encoder.set_focus(topic.focus);
+            }
+            if let Some(retention) = topic.retention {
+                encoder.0.retention = retention;
+            }
+            for (level, color) in topic.colors {
+                encoder
+                    .0
+                    .colors
+                    .insert(level, Packed::<Argb>::from(color).color);
+            }
+            for (level, style) in topic.styles {
+                encoder.set_style(level, style);
+            }
+            encoder.0.append(topic.vertex, topic.attachments);
+            encoder.0.tiles.extend(topic.tiles);
+            encoder.0.entities.extend(topic.entities);
+        }
+        for Layer { name, visible } in packet.layers {
+            encoder.layer(name, &[] as &[&str], visible);
+        }
+        encoder
     }
 }
 
@@ -176,6 +967,18 @@ impl<'a> TopicEncoder<'a> {
             .insert(level, Packed::<Argb>::from(color.into_format()).color);
     }
 
+    /// 设置级别样式
+    #[inline]
+    pub fn set_style(&mut self, level: u8, style: Style) {
+        self.0.styles.insert(level, style);
+    }
+
+    /// 修改级别样式，未设置的级别从 [`Style::DEFAULT`] 开始
+    #[inline]
+    pub fn style_mut(&mut self, level: u8) -> &mut Style {
+        self.0.styles.entry(level).or_default()
+    }
+
     /// 设置话题容量
     #[inline]
     pub fn set_capacity(&mut self, capacity: u32) {
@@ -188,23 +991,148 @@ impl<'a> TopicEncoder<'a> {
         self.0.focus = focus;
     }
 
-    /// 清空话题缓存
+    /// 设置保留时长，只保留最近这么长时间内的顶点和实体，`None` 表示不限
+    ///
+    /// 与容量同时生效，与是否加入同步组无关。
+    #[inline]
+    pub fn set_retention(&mut self, retention: Option<Duration>) {
+        self.0.retention = retention.filter(|t| !t.is_zero()).unwrap_or(Duration::MAX);
+    }
+
+    /// 清空话题缓存，包括栅格和所有实体
+    ///
+    /// 之前写入这个话题的顶点、栅格块和实体命令一并丢弃。
     #[inline]
     pub fn clear(&mut self) {
         self.0.vertex.clear();
+        self.0.attachments.clear();
+        self.0.tiles.clear();
+        self.0.entities.clear();
         self.0.clear = true;
     }
 
-    /// 保存顶点
+    /// 保存顶点，需要附件的形状使用缺省附件
     #[inline]
     pub fn push(&mut self, vertex: Vertex) {
-        self.0.vertex.push(vertex);
+        self.0.push(vertex, None);
+    }
+
+    /// 保存带附件的顶点
+    ///
+    /// 顶点形状与附件不符时改为附件对应的形状，因此矩形要旋转时顶点形状应为 [`Shape::OrientedBox`]。
+    #[inline]
+    pub fn push_attached(&mut self, vertex: Vertex, attachment: impl Into<Attachment>) {
+        let attachment = attachment.into();
+        let vertex = Vertex {
+            shape: if attachment.fits(vertex.shape) {
+                vertex.shape
+            } else {
+                attachment.shape()
+            },
+            ..vertex
+        };
+        self.0.push(vertex, Some(attachment));
+    }
+
+    /// 保存以顶点为中心的不确定性椭圆
+    ///
+    /// `covariance` 为协方差矩阵的 `[xx, xy, yy]`，椭圆的半轴为 `sigma` 倍标准差，转角写入补充数据。
+    #[inline]
+    pub fn push_covariance(&mut self, vertex: Vertex, covariance: [f32; 3], sigma: f32) {
+        let (ellipse, angle) = Ellipse::from_covariance(covariance, sigma);
+        let vertex = Vertex {
+            shape: Shape::Ellipse,
+            extra: angle,
+            ..vertex
+        };
+        self.0.push(vertex, Some(ellipse.into()));
+    }
+
+    /// 保存一块栅格
+    ///
+    /// 栅格画在所有顶点之下，未收到的单元 u8 为 255、f32 为 NaN。
+    /// 几何非法（见 [`Grid::is_valid`](super::Grid::is_valid)）或单元数超过 [`MAX_CELLS`](super::MAX_CELLS) 的栅格、
+    /// 超出栅格的块和不递增的色标在接收端被拒绝。
+    #[inline]
+    pub fn push_tile(&mut self, tile: Tile) {
+        self.0.tiles.push(tile);
+    }
+
+    /// 写入编号为 `id` 的实体，已有的同号实体被替换，需要附件的形状使用缺省附件
+    ///
+    /// 实体不进入顶点队列，不受容量限制，也不与其他顶点连线，适合画移动的物体。
+    #[inline]
+    pub fn put_entity(&mut self, id: u32, vertex: Vertex) {
+        self.put(id, vertex, None);
+    }
+
+    /// 写入带附件的实体，顶点形状与附件不符时改为附件对应的形状
+    #[inline]
+    pub fn put_entity_attached(
+        &mut self,
+        id: u32,
+        vertex: Vertex,
+        attachment: impl Into<Attachment>,
+    ) {
+        let attachment = attachment.into();
+        let vertex = Vertex {
+            shape: if attachment.fits(vertex.shape) {
+                vertex.shape
+            } else {
+                attachment.shape()
+            },
+            ..vertex
+        };
+        self.put(id, vertex, Some(attachment));
+    }
+
+    /// 移动实体，`extra` 为 NaN 时不修改补充数据，实体不存在时接收端忽略
+    #[inline]
+    pub fn move_entity(&mut self, id: u32, x: f32, y: f32, extra: f32) {
+        self.0
+            .entities
+            .push(EntityCommand::Move { id, x, y, extra });
+    }
+
+    /// 删除实体，连接它的边一并删除
+    #[inline]
+    pub fn remove_entity(&mut self, id: u32) {
+        self.0.entities.push(EntityCommand::Remove(id));
+    }
+
+    /// 写入从实体 `from` 到实体 `to` 的边，已有的同向边被替换
+    ///
+    /// 边的颜色和样式取 `level` 级别的配置，`alpha` 为透明度；两端的实体都存在时才画出。
+    #[inline]
+    pub fn put_edge(&mut self, from: u32, to: u32, level: u8, alpha: u8) {
+        self.0.entities.push(EntityCommand::PutEdge {
+            from,
+            to,
+            level,
+            alpha,
+        });
+    }
+
+    /// 删除从实体 `from` 到实体 `to` 的边
+    #[inline]
+    pub fn remove_edge(&mut self, from: u32, to: u32) {
+        self.0.entities.push(EntityCommand::RemoveEdge { from, to });
+    }
+
+    #[inline]
+    fn put(&mut self, id: u32, vertex: Vertex, attachment: Option<Attachment>) {
+        let (vertex, attachment) = attach([vertex], attachment).next().unwrap();
+        self.0.entities.push(EntityCommand::Put {
+            id,
+            vertex,
+            attachment,
+        });
     }
 
     /// 保存一组顶点
     #[inline]
     pub fn extend(&mut self, vertex: impl IntoIterator<Item = Vertex>) {
-        vertex.into_iter().for_each(|v| self.0.vertex.push(v));
+        vertex.into_iter().for_each(|v| self.push(v));
     }
 
     /// 保存折线
@@ -212,8 +1140,8 @@ impl<'a> TopicEncoder<'a> {
     pub fn extend_polyline(&mut self, vertex: impl IntoIterator<Item = Vertex>) {
         let mut iter = vertex.into_iter();
         if let Some(begin) = iter.next() {
-            self.0.vertex.push(Vertex { alpha: 0, ..begin });
-            iter.for_each(|v| self.0.vertex.push(v));
+            self.push(Vertex { alpha: 0, ..begin });
+            iter.for_each(|v| self.push(v));
         }
     }
 
@@ -222,15 +1150,95 @@ impl<'a> TopicEncoder<'a> {
     pub fn extend_polygon(&mut self, vertex: impl IntoIterator<Item = Vertex>) {
         let mut iter = vertex.into_iter();
         if let Some(begin) = iter.next() {
-            self.0.vertex.push(Vertex { alpha: 0, ..begin });
-            iter.for_each(|v| self.0.vertex.push(v));
-            self.0.vertex.push(begin);
+            self.push(Vertex { alpha: 0, ..begin });
+            iter.for_each(|v| self.push(v));
+            self.push(begin);
+        }
+    }
+}
+
+/// 附件编码后的长度
+#[inline]
+fn encoded_len(attachment: &Attachment) -> usize {
+    match attachment {
+        Attachment::Text(label) => 1 + 1 + 4 + 2 + truncate(&label.text).len(),
+        Attachment::Rect(_) | Attachment::Ellipse(_) => 4 + 4 + 1,
+        Attachment::Polygon(polygon) => 1 + 2 + 8 * polygon.points.len().min(u16::MAX as usize),
+        Attachment::Vector(_) => 4 + 4,
+    }
+}
+
+/// 实体命令编码后的长度
+#[inline]
+fn command_len(command: &EntityCommand) -> usize {
+    match command {
+        EntityCommand::Put { attachment, .. } => {
+            1 + 4 + Vertex::ENCODED_LEN + attachment.as_ref().map_or(0, encoded_len)
         }
+        EntityCommand::Move { .. } => 1 + 4 + 4 + 4 + 4,
+        EntityCommand::Remove(_) => 1 + 4,
+        EntityCommand::PutEdge { .. } => 1 + 4 + 4 + 1 + 1,
+        EntityCommand::RemoveEdge { .. } => 1 + 4 + 4,
     }
 }
 
+/// 在字符边界截断到 u16 能表示的长度
+#[inline]
+fn truncate(text: &str) -> &str {
+    truncate_to(text, u16::MAX as usize)
+}
+
+/// 在字符边界截断到不超过 `max` 字节
+fn truncate_to(text: &str, max: usize) -> &str {
+    let mut end = text.len().min(max);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// 名字截断到 u16 能表示的长度
+#[inline]
+fn name(name: impl ToString) -> String {
+    let mut name = name.to_string();
+    name.truncate(truncate(&name).len());
+    name
+}
+
+/// 同步组和图层名字截断到一项能放进表的长度
+#[inline]
+fn table_name(name: impl ToString) -> String {
+    // 表项尾部位置是 u16，同步组项另有 12 字节时限
+    let mut name = name.to_string();
+    name.truncate(truncate_to(&name, u16::MAX as usize - 12).len());
+    name
+}
+
+/// 从带序号的集合中删除一项，后面的序号前移，返回删除项的序号
+fn remove_indexed<T: Default>(map: &mut HashMap<String, WithIndex<T>>, name: &str) -> Option<u16> {
+    let index = map.remove(name)?.index;
+    for body in map.values_mut() {
+        if body.index > index {
+            body.index -= 1;
+        }
+    }
+    Some(index)
+}
+
+/// 按序号排列名字
+fn names_by_index<T: Default>(map: &HashMap<String, WithIndex<T>>) -> Vec<&str> {
+    let mut names = vec![""; map.len()];
+    for (name, body) in map {
+        names[body.index as usize - 1] = name;
+    }
+    names
+}
+
 /// 从已排序的集合编码
-fn sort_and_encode<T: Default + Copy>(map: &HashMap<String, WithIndex<T>>, buf: &mut Vec<u8>) {
+fn sort_and_encode<T: Default + Copy + EncodeLe>(
+    map: &HashMap<String, WithIndex<T>>,
+    buf: &mut Vec<u8>,
+) {
     // 用 u16 保存长度
     const USIZE_LEN: usize = std::mem::size_of::<u16>();
     // 依序号排序
@@ -249,16 +1257,219 @@ fn sort_and_encode<T: Default + Copy>(map: &HashMap<String, WithIndex<T>>, buf:
         .for_each(|(name, body)| {
             extend!(     *body => buf);
             extend!(str;  name => buf);
-            unsafe {
-                *(buf[ptr_len..].as_ptr() as *mut _) = (buf.len() - ptr_content) as u16;
-            }
+            let tail = u16::try_from(buf.len() - ptr_content).expect("table exceeds u16");
+            buf[ptr_len..][..USIZE_LEN].copy_from_slice(&tail.to_le_bytes());
             ptr_len += USIZE_LEN;
         });
 }
 
 #[test]
+fn long_names() {
+    use crate::Packet;
+
+    // 名字在字符边界截断，长度字段与内容一致
+    let long = "名".repeat(30000);
+    let mut encoder = Encoder::default();
+    encoder.layer(&long, &[&long], Some(true));
+    encoder.fit_topic(&long);
+    let packet = Packet::parse(&encoder.encode()).unwrap();
+    let topic = &packet.topics[0].name;
+    assert_eq!(topic.len(), 65535);
+    assert!(long.starts_with(topic.as_str()));
+    assert_eq!(packet.layers[0].name.len(), 65523);
+    assert!(matches!(&packet.views[0], ViewCommand::Fit(name) if name == topic));
+}
+
+#[test]
+fn fixed_layout() {
+    use crate::vertex;
+
+    let buf = Encoder::with(|encoder| {
+        encoder.layer("l", &["t"], Some(true));
+        let mut topic = encoder.topic("t");
+        topic.set_color(7, Srgba::new(1.0, 0.0, 0.0, 1.0));
+        topic.push(vertex!(3; 1.0, -2.0 => 0.5; 255));
+    });
+    assert_eq!(buf[..5], [b'M', b'T', b'R', b'S', VERSION]);
+    assert_eq!(buf[9..13], [0, 0, 1, 0]);
+    #[rustfmt::skip]
+    let expected: &[u8] = &[
+        0, 0,                                   // 同步组
+        1, 0, 2, 0, 0x55, b'l',                 // 图层
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,     // 源时间
+        0, 0,                                   // 删除表
+        0, 0,                                   // 视野命令表
+        1, 0, b't', 0, 0, 1, 0,                 // 话题名字、同步组、图层
+        0, 0, 0, 0, 0, 0, 0, 0, 0,              // 清空、容量、关注数量
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,     // 保留时长
+        1, 0, 7, 0, 0, 0xff, 0xff,              // 颜色
+        0, 0,                                   // 样式
+        1, 0,                                   // 顶点数
+        0, 0, 0x80, 0x3f, 0, 0, 0, 0xc0,        // x, y
+        3, 255, 0, 0,                           // 级别、连线、占位、形状
+        0, 0, 0, 0x3f,                          // 补充数据
+        0, 0,                                   // 栅格块数
+        0, 0,                                   // 实体命令数
+    ];
+    assert_eq!(&buf[13..], expected);
+}
+
+#[test]
+fn labels_in_chunks() {
+    use super::Label;
+    use crate::vertex;
+
+    let mut encoder = Encoder::default();
+    let mut topic = encoder.topic("t");
+    for i in 0..1000 {
+        topic.push_attached(vertex!(0; i, i => 0.0; 0), Label::screen(i, 12.0));
+        topic.push(vertex!(0; i, -i => 0.0; 0));
+    }
+    // 缺省附件
+    topic.push(vertex!(0; 0, 0; Text, 0.0; 0));
+    let chunks = encoder.encode_chunks(1400).unwrap();
+    assert!(chunks.len() > 1);
+    let mut vertex = Vec::new();
+    let mut attachments = Vec::new();
+    for chunk in &chunks {
+        assert!(chunk.len() <= 1400);
+        let mut packet = Packet::parse(chunk).unwrap();
+        vertex.append(&mut packet.topics[0].vertex);
+        attachments.append(&mut packet.topics[0].attachments);
+    }
+    assert_eq!(vertex.len(), 2001);
+    assert_eq!(attachments.len(), 1001);
+    assert_eq!(attachments[999], Label::screen(999, 12.0).into());
+    assert_eq!(attachments[1000], Label::default().into());
+}
+
+#[test]
+fn tiles_in_chunks() {
+    use super::{Cells, Grid};
+
+    let grid = Grid {
+        origin: [0.0, 0.0],
+        resolution: 0.1,
+        width: 100,
+        height: 100,
+    };
+    let cells = (0..10000).map(|i| i as u8).collect::<Vec<_>>();
+    let mut encoder = Encoder::default();
+    encoder
+        .topic("map")
+        .push_tile(Tile::whole(grid, vec![], cells.clone()));
+    // 空块只更新几何和调色板
+    encoder
+        .topic("map")
+        .push_tile(Tile::whole(grid, vec![], Vec::<f32>::new()));
+    let chunks = encoder.encode_chunks(1400).unwrap();
+    assert!(chunks.len() > 7);
+    let mut received = Vec::new();
+    let mut tiles = Vec::new();
+    for chunk in &chunks {
+        assert!(chunk.len() <= 1400);
+        for topic in Packet::parse(chunk).unwrap().topics {
+            tiles.extend(topic.tiles);
+        }
+    }
+    for tile in &tiles[..tiles.len() - 1] {
+        assert_eq!((tile.grid, tile.column, tile.width), (grid, 0, 100));
+        assert_eq!(tile.row as usize, received.len() / 100);
+        match &tile.cells {
+            Cells::U8(cells) => received.extend_from_slice(cells),
+            Cells::F32(_) => panic!("cell type changed"),
+        }
+    }
+    assert_eq!(received, cells);
+    assert_eq!(tiles.last().unwrap().cells, Cells::F32(vec![]));
+}
+
+#[test]
+fn clear_drops_tiles() {
+    use super::Grid;
+
+    let grid = Grid {
+        origin: [0.0, 0.0],
+        resolution: 1.0,
+        width: 2,
+        height: 2,
+    };
+    let mut encoder = Encoder::default();
+    let mut topic = encoder.topic("map");
+    topic.push_tile(Tile::whole(grid, vec![], vec![0u8; 4]));
+    topic.clear();
+    let buf = encoder.encode();
+    let topics = Packet::parse(&buf).unwrap().topics;
+    assert!(topics.iter().any(|topic| topic.clear));
+    assert!(topics.iter().all(|topic| topic.tiles.is_empty()));
+}
+
+#[test]
+fn entities_in_chunks() {
+    use super::{Label, Polygon};
+    use crate::vertex;
+
+    let mut encoder = Encoder::default();
+    let mut topic = encoder.topic("t");
+    for i in 0..300 {
+        topic.put_entity_attached(i, vertex!(0; i, 0 => 0.0; 0), Label::screen(i, 12.0));
+        topic.move_entity(i, 1.0, 2.0, f32::NAN);
+    }
+    topic.remove_entity(7);
+    // 缺省附件
+    topic.put_entity(1000, vertex!(0; 0, 0; Polygon, 0.0; 0));
+    let chunks = encoder.encode_chunks(1400).unwrap();
+    assert!(chunks.len() > 1);
+    let mut entities = Vec::new();
+    for chunk in &chunks {
+        assert!(chunk.len() <= 1400);
+        for topic in Packet::parse(chunk).unwrap().topics {
+            entities.extend(topic.entities);
+        }
+    }
+    assert_eq!(entities.len(), 602);
+    assert_eq!(
+        entities[2],
+        EntityCommand::Put {
+            id: 1,
+            vertex: vertex!(0; 1, 0; Text, 0.0; 0),
+            attachment: Some(Label::screen(1, 12.0).into()),
+        }
+    );
+    assert!(matches!(
+        entities[599],
+        EntityCommand::Move { id: 299, x, y, extra } if (x, y) == (1.0, 2.0) && extra.is_nan()
+    ));
+    assert_eq!(entities[600], EntityCommand::Remove(7));
+    assert_eq!(
+        entities[601],
+        EntityCommand::Put {
+            id: 1000,
+            vertex: vertex!(0; 0, 0; Polygon, 0.0; 0),
+            attachment: Some(Polygon::default().into()),
+        }
+    );
+}
+
+#[test]
+fn too_many_fragments() {
+    use crate::vertex;
+
+    // 包长过小时每个顶点单独成包
+    let mut encoder = Encoder::default();
+    encoder
+        .topic("t")
+        .extend((0..=u16::MAX as i32).map(|i| vertex!(0; i, 0; 0)));
+    assert_eq!(
+        encoder.encode_chunks(1).unwrap_err(),
+        EncodeError::Fragments(u16::MAX as usize + 1)
+    );
+}
+
+#[test]
+#[ignore = "演示用的发送端，不会自行结束，用 `cargo test send -- --ignored` 运行"]
 fn send() {
-    use crate::{vertex, Shape};
+    use crate::vertex;
     use palette::Srgba;
     use rand::{thread_rng, Rng};
     use std::net::UdpSocket;
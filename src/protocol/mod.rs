@@ -1,17 +1,45 @@
-﻿#[cfg(feature = "app")]
+﻿use crate::{Shape, Vertex};
+
+mod attachment;
 mod decode;
+mod packet;
+mod raster;
+mod style;
 
 #[cfg(feature = "app")]
 pub(crate) use decode::decode;
 
+pub use attachment::*;
+pub use decode::DecodeError;
+pub use packet::*;
+pub use raster::*;
+pub use style::*;
+
+#[cfg(feature = "json")]
+mod text;
+
+#[cfg(feature = "json")]
+pub(crate) use text::parse_line;
+
 #[cfg(feature = "client")]
 mod encode;
 
 #[cfg(feature = "client")]
 pub use encode::*;
 
+/// 包头魔数
+pub const MAGIC: [u8; 4] = *b"MTRS";
+
+/// 协议版本
+pub const VERSION: u8 = 2;
+
+/// 流式传输中单个包的最大长度
+///
+/// 流式传输（TCP 等）在每个包前加长度前缀：| 长度: u32 | 包 |
+pub const MAX_STREAM_PACKET: usize = 64 << 20;
+
 /// 图层是否显示
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum Visibility {
     NothingToDo = 0,
     Visible = 0x55,
@@ -35,3 +63,76 @@ impl From<Option<bool>> for Visibility {
         }
     }
 }
+
+impl Vertex {
+    /// 编码后的长度
+    pub const ENCODED_LEN: usize = 16;
+
+    /// 按小端序编码：| x: f32 | y: f32 | level: u8 | alpha: u8 | 0u8 | shape: u8 | extra: f32 |
+    pub fn to_le_bytes(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut bytes = [0u8; Self::ENCODED_LEN];
+        bytes[0..4].copy_from_slice(&self.x.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.y.to_le_bytes());
+        bytes[8] = self.level;
+        bytes[9] = self.alpha;
+        bytes[10] = self._zero;
+        bytes[11] = self.shape as u8;
+        bytes[12..16].copy_from_slice(&self.extra.to_le_bytes());
+        bytes
+    }
+
+    /// 按小端序解码，形状非法时返回形状字节
+    pub fn from_le_bytes(bytes: [u8; Self::ENCODED_LEN]) -> Result<Self, u8> {
+        let f32_at = |i: usize| f32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        Ok(Self {
+            x: f32_at(0),
+            y: f32_at(4),
+            level: bytes[8],
+            alpha: bytes[9],
+            _zero: bytes[10],
+            shape: Shape::try_from(bytes[11])?,
+            extra: f32_at(12),
+        })
+    }
+}
+
+impl TryFrom<u8> for Shape {
+    type Error = u8;
+
+    #[inline]
+    fn try_from(b: u8) -> Result<Self, Self::Error> {
+        match b {
+            0 => Ok(Self::Arrow),
+            1 => Ok(Self::Circle),
+            2 => Ok(Self::Text),
+            3 => Ok(Self::Rect),
+            4 => Ok(Self::OrientedBox),
+            5 => Ok(Self::Polygon),
+            6 => Ok(Self::Ellipse),
+            7 => Ok(Self::Vector),
+            b => Err(b),
+        }
+    }
+}
+
+impl Shape {
+    /// 顶点之后是否跟随附件
+    #[inline]
+    pub(crate) fn needs_attachment(self) -> bool {
+        !matches!(self, Self::Arrow | Self::Circle)
+    }
+}
+
+impl TryFrom<u8> for Visibility {
+    type Error = u8;
+
+    #[inline]
+    fn try_from(b: u8) -> Result<Self, Self::Error> {
+        match b {
+            0 => Ok(Self::NothingToDo),
+            0x55 => Ok(Self::Visible),
+            0xaa => Ok(Self::Invisible),
+            b => Err(b),
+        }
+    }
+}
@@ -0,0 +1,310 @@
+﻿use super::{decode, Attachment, DecodeError, Style, Tile};
+use crate::Vertex;
+use palette::Srgba;
+use std::time::{Duration, SystemTime};
+
+/// 解析完成的包
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct Packet {
+    pub fragment: Fragment,
+    pub sync_sets: Vec<SyncSet>,
+    pub layers: Vec<Layer>,
+    pub removals: Vec<Removal>,
+    pub views: Vec<ViewCommand>,
+    pub time: Option<SystemTime>, // 源时间，`None` 表示使用到达时间
+    pub topics: Vec<Topic>,
+}
+
+/// 分片信息
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Fragment {
+    pub frame: u32, // 帧序号
+    pub index: u16, // 分片序号
+    pub count: u16, // 分片数
+}
+
+/// 同步组配置
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SyncSet {
+    pub name: String,
+    pub life_time: Option<Duration>, // 时限，`None` 表示不修改
+}
+
+/// 图层配置
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Layer {
+    pub name: String,
+    pub visible: Option<bool>, // 可见性，`None` 表示不修改
+}
+
+/// 删除命令，在包内先于其他内容执行
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Removal {
+    Topic(String),   // 删除话题
+    Layer(String),   // 删除图层及其中的所有话题
+    SyncSet(String), // 解散同步组，其中的话题保留
+}
+
+/// 视野命令，在整帧的话题更新之后依次执行
+#[derive(Clone, PartialEq, Debug)]
+pub enum ViewCommand {
+    /// 设置视野中心和比例，非有限值表示不修改，比例为 0 表示开启自动视野
+    Look { x: f32, y: f32, scale: f32 },
+    /// 使视野适应一个话题的范围，并关闭自动视野
+    Fit(String),
+    /// 开启或关闭自动视野
+    Auto(bool),
+}
+
+/// 实体命令，按编号作用于话题中的单个实体或两个实体之间的边
+///
+/// 实体与顶点队列分开保存，不受容量限制，也不与其他顶点连线；边按起点和终点区分，
+/// 两端的实体都存在时才画出。
+#[derive(Clone, PartialEq, Debug)]
+pub enum EntityCommand {
+    /// 写入实体，已有的同号实体被替换
+    Put {
+        id: u32,
+        vertex: Vertex,
+        attachment: Option<Attachment>, // 只有需要附件的形状才有
+    },
+    /// 移动实体，补充数据为 NaN 时不修改，实体不存在时忽略
+    Move { id: u32, x: f32, y: f32, extra: f32 },
+    /// 删除实体，连接它的边一并删除
+    Remove(u32),
+    /// 写入从 `from` 到 `to` 的边，颜色和样式取级别的配置，`alpha` 为透明度
+    PutEdge {
+        from: u32,
+        to: u32,
+        level: u8,
+        alpha: u8,
+    },
+    /// 删除从 `from` 到 `to` 的边
+    RemoveEdge { from: u32, to: u32 },
+}
+
+/// 话题数据
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct Topic {
+    pub name: String,
+    pub sync_set: Option<String>,     // 加入的同步组
+    pub layer: Option<String>,        // 所在图层
+    pub clear: bool,                  // 是否先清空
+    pub capacity: u32,                // 容量，0 表示不修改
+    pub focus: u32,                   // 关注数量，0 表示不修改
+    pub retention: Option<Duration>,  // 保留时长，`None` 表示不修改，`Duration::MAX` 表示不限
+    pub colors: Vec<(u8, Srgba<u8>)>, // 级别颜色
+    pub styles: Vec<(u8, Style)>,     // 级别样式
+    pub vertex: Vec<Vertex>,          // 顶点
+    pub attachments: Vec<Attachment>, // 附件，依次对应需要附件的顶点
+    pub tiles: Vec<Tile>,             // 栅格块
+    pub entities: Vec<EntityCommand>, // 实体命令，依次执行
+}
+
+impl Default for Fragment {
+    /// 不分片的包
+    #[inline]
+    fn default() -> Self {
+        Self {
+            frame: 0,
+            index: 0,
+            count: 1,
+        }
+    }
+}
+
+impl Fragment {
+    /// 只解析包头中的分片信息
+    #[inline]
+    pub fn peek(buf: &[u8]) -> Result<Self, DecodeError> {
+        decode::parse_head(buf)
+    }
+}
+
+impl Packet {
+    /// 解析一个包
+    #[inline]
+    pub fn parse(buf: &[u8]) -> Result<Self, DecodeError> {
+        decode::parse(buf)
+    }
+}
+
+#[cfg(feature = "client")]
+#[test]
+fn round_trip() {
+    use crate::{
+        vertex, Anchor, Ellipse, Encoder, Grid, Label, Marker, Polygon, Rect, Tile, Vector,
+    };
+
+    let label = Label::world("路点", 2.0).with_anchor(Anchor::Top);
+    let style = Style {
+        marker: Marker::Dot,
+        dash: [3.0, 1.0],
+        vector_scale: 0.5,
+        magnitude: [1.0, 3.0],
+        ..Style::DEFAULT
+    };
+    let rect = Rect {
+        width: 2.0,
+        height: 1.0,
+        fill: 64,
+    };
+    let polygon = Polygon {
+        points: vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]],
+        fill: 0,
+    };
+    let tile = Tile {
+        grid: Grid {
+            origin: [-1.0, -1.0],
+            resolution: 0.5,
+            width: 4,
+            height: 4,
+        },
+        palette: vec![
+            (0.0, Srgba::new(255, 255, 255, 255)),
+            (100.0, Srgba::new(0, 0, 0, 255)),
+        ],
+        column: 1,
+        row: 2,
+        width: 2,
+        cells: vec![0.0, 1.5, f32::INFINITY, -2.0].into(),
+    };
+    let time = SystemTime::UNIX_EPOCH + Duration::new(1_760_000_000, 123);
+    let buf = Encoder::with(|encoder| {
+        encoder.set_time(time);
+        encoder.sync_set("set", &["a"], Some(Duration::from_millis(1500)));
+        encoder.layer("layer", &["a"], Some(false));
+        encoder.config_topic("a", 100, 10, &[(1, Srgba::new(1.0, 0.0, 0.0, 1.0))], |t| {
+            t.clear();
+            t.set_retention(Some(Duration::from_secs(30)));
+            t.set_style(1, style);
+            t.push(vertex!(1; 2, 3 => 0.5; 255));
+            t.push_attached(vertex!(1; 4, 5 => 0.0; 0), label.clone());
+            t.push_attached(vertex!(1; 6, 7; OrientedBox, 0.5; 0), rect);
+            t.push_attached(vertex!(1; 8, 9 => 0.0; 0), polygon.clone());
+            t.push_covariance(vertex!(1; 10, 11 => 0.0; 0), [4.0, 0.0, 1.0], 2.0);
+            t.push_attached(vertex!(1; 12, 13 => 0.0; 0), Vector { dx: 1.0, dy: -2.0 });
+            t.push_tile(tile.clone());
+            t.put_entity_attached(3, vertex!(1; 0, 0; OrientedBox, 0.5; 0), rect);
+            t.move_entity(3, 1.0, 2.0, f32::INFINITY);
+            t.remove_entity(4);
+            t.put_edge(3, 5, 1, 128);
+            t.remove_edge(5, 3);
+        });
+        encoder.set_view(1.0, 2.0, 0.5);
+        encoder.fit_topic("a");
+        encoder.set_auto_view(true);
+    });
+    let packet = Packet::parse(&buf).unwrap();
+    assert_eq!(packet.time, Some(time));
+    assert_eq!(
+        packet.views,
+        [
+            ViewCommand::Look {
+                x: 1.0,
+                y: 2.0,
+                scale: 0.5,
+            },
+            ViewCommand::Fit("a".into()),
+            ViewCommand::Auto(true),
+        ]
+    );
+    assert_eq!(
+        packet.sync_sets,
+        [SyncSet {
+            name: "set".into(),
+            life_time: Some(Duration::from_millis(1500)),
+        }]
+    );
+    assert_eq!(
+        packet.layers,
+        [Layer {
+            name: "layer".into(),
+            visible: Some(false),
+        }]
+    );
+    assert_eq!(
+        packet.topics,
+        [
+            Topic {
+                name: "a".into(),
+                sync_set: Some("set".into()),
+                layer: Some("layer".into()),
+                clear: true,
+                capacity: 100,
+                focus: 10,
+                retention: Some(Duration::from_secs(30)),
+                colors: vec![(1, Srgba::new(255, 0, 0, 255))],
+                styles: vec![(1, style)],
+                vertex: vec![
+                    vertex!(1; 2, 3 => 0.5; 255),
+                    vertex!(1; 4, 5; Text, 0.0; 0),
+                    vertex!(1; 6, 7; OrientedBox, 0.5; 0),
+                    vertex!(1; 8, 9; Polygon, 0.0; 0),
+                    vertex!(1; 10, 11; Ellipse, 0.0; 0),
+                    vertex!(1; 12, 13; Vector, 0.0; 0),
+                ],
+                attachments: vec![
+                    label.into(),
+                    rect.into(),
+                    polygon.into(),
+                    Ellipse {
+                        a: 4.0,
+                        b: 2.0,
+                        fill: 0,
+                    }
+                    .into(),
+                    Vector { dx: 1.0, dy: -2.0 }.into(),
+                ],
+                tiles: vec![],
+                entities: vec![],
+            },
+            // 栅格块单独成段，不带配置
+            Topic {
+                name: "a".into(),
+                sync_set: Some("set".into()),
+                layer: Some("layer".into()),
+                tiles: vec![tile],
+                ..Default::default()
+            },
+            // 实体命令也单独成段
+            Topic {
+                name: "a".into(),
+                sync_set: Some("set".into()),
+                layer: Some("layer".into()),
+                entities: vec![
+                    EntityCommand::Put {
+                        id: 3,
+                        vertex: vertex!(1; 0, 0; OrientedBox, 0.5; 0),
+                        attachment: Some(rect.into()),
+                    },
+                    EntityCommand::Move {
+                        id: 3,
+                        x: 1.0,
+                        y: 2.0,
+                        extra: f32::INFINITY,
+                    },
+                    EntityCommand::Remove(4),
+                    EntityCommand::PutEdge {
+                        from: 3,
+                        to: 5,
+                        level: 1,
+                        alpha: 128,
+                    },
+                    EntityCommand::RemoveEdge { from: 5, to: 3 },
+                ],
+                ..Default::default()
+            },
+        ]
+    );
+    // 经过编码器再编码，除帧序号外不变
+    let again = Packet::parse(&Encoder::from(packet.clone()).encode()).unwrap();
+    assert_eq!(Fragment::peek(&buf).unwrap(), packet.fragment);
+    assert_eq!(
+        Packet {
+            fragment: packet.fragment,
+            ..again
+        },
+        packet
+    );
+}
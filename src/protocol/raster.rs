@@ -0,0 +1,109 @@
+﻿use palette::Srgba;
+
+/// 栅格单元数的上限，接收端拒绝更大的栅格
+pub const MAX_CELLS: usize = 1 << 24;
+
+/// 栅格的几何，第 0 行第 0 列单元的左下角为原点，行号沿 y 轴正方向增长
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Grid {
+    pub origin: [f32; 2], // 原点的世界坐标
+    pub resolution: f32,  // 单元边长
+    pub width: u32,       // 列数
+    pub height: u32,      // 行数
+}
+
+impl Grid {
+    /// 原点是有限值，单元边长是正的有限值
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        let [x, y] = self.origin;
+        x.is_finite() && y.is_finite() && self.resolution.is_finite() && self.resolution > 0.0
+    }
+}
+
+/// 一组单元值，按行优先排列
+#[derive(Clone, PartialEq, Debug)]
+pub enum Cells {
+    U8(Vec<u8>),
+    F32(Vec<f32>), // NaN 表示未知
+}
+
+/// 栅格话题的一块
+///
+/// 每块都带有整个栅格的几何和调色板，几何或值的类型改变时接收端重建栅格。
+#[derive(Clone, PartialEq, Debug)]
+pub struct Tile {
+    pub grid: Grid,
+    pub palette: Vec<(f32, Srgba<u8>)>, // 色标，按值递增，值在相邻色标之间时线性插值
+    pub column: u32,                    // 块的起始列
+    pub row: u32,                       // 块的起始行
+    pub width: u32,                     // 块的列数，行数由值的数量决定
+    pub cells: Cells,
+}
+
+impl Cells {
+    /// 值的数量
+    #[inline]
+    pub fn len(&self) -> usize {
+        match self {
+            Self::U8(cells) => cells.len(),
+            Self::F32(cells) => cells.len(),
+        }
+    }
+
+    /// 是否没有值
+    #[cfg(feature = "client")]
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 每个值编码后的字节数
+    #[cfg(any(feature = "app", feature = "client"))]
+    #[inline]
+    pub(crate) fn cell_len(&self) -> usize {
+        match self {
+            Self::U8(_) => 1,
+            Self::F32(_) => 4,
+        }
+    }
+}
+
+impl From<Vec<u8>> for Cells {
+    #[inline]
+    fn from(cells: Vec<u8>) -> Self {
+        Self::U8(cells)
+    }
+}
+
+impl From<Vec<f32>> for Cells {
+    #[inline]
+    fn from(cells: Vec<f32>) -> Self {
+        Self::F32(cells)
+    }
+}
+
+impl Tile {
+    /// 覆盖整个栅格的块
+    #[cfg(feature = "client")]
+    #[inline]
+    pub fn whole(grid: Grid, palette: Vec<(f32, Srgba<u8>)>, cells: impl Into<Cells>) -> Self {
+        Self {
+            grid,
+            palette,
+            column: 0,
+            row: 0,
+            width: grid.width,
+            cells: cells.into(),
+        }
+    }
+
+    /// 块的行数，不足一行的值被忽略
+    #[inline]
+    pub fn height(&self) -> u32 {
+        match self.width {
+            0 => 0,
+            width => (self.cells.len() / width as usize) as u32,
+        }
+    }
+}
@@ -0,0 +1,86 @@
+﻿/// 级别样式，与颜色一样按话题和级别设置
+///
+/// 长度除箭头长度可选世界单位外均以像素计，向量按世界单位画出。
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Style {
+    pub marker: Marker,      // 点的形状
+    pub marker_size: f32,    // 点的边长或直径
+    pub line_width: f32,     // 连线和轮廓的线宽
+    pub dash: [f32; 2],      // 虚线的实段和空段长度，任一不为正表示实线
+    pub arrow_length: f32,   // 箭头长度
+    pub arrow_world: bool,   // 箭头长度以世界单位计，否则以像素计
+    pub vector_scale: f32,   // 向量画出的长度与模长之比
+    pub magnitude: [f32; 2], // 按模长着色的范围，上限不大于下限时使用级别颜色
+}
+
+/// 点的形状
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[repr(u8)]
+pub enum Marker {
+    #[default]
+    Square = 0,
+    Dot = 1,
+    Cross = 2,
+}
+
+impl Style {
+    /// 未设置样式的级别使用的样式
+    pub const DEFAULT: Self = Self {
+        marker: Marker::Square,
+        marker_size: 3.5,
+        line_width: 1.5,
+        dash: [0.0, 0.0],
+        arrow_length: 15.0,
+        arrow_world: false,
+        vector_scale: 1.0,
+        magnitude: [0.0, 0.0],
+    };
+
+    /// 是否为虚线
+    #[inline]
+    pub fn is_dashed(&self) -> bool {
+        self.dash[0] > 0.0 && self.dash[1] > 0.0
+    }
+
+    /// 模长在着色范围中的位置，截断到 [0, 1]，不按模长着色时为 `None`
+    #[cfg(feature = "app")]
+    #[inline]
+    pub(crate) fn magnitude_ratio(&self, magnitude: f32) -> Option<f32> {
+        let [min, max] = self.magnitude;
+        (max > min).then(|| ((magnitude - min) / (max - min)).clamp(0.0, 1.0))
+    }
+}
+
+impl Default for Style {
+    #[inline]
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl TryFrom<u8> for Marker {
+    type Error = u8;
+
+    #[inline]
+    fn try_from(b: u8) -> Result<Self, Self::Error> {
+        match b {
+            0 => Ok(Self::Square),
+            1 => Ok(Self::Dot),
+            2 => Ok(Self::Cross),
+            b => Err(b),
+        }
+    }
+}
+
+#[cfg(feature = "app")]
+#[test]
+fn magnitude_ratio() {
+    assert_eq!(Style::DEFAULT.magnitude_ratio(1.0), None);
+    let style = Style {
+        magnitude: [1.0, 3.0],
+        ..Style::DEFAULT
+    };
+    assert_eq!(style.magnitude_ratio(2.0), Some(0.5));
+    assert_eq!(style.magnitude_ratio(0.0), Some(0.0));
+    assert_eq!(style.magnitude_ratio(5.0), Some(1.0));
+}
@@ -0,0 +1,527 @@
+﻿use super::{
+    Anchor, Attachment, Ellipse, EntityCommand, Label, Layer, Marker, Packet, Polygon, Rect, Style,
+    SyncSet, Topic, Vector,
+};
+use crate::{Shape, Vertex};
+use palette::Srgba;
+use serde_json::{Map, Value};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// 文本行解析错误
+#[derive(Debug)]
+pub(crate) enum TextError {
+    /// 不是合法的 JSON 对象
+    Json(serde_json::Error),
+    /// 不是 JSON 对象
+    NotObject,
+    /// 未知的字段
+    Unknown(String),
+    /// 字段类型或取值错误：字段名、期望的格式
+    Field(String, &'static str),
+}
+
+impl std::fmt::Display for TextError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Json(e) => write!(f, "invalid json: {}", e),
+            Self::NotObject => write!(f, "line is not a json object"),
+            Self::Unknown(key) => write!(f, "unknown field `{}`", key),
+            Self::Field(key, expected) => write!(f, "`{}` should be {}", key, expected),
+        }
+    }
+}
+
+/// 解析一行 JSON 为包
+///
+/// ```text
+/// {"topic": "a", "points": [[x, y], [x, y, level, alpha, shape, extra], {"x": 0, "y": 0}],
+///  "colors": {"0": "#ff0000", "1": [0, 255, 0, 128]}, "capacity": 1000, "focus": 10,
+///  "styles": {"0": {"marker": "dot", "marker_size": 4, "line_width": 2, "dash": [4, 2],
+///                   "arrow_length": 0.5, "arrow_world": true, "vector_scale": 0.1, "magnitude": [0, 5]}},
+///  "entities": {"7": [x, y, level, alpha, shape, extra, attachment], "8": null},
+///  "clear": true, "layer": "l", "visible": true, "sync_set": "s", "life_time": 1.5,
+///  "time": 1760000000.25, "retention": 30}
+/// ```
+///
+/// 所有字段都可省略；省略 `topic` 时只修改图层和同步组。
+/// `time` 是数据产生的源时间，单位为自 UNIX 纪元的秒数；`retention` 是话题的保留时长，以秒计，0 表示不限。
+/// `entities` 按编号写入实体，`null` 删除实体；实体的格式与顶点相同，附件规则也相同。
+/// 顶点缺省级别 0、连线透明度 0、形状 arrow、补充数据 NaN；
+/// 形状可写 "arrow"、"circle"、"text"、"rect"、"box"、"polygon"、"ellipse"、"vector" 或编号。
+/// 需要附件的形状必须带 `attachment`，其他形状不能带：
+///
+/// ```text
+/// text:    "内容" 或 {"text": "内容", "anchor": "top_left", "size": 16, "world": false}
+/// rect、box: {"width": 2, "height": 1, "fill": 128}
+/// polygon: {"points": [[x, y], ...], "fill": 128}
+/// ellipse: {"a": 2, "b": 1, "fill": 128}
+/// vector:  {"dx": 1, "dy": 0}
+/// ```
+pub(crate) fn parse_line(line: &str) -> Result<Packet, TextError> {
+    let object = match serde_json::from_str(line).map_err(TextError::Json)? {
+        Value::Object(object) => object,
+        _ => return Err(TextError::NotObject),
+    };
+    let mut topic = Topic::default();
+    let mut name = None;
+    let mut visible = None;
+    let mut life_time = None;
+    let mut time = None;
+    for (key, value) in &object {
+        let field = |expected| TextError::Field(key.clone(), expected);
+        match key.as_str() {
+            "topic" => name = Some(value.as_str().ok_or_else(|| field("a string"))?),
+            "layer" => topic.layer = Some(string(value).ok_or_else(|| field("a string"))?),
+            "sync_set" => topic.sync_set = Some(string(value).ok_or_else(|| field("a string"))?),
+            "visible" => visible = Some(value.as_bool().ok_or_else(|| field("a bool"))?),
+            "clear" => topic.clear = value.as_bool().ok_or_else(|| field("a bool"))?,
+            "capacity" => topic.capacity = u32_of(value).ok_or_else(|| field("a u32"))?,
+            "focus" => topic.focus = u32_of(value).ok_or_else(|| field("a u32"))?,
+            "retention" => {
+                topic.retention = value
+                    .as_f64()
+                    .and_then(|secs| match Duration::try_from_secs_f64(secs) {
+                        Ok(t) if t.is_zero() => Some(Duration::MAX),
+                        t => t.ok(),
+                    })
+                    .ok_or_else(|| field("seconds"))
+                    .map(Some)?
+            }
+            "life_time" => {
+                life_time = value
+                    .as_f64()
+                    .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                    .ok_or_else(|| field("seconds"))
+                    .map(Some)?
+            }
+            "time" => {
+                time = value
+                    .as_f64()
+                    .filter(|secs| *secs > 0.0)
+                    .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                    .ok_or_else(|| field("seconds since the unix epoch"))
+                    .map(|t| Some(UNIX_EPOCH + t))?
+            }
+            "colors" => {
+                topic.colors = colors(value).ok_or_else(|| field("{level: color}"))?;
+            }
+            "styles" => {
+                topic.styles = styles(value).ok_or_else(|| field("{level: style}"))?;
+            }
+            "points" => {
+                (topic.vertex, topic.attachments) =
+                    points(value).ok_or_else(|| field("a list of points"))?;
+            }
+            "entities" => {
+                topic.entities = entities(value).ok_or_else(|| field("{id: point | null}"))?;
+            }
+            _ => return Err(TextError::Unknown(key.clone())),
+        }
+    }
+    let mut packet = Packet {
+        time,
+        ..Default::default()
+    };
+    if let Some(layer) = &topic.layer {
+        packet.layers.push(Layer {
+            name: layer.clone(),
+            visible,
+        });
+    } else if visible.is_some() {
+        return Err(TextError::Field("visible".into(), "used with `layer`"));
+    }
+    if let Some(sync_set) = &topic.sync_set {
+        packet.sync_sets.push(SyncSet {
+            name: sync_set.clone(),
+            life_time: life_time.filter(|t| !t.is_zero()),
+        });
+    } else if life_time.is_some() {
+        return Err(TextError::Field("life_time".into(), "used with `sync_set`"));
+    }
+    match name {
+        Some(name) => {
+            topic.name = name.into();
+            packet.topics.push(topic);
+        }
+        None if only_memberships(&object) => {}
+        None => return Err(TextError::Field("topic".into(), "present")),
+    }
+    Ok(packet)
+}
+
+/// 没有话题时只允许修改图层和同步组
+fn only_memberships(object: &Map<String, Value>) -> bool {
+    object
+        .keys()
+        .all(|key| matches!(key.as_str(), "layer" | "visible" | "sync_set" | "life_time"))
+}
+
+#[inline]
+fn string(value: &Value) -> Option<String> {
+    value.as_str().map(str::to_string)
+}
+
+#[inline]
+fn u32_of(value: &Value) -> Option<u32> {
+    value.as_u64().and_then(|n| n.try_into().ok())
+}
+
+#[inline]
+fn u8_of(value: &Value) -> Option<u8> {
+    value.as_u64().and_then(|n| n.try_into().ok())
+}
+
+/// 颜色表：{"级别": "#rrggbb" | "#rrggbbaa" | [r, g, b] | [r, g, b, a]}
+fn colors(value: &Value) -> Option<Vec<(u8, Srgba<u8>)>> {
+    let mut colors = Vec::new();
+    for (level, color) in value.as_object()? {
+        colors.push((level.parse().ok()?, color_of(color)?));
+    }
+    Some(colors)
+}
+
+fn color_of(value: &Value) -> Option<Srgba<u8>> {
+    let rgba = match value {
+        Value::String(s) => {
+            let hex = s.strip_prefix('#')?;
+            if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+                return None;
+            }
+            let mut rgba = [255u8; 4];
+            for (i, c) in rgba.iter_mut().take(hex.len() / 2).enumerate() {
+                *c = u8::from_str_radix(&hex[i * 2..][..2], 16).ok()?;
+            }
+            rgba
+        }
+        Value::Array(list) if matches!(list.len(), 3 | 4) => {
+            let mut rgba = [255u8; 4];
+            for (c, v) in rgba.iter_mut().zip(list) {
+                *c = u8_of(v)?;
+            }
+            rgba
+        }
+        _ => return None,
+    };
+    Some(Srgba::new(rgba[0], rgba[1], rgba[2], rgba[3]))
+}
+
+/// 样式表：{"级别": {样式字段}}，省略的字段取默认值
+fn styles(value: &Value) -> Option<Vec<(u8, Style)>> {
+    let mut styles = Vec::new();
+    for (level, style) in value.as_object()? {
+        styles.push((level.parse().ok()?, style_of(style)?));
+    }
+    Some(styles)
+}
+
+fn style_of(value: &Value) -> Option<Style> {
+    let f32_of = |v: &Value| v.as_f64().map(|x| x as f32);
+    let mut style = Style::DEFAULT;
+    for (key, v) in value.as_object()? {
+        match key.as_str() {
+            "marker" => {
+                style.marker = match v.as_str()? {
+                    "square" => Marker::Square,
+                    "dot" => Marker::Dot,
+                    "cross" => Marker::Cross,
+                    _ => return None,
+                }
+            }
+            "marker_size" => style.marker_size = f32_of(v)?,
+            "line_width" => style.line_width = f32_of(v)?,
+            "dash" => match v.as_array()?.as_slice() {
+                [on, off] => style.dash = [f32_of(on)?, f32_of(off)?],
+                _ => return None,
+            },
+            "arrow_length" => style.arrow_length = f32_of(v)?,
+            "arrow_world" => style.arrow_world = v.as_bool()?,
+            "vector_scale" => style.vector_scale = f32_of(v)?,
+            "magnitude" => match v.as_array()?.as_slice() {
+                [min, max] => style.magnitude = [f32_of(min)?, f32_of(max)?],
+                _ => return None,
+            },
+            _ => return None,
+        }
+    }
+    Some(style)
+}
+
+/// 顶点列表，需要附件的顶点依次带出附件
+fn points(value: &Value) -> Option<(Vec<Vertex>, Vec<Attachment>)> {
+    let mut vertex = Vec::new();
+    let mut attachments = Vec::new();
+    for v in value.as_array()? {
+        let (v, attachment) = point(v)?;
+        vertex.push(v);
+        attachments.extend(attachment);
+    }
+    Some((vertex, attachments))
+}
+
+/// 实体表：{"编号": 顶点 | null}
+fn entities(value: &Value) -> Option<Vec<EntityCommand>> {
+    let mut entities = Vec::new();
+    for (id, entity) in value.as_object()? {
+        let id = id.parse().ok()?;
+        entities.push(match entity {
+            Value::Null => EntityCommand::Remove(id),
+            v => {
+                let (vertex, attachment) = point(v)?;
+                EntityCommand::Put {
+                    id,
+                    vertex,
+                    attachment,
+                }
+            }
+        });
+    }
+    Some(entities)
+}
+
+/// 顶点：[x, y, level, alpha, shape, extra, attachment] 或同名字段的对象，x、y 之后均可省略
+///
+/// 需要附件的形状必须带附件，其他形状不能带。
+fn point(value: &Value) -> Option<(Vertex, Option<Attachment>)> {
+    const KEYS: [&str; 7] = ["x", "y", "level", "alpha", "shape", "extra", "attachment"];
+    let mut fields: [Option<&Value>; 7] = [None; 7];
+    match value {
+        Value::Array(list) if (2..=7).contains(&list.len()) => {
+            for (field, v) in fields.iter_mut().zip(list) {
+                *field = Some(v);
+            }
+        }
+        Value::Object(object) => {
+            for (key, v) in object {
+                fields[KEYS.iter().position(|k| k == key)?] = Some(v);
+            }
+        }
+        _ => return None,
+    }
+    let [x, y, level, alpha, shape, extra, attachment] = fields;
+    let vertex = Vertex {
+        x: x?.as_f64()? as f32,
+        y: y?.as_f64()? as f32,
+        level: level.map_or(Some(0), u8_of)?,
+        alpha: alpha.map_or(Some(0), u8_of)?,
+        _zero: 0,
+        shape: shape.map_or(Some(Shape::Arrow), shape_of)?,
+        extra: extra.map_or(Some(f32::NAN), |v| v.as_f64().map(|e| e as f32))?,
+    };
+    let attachment = match attachment {
+        Some(v) => Some(attachment_of(vertex.shape, v)?),
+        None if vertex.shape.needs_attachment() => return None,
+        None => None,
+    };
+    Some((vertex, attachment))
+}
+
+fn shape_of(value: &Value) -> Option<Shape> {
+    match value {
+        Value::String(s) => match s.as_str() {
+            "arrow" => Some(Shape::Arrow),
+            "circle" => Some(Shape::Circle),
+            "text" => Some(Shape::Text),
+            "rect" => Some(Shape::Rect),
+            "box" => Some(Shape::OrientedBox),
+            "polygon" => Some(Shape::Polygon),
+            "ellipse" => Some(Shape::Ellipse),
+            "vector" => Some(Shape::Vector),
+            _ => None,
+        },
+        v => Shape::try_from(u8_of(v)?).ok(),
+    }
+}
+
+/// 形状对应的附件，省略的字段取默认值，不需要附件的形状返回 `None`
+fn attachment_of(shape: Shape, value: &Value) -> Option<Attachment> {
+    // 文字可以直接写成字符串
+    if let (Shape::Text, Value::String(text)) = (shape, value) {
+        return Some(Attachment::Text(Label {
+            text: text.clone(),
+            ..Default::default()
+        }));
+    }
+    let object = value.as_object()?;
+    let keys: &[&str] = match shape {
+        Shape::Arrow | Shape::Circle => return None,
+        Shape::Text => &["text", "anchor", "size", "world"],
+        Shape::Rect | Shape::OrientedBox => &["width", "height", "fill"],
+        Shape::Polygon => &["points", "fill"],
+        Shape::Ellipse => &["a", "b", "fill"],
+        Shape::Vector => &["dx", "dy"],
+    };
+    if !object.keys().all(|key| keys.contains(&key.as_str())) {
+        return None;
+    }
+    let f32_of = |key, default| {
+        object
+            .get(key)
+            .map_or(Some(default), |v: &Value| v.as_f64().map(|x| x as f32))
+    };
+    let fill = || object.get("fill").map_or(Some(0), u8_of);
+    Some(match shape {
+        Shape::Arrow | Shape::Circle => return None,
+        Shape::Text => {
+            let label = Label::default();
+            Attachment::Text(Label {
+                text: object.get("text").map_or(Some(""), Value::as_str)?.into(),
+                anchor: object.get("anchor").map_or(Some(label.anchor), anchor_of)?,
+                size: f32_of("size", label.size)?,
+                world: object.get("world").map_or(Some(false), Value::as_bool)?,
+            })
+        }
+        Shape::Rect | Shape::OrientedBox => Attachment::Rect(Rect {
+            width: f32_of("width", 0.0)?,
+            height: f32_of("height", 0.0)?,
+            fill: fill()?,
+        }),
+        Shape::Polygon => Attachment::Polygon(Polygon {
+            points: object.get("points").map_or(Some(vec![]), pairs)?,
+            fill: fill()?,
+        }),
+        Shape::Ellipse => Attachment::Ellipse(Ellipse {
+            a: f32_of("a", 0.0)?,
+            b: f32_of("b", 0.0)?,
+            fill: fill()?,
+        }),
+        Shape::Vector => Attachment::Vector(Vector {
+            dx: f32_of("dx", 0.0)?,
+            dy: f32_of("dy", 0.0)?,
+        }),
+    })
+}
+
+fn anchor_of(value: &Value) -> Option<Anchor> {
+    Some(match value.as_str()? {
+        "top_left" => Anchor::TopLeft,
+        "top" => Anchor::Top,
+        "top_right" => Anchor::TopRight,
+        "left" => Anchor::Left,
+        "center" => Anchor::Center,
+        "right" => Anchor::Right,
+        "bottom_left" => Anchor::BottomLeft,
+        "bottom" => Anchor::Bottom,
+        "bottom_right" => Anchor::BottomRight,
+        _ => return None,
+    })
+}
+
+/// 点列表：[[x, y], ...]
+fn pairs(value: &Value) -> Option<Vec<[f32; 2]>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|pair| match pair.as_array()?.as_slice() {
+            [x, y] => Some([x.as_f64()? as f32, y.as_f64()? as f32]),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn parse_lines() {
+    use crate::vertex;
+
+    let packet = parse_line(
+        r##"{"topic": "a", "layer": "l", "visible": false, "capacity": 10,
+             "colors": {"3": "#ff000080"},
+             "points": [[1, 2], [3, 4, 3, 255, "circle", 0.5], {"x": 5, "y": 6, "extra": 0}]}"##,
+    )
+    .unwrap();
+    assert_eq!(
+        packet.layers,
+        [Layer {
+            name: "l".into(),
+            visible: Some(false),
+        }]
+    );
+    let topic = &packet.topics[0];
+    assert_eq!(topic.name, "a");
+    assert_eq!(topic.layer.as_deref(), Some("l"));
+    assert_eq!(topic.capacity, 10);
+    assert_eq!(topic.colors, [(3, Srgba::new(255, 0, 0, 0x80))]);
+    assert_eq!(topic.vertex.len(), 3);
+    assert!(topic.vertex[0].extra.is_nan());
+    assert_eq!(topic.vertex[1], vertex!(3; 3, 4; Circle, 0.5; 255));
+    assert_eq!(topic.vertex[2], vertex!(0; 5, 6 => 0.0; 0));
+    // 样式
+    let packet =
+        parse_line(r#"{"topic": "a", "styles": {"2": {"marker": "cross", "dash": [4, 2], "magnitude": [0, 5]}}}"#)
+            .unwrap();
+    let style = Style {
+        marker: Marker::Cross,
+        dash: [4.0, 2.0],
+        magnitude: [0.0, 5.0],
+        ..Style::DEFAULT
+    };
+    assert_eq!(packet.topics[0].styles, [(2, style)]);
+    // 实体
+    let packet = parse_line(
+        r#"{"topic": "a", "entities": {"7": [1, 2, 0, 0, "box", 0.5, {"width": 2}], "8": null}}"#,
+    )
+    .unwrap();
+    let rect = Rect {
+        width: 2.0,
+        ..Default::default()
+    };
+    assert_eq!(
+        packet.topics[0].entities,
+        [
+            EntityCommand::Put {
+                id: 7,
+                vertex: vertex!(0; 1, 2; OrientedBox, 0.5; 0),
+                attachment: Some(Attachment::Rect(rect)),
+            },
+            EntityCommand::Remove(8),
+        ]
+    );
+    // 附件依次对应需要附件的顶点
+    let packet = parse_line(
+        r#"{"topic": "a", "points": [[0, 0, 0, 0, "text", 0, "hi"], [1, 1],
+                                     {"x": 2, "y": 2, "shape": "polygon", "attachment": {"points": [[0, 0], [1, 0], [0, 1]], "fill": 9}}]}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        packet.topics[0].attachments,
+        [
+            Attachment::Text(Label::screen("hi", Label::default().size)),
+            Attachment::Polygon(Polygon {
+                points: vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]],
+                fill: 9,
+            }),
+        ]
+    );
+    // 保留时长
+    let packet = parse_line(r#"{"topic": "a", "retention": 0.5}"#).unwrap();
+    assert_eq!(packet.topics[0].retention, Some(Duration::from_millis(500)));
+    let packet = parse_line(r#"{"topic": "a", "retention": 0}"#).unwrap();
+    assert_eq!(packet.topics[0].retention, Some(Duration::MAX));
+    // 源时间
+    let packet = parse_line(r#"{"topic": "a", "time": 1.5}"#).unwrap();
+    assert_eq!(packet.time, Some(UNIX_EPOCH + Duration::from_millis(1500)));
+    // 只修改同步组
+    let packet = parse_line(r#"{"sync_set": "s", "life_time": 2}"#).unwrap();
+    assert_eq!(packet.sync_sets[0].life_time, Some(Duration::from_secs(2)));
+    assert!(packet.topics.is_empty());
+    // 逐行报告错误
+    for line in [
+        "[1, 2]",
+        "{",
+        r#"{"topic": "a", "points": [[1]]}"#,
+        r#"{"topic": "a", "colour": {}}"#,
+        r#"{"topic": "a", "capacity": -1}"#,
+        r#"{"topic": "a", "entities": {"x": [0, 0]}}"#,
+        r#"{"topic": "a", "entities": {"7": [0, 0, 0, 0, "rect"]}}"#,
+        r#"{"topic": "a", "entities": {"7": {"x": 0, "y": 0, "attachment": "hi"}}}"#,
+        r#"{"topic": "a", "points": [[0, 0, 0, 0, "ellipse", 0]]}"#,
+        r#"{"topic": "a", "points": [[0, 0, 0, 0, "circle", 0, {"a": 1}]]}"#,
+        r#"{"topic": "a", "points": [[0, 0, 0, 0, "vector", 0, {"dz": 1}]]}"#,
+        r#"{"topic": "a", "styles": {"0": {"marker": "star"}}}"#,
+        r#"{"visible": true}"#,
+        r#"{"capacity": 1}"#,
+        r#"{"topic": "a", "time": -1}"#,
+        r#"{"time": 1}"#,
+    ] {
+        assert!(parse_line(line).is_err(), "{}", line);
+    }
+}